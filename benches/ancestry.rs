@@ -0,0 +1,72 @@
+//! Allocations and time per vote of the lockout check and vote gap measurement, which walk the
+//! slot ancestry rather than collecting it. Run with `cargo bench --bench ancestry`
+use {
+    solana_sdk::{pubkey::Pubkey, signature::Signature},
+    std::{
+        alloc::{GlobalAlloc, Layout, System},
+        sync::atomic::{AtomicUsize, Ordering},
+        time::{Duration, Instant},
+    },
+    votalizer::{slot_tree::SlotTree, tower::Tower},
+};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// A linear fork, voted on from `SLOTS - VOTES + 1`
+const SLOTS: u64 = 6000;
+const VOTES: u64 = 5000;
+
+fn main() {
+    let mut slot_tree = SlotTree::new(10240);
+    for slot in 1..=SLOTS {
+        slot_tree.insert(slot, slot - 1);
+    }
+    let vote_account_address = Pubkey::new_unique();
+    let signature = Signature::default();
+    let mut tower = Tower::default();
+
+    let mut allocations = 0;
+    let mut elapsed = Duration::ZERO;
+    for vote_slot in SLOTS - VOTES + 1..=SLOTS {
+        let (start_allocations, start) = (ALLOCATIONS.load(Ordering::Relaxed), Instant::now());
+        tower.vote_gap(&[vote_slot], &slot_tree);
+        let incidents = tower.process_vote_slot(
+            &vote_account_address,
+            vote_slot,
+            &signature,
+            &slot_tree,
+            vote_slot,
+            16,
+            false,
+        );
+        elapsed += start.elapsed();
+        allocations += ALLOCATIONS.load(Ordering::Relaxed) - start_allocations;
+        assert!(incidents.is_empty());
+        // Recorded outside the measurement, as the vote gap is measured from the previous vote
+        tower.record_vote_signature(signature, vec![vote_slot]);
+    }
+
+    println!(
+        "{} votes on a linear fork of {} slots: {:.2} allocations/vote, {} ns/vote",
+        VOTES,
+        SLOTS,
+        allocations as f64 / VOTES as f64,
+        elapsed.as_nanos() / VOTES as u128
+    );
+}
@@ -0,0 +1,89 @@
+//! Throughput of vote processing for a synthetic stream of `VALIDATORS` validators each voting on
+//! every one of `SLOTS` slots, with each of the given numbers of vote workers. Run with
+//! `cargo bench --bench vote_processing -- 1 4`
+use {
+    solana_client::rpc_response::SlotInfo,
+    solana_sdk::{hash::Hash, pubkey::Pubkey, signature::Signature},
+    std::{env, sync::Arc, time::Instant},
+    votalizer::{
+        notifier::{Notifier, NotifierConfig},
+        statsd::Statsd,
+        vote::Vote,
+        Votalizer, VotalizerConfig,
+    },
+};
+
+const VALIDATORS: usize = 1000;
+const SLOTS: u64 = 200;
+
+#[tokio::main]
+async fn main() {
+    // `cargo bench` passes `--bench` along with the worker counts
+    let mut vote_workers = env::args()
+        .skip(1)
+        .filter_map(|arg| arg.parse().ok())
+        .collect::<Vec<usize>>();
+    if vote_workers.is_empty() {
+        vote_workers = vec![1, 4];
+    }
+
+    let vote_pubkeys = (0..VALIDATORS)
+        .map(|_| Pubkey::new_unique().to_string())
+        .collect::<Vec<_>>();
+    let hash = Hash::default().to_string();
+    for vote_workers in vote_workers {
+        // Built beforehand, so that only their processing is measured
+        let votes = (1..=SLOTS)
+            .map(|slot| {
+                vote_pubkeys
+                    .iter()
+                    .map(|vote_pubkey| Vote {
+                        vote_pubkey: vote_pubkey.clone(),
+                        slots: vec![slot],
+                        hash: hash.clone(),
+                        timestamp: None,
+                        signature: Signature::new_unique().to_string(),
+                        root: None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut votalizer = Votalizer::new(
+            VotalizerConfig {
+                write_text_incident: false,
+                vote_workers,
+                ..VotalizerConfig::default()
+            },
+            Notifier::new(NotifierConfig::default()),
+            Arc::default(),
+            Statsd::new(None, None).unwrap(),
+        );
+        let start = Instant::now();
+        for (slot, votes) in (1..=SLOTS).zip(votes) {
+            votalizer
+                .process_slot(&SlotInfo {
+                    slot,
+                    parent: slot - 1,
+                    root: slot.saturating_sub(32),
+                })
+                .await;
+            for vote in votes {
+                votalizer.process_vote(vote).await;
+            }
+        }
+        // Waits for the votes queued to be processed
+        let processed_votes = votalizer.processed_vote_count().await;
+        let elapsed = start.elapsed();
+        assert_eq!(processed_votes, VALIDATORS as u64 * SLOTS);
+
+        println!(
+            "{} votes with {} worker{}: {:.2}s, {} ns/vote",
+            processed_votes,
+            vote_workers,
+            if vote_workers == 1 { "" } else { "s" },
+            elapsed.as_secs_f64(),
+            elapsed.as_nanos() / processed_votes as u128
+        );
+    }
+}
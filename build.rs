@@ -0,0 +1,57 @@
+use {
+    chrono::{SecondsFormat, TimeZone, Utc},
+    std::{env, path::Path, process::Command},
+};
+
+/// Runs git with `args` in the package directory, returning its trimmed output if it succeeds
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Embeds the git commit and build time, for `--build-info` and the startup log
+fn main() {
+    let git_hash = match git(&["rev-parse", "HEAD"]) {
+        Some(git_hash)
+            if git(&["status", "--porcelain", "--untracked-files=no"])
+                .is_some_and(|status| !status.is_empty()) =>
+        {
+            format!("{}-dirty", git_hash)
+        }
+        Some(git_hash) => git_hash,
+        None => "unknown".into(),
+    };
+
+    // Honor SOURCE_DATE_EPOCH for reproducible builds
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    let build_timestamp = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|secs| secs.parse::<i64>().ok())
+        .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+        .unwrap_or_else(Utc::now);
+
+    println!("cargo:rustc-env=VOTALIZER_GIT_HASH={}", git_hash);
+    println!(
+        "cargo:rustc-env=VOTALIZER_BUILD_TIMESTAMP={}",
+        build_timestamp.to_rfc3339_opts(SecondsFormat::Secs, true)
+    );
+
+    // Rerun when the checked out commit or the sources change, but not on every build
+    println!("cargo:rerun-if-changed=src");
+    if let Some(git_dir) = git(&["rev-parse", "--git-dir"]) {
+        for path in [
+            Path::new(&git_dir).join("HEAD"),
+            Path::new(&git_dir).join("index"),
+        ]
+        .into_iter()
+        .chain(git(&["symbolic-ref", "HEAD"]).map(|head_ref| Path::new(&git_dir).join(head_ref)))
+        {
+            if path.exists() {
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use {
+    solana_client::rpc_response::{SlotInfo, SlotUpdate},
+    solana_sdk::{clock::Slot, commitment_config::CommitmentLevel},
+    std::collections::BTreeMap,
+};
+
+/// Reconstructs `SlotInfo` notifications for slots as they reach the `confirmed` or `finalized`
+/// commitment level, from the slot updates stream
+pub struct CommittedSlots {
+    commitment: CommitmentLevel,
+    parents: BTreeMap<Slot, Slot>,
+    last_committed_slot: Option<Slot>,
+    root: Slot,
+}
+
+impl CommittedSlots {
+    pub fn new(commitment: CommitmentLevel) -> Self {
+        Self {
+            commitment,
+            parents: BTreeMap::new(),
+            last_committed_slot: None,
+            root: 0,
+        }
+    }
+
+    /// Returns the slots that reached the commitment level due to `update`, oldest first. When a
+    /// slot is committed, so are any of its ancestors that had not yet been reported
+    pub fn process(&mut self, update: &SlotUpdate) -> Vec<SlotInfo> {
+        let committed_slot = match update {
+            SlotUpdate::CreatedBank { slot, parent, .. } => {
+                self.parents.insert(*slot, *parent);
+                return vec![];
+            }
+            SlotUpdate::OptimisticConfirmation { slot, .. }
+                if self.commitment == CommitmentLevel::Confirmed =>
+            {
+                *slot
+            }
+            SlotUpdate::Root { slot, .. } => {
+                self.root = self.root.max(*slot);
+                *slot
+            }
+            _ => return vec![],
+        };
+
+        let mut committed_slots = vec![];
+        let mut slot = committed_slot;
+        while self
+            .last_committed_slot
+            .is_none_or(|last_committed_slot| slot > last_committed_slot)
+        {
+            let Some(parent) = self.parents.get(&slot) else {
+                break;
+            };
+            committed_slots.push(SlotInfo {
+                slot,
+                parent: *parent,
+                root: self.root,
+            });
+            slot = *parent;
+        }
+        committed_slots.reverse();
+
+        if !committed_slots.is_empty() {
+            self.last_committed_slot = Some(committed_slot);
+            // Slots at or below a committed slot that have not been reported are on dead forks
+            self.parents = self.parents.split_off(&(committed_slot + 1));
+        }
+        committed_slots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn created_bank(slot: Slot, parent: Slot) -> SlotUpdate {
+        SlotUpdate::CreatedBank {
+            slot,
+            parent,
+            timestamp: 0,
+        }
+    }
+
+    fn slots(slot_infos: Vec<SlotInfo>) -> Vec<(Slot, Slot)> {
+        slot_infos
+            .into_iter()
+            .map(|slot_info| (slot_info.slot, slot_info.parent))
+            .collect()
+    }
+
+    #[test]
+    fn test_committed_slots() {
+        let mut committed_slots = CommittedSlots::new(CommitmentLevel::Confirmed);
+        for (slot, parent) in [(1, 0), (2, 1), (3, 1), (4, 2), (5, 4)] {
+            assert!(committed_slots
+                .process(&created_bank(slot, parent))
+                .is_empty());
+        }
+
+        // Confirming slot 4 also confirms its unreported ancestors, but not slot 3 on another fork
+        assert_eq!(
+            slots(
+                committed_slots.process(&SlotUpdate::OptimisticConfirmation {
+                    slot: 4,
+                    timestamp: 0
+                })
+            ),
+            vec![(1, 0), (2, 1), (4, 2)]
+        );
+        assert_eq!(
+            slots(committed_slots.process(&SlotUpdate::Root {
+                slot: 2,
+                timestamp: 0
+            })),
+            vec![]
+        );
+        assert_eq!(
+            slots(committed_slots.process(&SlotUpdate::Root {
+                slot: 5,
+                timestamp: 0
+            })),
+            vec![(5, 4)]
+        );
+        assert_eq!(committed_slots.root, 5);
+    }
+
+    #[test]
+    fn test_finalized_slots_ignore_confirmation() {
+        let mut committed_slots = CommittedSlots::new(CommitmentLevel::Finalized);
+        committed_slots.process(&created_bank(1, 0));
+        assert!(committed_slots
+            .process(&SlotUpdate::OptimisticConfirmation {
+                slot: 1,
+                timestamp: 0
+            })
+            .is_empty());
+        assert_eq!(
+            slots(committed_slots.process(&SlotUpdate::Root {
+                slot: 1,
+                timestamp: 0
+            })),
+            vec![(1, 0)]
+        );
+    }
+}
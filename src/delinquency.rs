@@ -0,0 +1,167 @@
+//! The cluster's own view of which validators are delinquent, from the RPC node's
+//! `getVoteAccounts`, to complement the liveness the votalizer infers from the votes it receives
+use {
+    crate::notifier::redact_client_error,
+    log::*,
+    solana_client::{nonblocking::rpc_client::RpcClient, rpc_response::RpcVoteAccountStatus},
+    solana_sdk::{clock::Slot, pubkey::Pubkey},
+    std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+    tokio::{task::JoinHandle, time::sleep},
+};
+
+/// A vote account's standing in a `getVoteAccounts` response
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoteAccountStatus {
+    pub delinquent: bool,
+    /// The most recent slot the vote account voted on
+    pub last_vote: Slot,
+}
+
+/// The vote accounts of a `getVoteAccounts` response, by address
+pub type VoteAccountStatuses = Arc<HashMap<Pubkey, VoteAccountStatus>>;
+
+/// Hands the `getVoteAccounts` responses of another poller to a `DelinquencyMonitor`
+#[derive(Clone)]
+pub struct VoteAccountsSink(Arc<Mutex<Option<VoteAccountStatuses>>>);
+
+impl VoteAccountsSink {
+    /// Replaces any response not yet taken by the monitor
+    pub fn publish(&self, vote_accounts: RpcVoteAccountStatus) {
+        *self.0.lock().unwrap() = Some(Arc::new(vote_account_statuses(vote_accounts)));
+    }
+}
+
+/// Holds the latest of the RPC node's vote accounts until the main loop takes it to check for
+/// delinquency. Queried every interval by the monitor itself, or by the `ValidatorDirectory` when
+/// both are in use, so that the RPC node is polled only once
+pub struct DelinquencyMonitor {
+    // The most recent response, until taken
+    latest: VoteAccountsSink,
+    // Unless another poller publishes to `latest`
+    refresh: Option<JoinHandle<()>>,
+}
+
+impl Drop for DelinquencyMonitor {
+    fn drop(&mut self) {
+        if let Some(refresh) = &self.refresh {
+            refresh.abort();
+        }
+    }
+}
+
+impl DelinquencyMonitor {
+    /// Starts querying the RPC node at `json_rpc_url` every `interval`. Must be called from within
+    /// a tokio runtime
+    pub fn start(json_rpc_url: String, interval: Duration) -> Self {
+        let (mut delinquency_monitor, latest) = Self::new();
+        delinquency_monitor.refresh = Some(tokio::spawn(Self::run(
+            RpcClient::new(json_rpc_url),
+            interval,
+            latest,
+        )));
+        delinquency_monitor
+    }
+
+    /// A monitor of the responses published to the returned sink, rather than of its own queries
+    pub fn new() -> (Self, VoteAccountsSink) {
+        let latest = VoteAccountsSink(Arc::default());
+        (
+            Self {
+                latest: latest.clone(),
+                refresh: None,
+            },
+            latest,
+        )
+    }
+
+    async fn run(rpc_client: RpcClient, interval: Duration, latest: VoteAccountsSink) {
+        loop {
+            match rpc_client.get_vote_accounts().await {
+                Ok(vote_accounts) => latest.publish(vote_accounts),
+                // Retried after the interval
+                Err(err) => warn!(
+                    "Unable to check vote account delinquency: {}",
+                    redact_client_error(err)
+                ),
+            }
+            sleep(interval).await;
+        }
+    }
+
+    /// The vote accounts, if refreshed since they were last taken
+    pub fn take_update(&self) -> Option<VoteAccountStatuses> {
+        self.latest.0.lock().unwrap().take()
+    }
+}
+
+fn vote_account_statuses(
+    vote_accounts: RpcVoteAccountStatus,
+) -> HashMap<Pubkey, VoteAccountStatus> {
+    let current = vote_accounts.current.into_iter().map(|info| (info, false));
+    let delinquent = vote_accounts
+        .delinquent
+        .into_iter()
+        .map(|info| (info, true));
+    current
+        .chain(delinquent)
+        .filter_map(|(info, delinquent)| {
+            let vote_account = info.vote_pubkey.parse().ok()?;
+            Some((
+                vote_account,
+                VoteAccountStatus {
+                    delinquent,
+                    last_vote: info.last_vote,
+                },
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, solana_client::rpc_response::RpcVoteAccountInfo};
+
+    fn vote_account_info(vote_pubkey: &str, last_vote: Slot) -> RpcVoteAccountInfo {
+        RpcVoteAccountInfo {
+            vote_pubkey: vote_pubkey.to_string(),
+            node_pubkey: Pubkey::new_unique().to_string(),
+            activated_stake: 0,
+            commission: 0,
+            epoch_vote_account: true,
+            epoch_credits: vec![],
+            last_vote,
+            root_slot: 0,
+        }
+    }
+
+    #[test]
+    fn test_vote_account_statuses() {
+        let (current, delinquent) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let statuses = vote_account_statuses(RpcVoteAccountStatus {
+            current: vec![vote_account_info(&current.to_string(), 100)],
+            delinquent: vec![
+                vote_account_info(&delinquent.to_string(), 10),
+                vote_account_info("not a pubkey", 10),
+            ],
+        });
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(
+            statuses[&current],
+            VoteAccountStatus {
+                delinquent: false,
+                last_vote: 100
+            }
+        );
+        assert_eq!(
+            statuses[&delinquent],
+            VoteAccountStatus {
+                delinquent: true,
+                last_vote: 10
+            }
+        );
+    }
+}
@@ -0,0 +1,124 @@
+use {
+    crate::notifier::redact_url,
+    log::*,
+    std::time::{Duration, Instant},
+};
+
+/// Consecutive failures after which an endpoint is temporarily skipped
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+/// How long an unhealthy endpoint is skipped for
+const UNHEALTHY_ENDPOINT_SKIP: Duration = Duration::from_secs(5 * 60);
+
+struct Endpoint {
+    websocket_url: String,
+    consecutive_failures: u32,
+    skip_until: Option<Instant>,
+}
+
+/// RPC endpoints to fail over between, round-robin
+pub struct Endpoints {
+    endpoints: Vec<Endpoint>,
+    current: usize,
+}
+
+impl Endpoints {
+    pub fn new(websocket_urls: Vec<String>) -> Self {
+        assert!(!websocket_urls.is_empty());
+        Self {
+            endpoints: websocket_urls
+                .into_iter()
+                .map(|websocket_url| Endpoint {
+                    websocket_url,
+                    consecutive_failures: 0,
+                    skip_until: None,
+                })
+                .collect(),
+            current: 0,
+        }
+    }
+
+    pub fn websocket_url(&self) -> &str {
+        &self.endpoints[self.current].websocket_url
+    }
+
+    pub fn succeeded(&mut self) {
+        let endpoint = &mut self.endpoints[self.current];
+        endpoint.consecutive_failures = 0;
+        endpoint.skip_until = None;
+    }
+
+    /// Records a failure of the current endpoint and fails over to the next one that is not
+    /// being skipped. If every other endpoint is being skipped, the next one is used regardless
+    pub fn failed(&mut self, now: Instant) {
+        let endpoint = &mut self.endpoints[self.current];
+        endpoint.consecutive_failures += 1;
+        if endpoint.consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+            warn!(
+                "{} failed {} times in a row, skipping it for {}s",
+                redact_url(&endpoint.websocket_url),
+                endpoint.consecutive_failures,
+                UNHEALTHY_ENDPOINT_SKIP.as_secs()
+            );
+            endpoint.skip_until = Some(now + UNHEALTHY_ENDPOINT_SKIP);
+            endpoint.consecutive_failures = 0;
+        }
+
+        if self.endpoints.len() == 1 {
+            return;
+        }
+        let previous = self.current;
+        let next = (1..self.endpoints.len())
+            .map(|offset| (previous + offset) % self.endpoints.len())
+            .find(|index| {
+                self.endpoints[*index]
+                    .skip_until
+                    .is_none_or(|skip_until| skip_until <= now)
+            })
+            .unwrap_or((previous + 1) % self.endpoints.len());
+        self.current = next;
+        info!(
+            "Failing over from {} to {}",
+            redact_url(&self.endpoints[previous].websocket_url),
+            redact_url(&self.endpoints[next].websocket_url)
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failover() {
+        let now = Instant::now();
+        let mut endpoints = Endpoints::new(vec!["a".into(), "b".into(), "c".into()]);
+        assert_eq!(endpoints.websocket_url(), "a");
+
+        endpoints.failed(now);
+        assert_eq!(endpoints.websocket_url(), "b");
+        endpoints.failed(now);
+        assert_eq!(endpoints.websocket_url(), "c");
+        endpoints.failed(now);
+        assert_eq!(endpoints.websocket_url(), "a");
+
+        // "a" fails once more and is then skipped
+        endpoints.endpoints[0].consecutive_failures = MAX_CONSECUTIVE_FAILURES - 1;
+        endpoints.failed(now);
+        assert_eq!(endpoints.websocket_url(), "b");
+        endpoints.failed(now);
+        assert_eq!(endpoints.websocket_url(), "c");
+        endpoints.failed(now);
+        assert_eq!(endpoints.websocket_url(), "b");
+
+        // A successful connection resets the failure count
+        endpoints.succeeded();
+        endpoints.failed(now);
+        assert_eq!(endpoints.websocket_url(), "c");
+        assert_eq!(endpoints.endpoints[1].consecutive_failures, 1);
+
+        // Once the skip expires, "a" is used again
+        endpoints.failed(now + UNHEALTHY_ENDPOINT_SKIP);
+        assert_eq!(endpoints.websocket_url(), "a");
+    }
+}
@@ -0,0 +1,76 @@
+//! Optional error reporting to Sentry, built with the `sentry` cargo feature and enabled by setting
+//! `SENTRY_DSN`. Otherwise every function here is a no-op
+use {crate::tower::Incident, log::*, std::env};
+
+/// Flushes any pending events when dropped
+#[cfg(feature = "sentry")]
+pub type Guard = sentry::ClientInitGuard;
+#[cfg(not(feature = "sentry"))]
+pub enum Guard {}
+
+/// Initializes Sentry if `SENTRY_DSN` is set. Panics and error records are reported until the
+/// returned guard is dropped, which flushes any pending events
+#[cfg(feature = "sentry")]
+pub fn init() -> Option<Guard> {
+    let dsn = env::var("SENTRY_DSN").ok().filter(|dsn| !dsn.is_empty())?;
+    let guard = sentry::init((
+        dsn,
+        sentry::ClientOptions {
+            release: sentry::release_name!(),
+            ..sentry::ClientOptions::default()
+        },
+    ));
+    if guard.is_enabled() {
+        info!("Reporting errors to Sentry");
+        Some(guard)
+    } else {
+        warn!("SENTRY_DSN is invalid, errors will not be reported to Sentry");
+        None
+    }
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn init() -> Option<Guard> {
+    if env::var("SENTRY_DSN").is_ok_and(|dsn| !dsn.is_empty()) {
+        warn!("SENTRY_DSN set but this build lacks the sentry feature, ignoring");
+    }
+    None
+}
+
+/// Flushes any pending events, for use before `std::process::exit`, which skips dropping the guard
+pub fn flush(guard: Option<Guard>) {
+    #[cfg(feature = "sentry")]
+    drop(guard);
+    #[cfg(not(feature = "sentry"))]
+    let _ = guard;
+}
+
+/// Wraps `logger` so that error records are also reported to Sentry as events, and warning and
+/// info records are attached to them as breadcrumbs
+#[cfg(feature = "sentry")]
+pub fn wrap_logger<L: Log + 'static>(logger: L) -> impl Log {
+    sentry::integrations::log::SentryLogger::with_dest(logger)
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn wrap_logger<L: Log + 'static>(logger: L) -> impl Log {
+    logger
+}
+
+/// Runs `f` with the incident's vote account and signature attached as tags to any event it
+/// reports
+#[cfg(feature = "sentry")]
+pub fn with_incident_scope<R>(incident: &Incident, f: impl FnOnce() -> R) -> R {
+    sentry::with_scope(
+        |scope| {
+            scope.set_tag("vote_account", incident.vote_account);
+            scope.set_tag("signature", incident.signature);
+        },
+        f,
+    )
+}
+
+#[cfg(not(feature = "sentry"))]
+pub fn with_incident_scope<R>(_incident: &Incident, f: impl FnOnce() -> R) -> R {
+    f()
+}
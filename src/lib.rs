@@ -0,0 +1,1573 @@
+//! Monitors Solana validators for voting lockout violations. `Votalizer` holds the state built up
+//! from the slot and vote notifications of an RPC node's pubsub service, however they are received
+use {
+    crate::{
+        delinquency::DelinquencyMonitor,
+        metrics::Metrics,
+        notifier::*,
+        replay::PubsubNotification,
+        shard::{ShardHandle, ShardStatus, Shared},
+        slot_tree::SlotTree,
+        statsd::Statsd,
+        tower::*,
+        validator_info::ValidatorDirectory,
+        vote::Vote,
+    },
+    chrono::{DateTime, SecondsFormat, TimeZone, Utc},
+    itertools::Itertools,
+    log::*,
+    serde_json::json,
+    solana_client::rpc_response::SlotInfo,
+    solana_sdk::{
+        clock::{Epoch, Slot},
+        epoch_schedule::EpochSchedule,
+        native_token::lamports_to_sol,
+        pubkey::Pubkey,
+        signature::Signature,
+    },
+    solana_vote_program::vote_state::MAX_LOCKOUT_HISTORY,
+    std::{
+        collections::{HashMap, HashSet, VecDeque},
+        ffi::OsStr,
+        fs::{self, File},
+        mem,
+        path::PathBuf,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, RwLock,
+        },
+        time::{Duration, Instant, SystemTime},
+    },
+    tokio::sync::oneshot,
+};
+
+#[macro_use]
+pub mod logging;
+
+pub mod commitment;
+pub mod delinquency;
+pub mod endpoint;
+pub mod error_reporting;
+pub mod metrics;
+pub mod notifier;
+pub mod pubsub;
+pub mod replay;
+mod shard;
+pub mod slot_tree;
+pub mod statsd;
+pub mod templates;
+pub mod tower;
+pub mod validator_info;
+pub mod vote;
+
+/// Accumulates activity over a day for a single consolidated status notification
+struct Digest {
+    hour: u32,
+    next_digest: DateTime<Utc>,
+    period_start: DateTime<Utc>,
+    processed_votes_at_start: u64,
+    incidents_at_start: usize,
+    max_validators: usize,
+}
+
+impl Digest {
+    fn new(hour: u32, processed_vote_counter: u64, incident_counter: usize) -> Self {
+        let now = Utc::now();
+        Self {
+            hour,
+            next_digest: Self::next_occurrence(hour, now),
+            period_start: now,
+            processed_votes_at_start: processed_vote_counter,
+            incidents_at_start: incident_counter,
+            max_validators: 0,
+        }
+    }
+
+    // The first top of `hour` (UTC) strictly after `now`
+    fn next_occurrence(hour: u32, now: DateTime<Utc>) -> DateTime<Utc> {
+        let today = Utc.from_utc_datetime(&now.naive_utc().date().and_hms_opt(hour, 0, 0).unwrap());
+        if today > now {
+            today
+        } else {
+            today + chrono::Duration::days(1)
+        }
+    }
+
+    /// Records the current counters, returning the digest message once it is due
+    fn update(
+        &mut self,
+        validators: usize,
+        processed_vote_counter: u64,
+        incident_counter: usize,
+    ) -> Option<String> {
+        self.max_validators = self.max_validators.max(validators);
+
+        let now = Utc::now();
+        if now < self.next_digest {
+            return None;
+        }
+
+        let incidents = incident_counter - self.incidents_at_start;
+        let digest = format!(
+            "daily digest since {}: tracking {} validators (max {}), {} votes processed, {} incident{} observed",
+            self.period_start.to_rfc3339_opts(SecondsFormat::Secs, true),
+            validators,
+            self.max_validators,
+            processed_vote_counter - self.processed_votes_at_start,
+            incidents,
+            if incidents == 1 { "" } else { "s" },
+        );
+        *self = Self::new(self.hour, processed_vote_counter, incident_counter);
+        Some(digest)
+    }
+}
+
+/// Known slots resent without a new slot before the RPC feed is considered stalled
+const STALL_DUPLICATE_SLOTS: usize = 10;
+
+/// Interval between removals of incident files older than the incident retention
+const INCIDENT_CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// How far processing may fall behind the websocket before it is logged, when notifications are
+/// not dropped
+const NOTIFICATION_LAG_WARNING: Duration = Duration::from_secs(10);
+
+/// Votes held for their slots to be observed, beyond which the oldest is processed without
+/// waiting further
+const MAX_PENDING_VOTES: usize = 65536;
+
+/// A vote held until its slot is observed, so that it can be checked against the validator's
+/// lockouts
+struct PendingVote {
+    vote: Vote,
+    vote_account_address: Pubkey,
+    signature: Signature,
+    slot: Slot,
+    received_at: Instant,
+}
+
+/// Parses the vote account address and transaction signature of `vote`
+pub fn parse_vote_ids(vote: &Vote) -> Result<(Pubkey, Signature), String> {
+    let vote_account_address = vote
+        .vote_pubkey
+        .parse::<Pubkey>()
+        .map_err(|err| format!("invalid vote account address {}: {}", vote.vote_pubkey, err))?;
+    let signature = vote
+        .signature
+        .parse::<Signature>()
+        .map_err(|err| format!("invalid signature {}: {}", vote.signature, err))?;
+    Ok((vote_account_address, signature))
+}
+
+/// Monitoring configuration. The defaults match those of the command line
+pub struct VotalizerConfig {
+    pub max_tracked_ancestors: usize,
+    pub max_tracked_slots: usize,
+    /// Only monitor these vote accounts [default: every vote account]
+    pub vote_account_filter: Option<HashSet<Pubkey>>,
+    /// Notify when a validator that has voted before goes this long without voting
+    pub liveness_threshold: Option<Duration>,
+    /// Notify when the RPC feed produces no new slot for this long
+    pub stall_threshold: Option<Duration>,
+    /// Seconds a vote timestamp may differ from the local clock by
+    pub timestamp_tolerance: i64,
+    /// Slots a validator's votes may be received after the current slot by, on average
+    pub max_vote_latency: Option<f64>,
+    /// Slots a validator may skip voting on between consecutive votes
+    pub max_vote_gap: Option<usize>,
+    /// Report a vote expiring every vote of a tower at least this deep as a tower reset, unless 0
+    pub tower_reset_depth: usize,
+    /// Slots a validator's root may lag the median root of the tracked validators by before the
+    /// status report lists it [default: lagging roots not listed]
+    pub max_root_lag: Option<Slot>,
+    /// Incidents with at least this severity score are notified as critical, and the rest as
+    /// warnings
+    pub critical_severity: u64,
+    pub incident_cooldown: Duration,
+    /// Forget the towers of validators that have not voted for this long, so that towers of
+    /// validators that leave the cluster don't accumulate
+    pub tower_idle_timeout: Option<Duration>,
+    pub incident_dir: PathBuf,
+    /// Remove incident files from `incident_dir` once they are this old [default: incident files
+    /// kept forever]
+    pub incident_retention: Option<Duration>,
+    pub write_text_incident: bool,
+    pub write_json_incident: bool,
+    /// Towers are saved to this file every minute, and restored from it by `Votalizer::new`
+    pub tower_snapshot: Option<PathBuf>,
+    /// Seconds after which a tower snapshot is too old to restore
+    pub tower_snapshot_max_age: i64,
+    pub status_interval: Duration,
+    pub status_notify_interval: Duration,
+    /// Send a daily digest at this UTC hour instead of a status notification every
+    /// `status_notify_interval`
+    pub digest_hour: Option<u32>,
+    /// Look up the node identity and stake of each validator with the RPC node at this URL, for
+    /// incident reports and the status report
+    pub validator_info_url: Option<String>,
+    /// Ignore the votes of validators with less than this many lamports of stake, once looked up
+    /// with `validator_info_url`
+    pub min_stake: Option<u64>,
+    /// Notify when a validator becomes delinquent, or stops being, according to the vote accounts
+    /// of the RPC node at this URL
+    pub delinquency_check_url: Option<String>,
+    /// Interval between queries of the vote accounts with `delinquency_check_url`
+    pub delinquency_check_interval: Duration,
+    /// Notifications are replayed rather than received live, so only the ordering of vote
+    /// timestamps can be checked
+    pub replaying: bool,
+    /// Number of shards processing votes concurrently. A single shard processes each vote as it
+    /// is received
+    pub vote_workers: usize,
+    /// Number of votes in a validator's tower before the oldest is rooted
+    pub max_lockout_history: usize,
+    /// Recent incidents remembered for each validator
+    pub max_incident_history: usize,
+    /// Hold a vote for a slot not yet observed for up to this long, to check it against the
+    /// validator's lockouts once the slot arrives [default: such votes are applied unchecked]
+    pub pending_vote_timeout: Option<Duration>,
+    /// Drop notifications that waited longer than this to be processed, so that processing
+    /// catches up with the websocket after a slowdown [default: notifications never dropped]
+    pub max_notification_lag: Option<Duration>,
+    /// Derives the epoch of each slot, to report incidents and status by epoch [default: epochs
+    /// not reported]
+    pub epoch_schedule: Option<EpochSchedule>,
+    /// Friendly names of validators, shown alongside their vote account addresses in incident
+    /// reports and notifications
+    pub labels: HashMap<Pubkey, String>,
+    /// Vote accounts whose votes are traced through each step of the lockout check, at `info`
+    /// level
+    pub explain: HashSet<Pubkey>,
+    /// Distinguishes this votalizer from others on the same host, namespacing its incident files
+    pub instance_name: Option<String>,
+}
+
+impl Default for VotalizerConfig {
+    fn default() -> Self {
+        Self {
+            max_tracked_ancestors: 10240,
+            max_tracked_slots: 10240,
+            vote_account_filter: None,
+            liveness_threshold: Some(Duration::from_secs(60)),
+            stall_threshold: Some(Duration::from_secs(60)),
+            timestamp_tolerance: 120,
+            max_vote_latency: None,
+            max_vote_gap: None,
+            tower_reset_depth: 16,
+            max_root_lag: None,
+            critical_severity: 8,
+            incident_cooldown: Duration::ZERO,
+            tower_idle_timeout: Some(Duration::from_secs(86400)),
+            incident_dir: PathBuf::from("."),
+            incident_retention: None,
+            write_text_incident: true,
+            write_json_incident: false,
+            tower_snapshot: None,
+            tower_snapshot_max_age: 600,
+            status_interval: Duration::from_secs(30),
+            status_notify_interval: Duration::from_secs(43200),
+            digest_hour: None,
+            validator_info_url: None,
+            delinquency_check_url: None,
+            delinquency_check_interval: Duration::from_secs(60),
+            min_stake: None,
+            replaying: false,
+            vote_workers: 1,
+            max_lockout_history: MAX_LOCKOUT_HISTORY,
+            max_incident_history: 10,
+            pending_vote_timeout: Some(Duration::from_secs(30)),
+            max_notification_lag: None,
+            epoch_schedule: None,
+            labels: HashMap::new(),
+            explain: HashSet::new(),
+            instance_name: None,
+        }
+    }
+}
+
+impl VotalizerConfig {
+    /// Removes the incident files, and their acknowledgement markers, last modified longer than
+    /// the incident retention before `now`. Returns the number removed
+    fn remove_expired_incidents(&self, now: SystemTime) -> usize {
+        let Some(incident_retention) = self.incident_retention else {
+            return 0;
+        };
+        let entries = match fs::read_dir(&self.incident_dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                warn!("Unable to read {}: {}", self.incident_dir.display(), err);
+                return 0;
+            }
+        };
+        let prefix = incident_file_prefix(self.instance_name.as_deref());
+        let mut removed = 0;
+        for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+            let is_incident_file = path
+                .file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(|filename| filename.starts_with(&prefix))
+                && path
+                    .extension()
+                    .and_then(OsStr::to_str)
+                    .is_some_and(|extension| matches!(extension, "log" | "json" | "acked"));
+            let expired = fs::metadata(&path)
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|modified| {
+                    now.duration_since(modified)
+                        .is_ok_and(|age| age > incident_retention)
+                });
+            if !is_incident_file || !expired {
+                continue;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    info!("Removed expired incident file {}", path.display());
+                    removed += 1;
+                }
+                Err(err) => warn!("Unable to remove {}: {}", path.display(), err),
+            }
+        }
+        removed
+    }
+
+    /// The label of `vote_account_address` followed by the address, or the bare address if it is
+    /// not labeled
+    pub fn validator_name(&self, vote_account_address: &Pubkey) -> String {
+        match self.labels.get(vote_account_address) {
+            Some(label) => format!("{} ({})", label, vote_account_address),
+            None => vote_account_address.to_string(),
+        }
+    }
+
+    fn write_incident(&self, incident: &Incident) {
+        if self.write_text_incident {
+            let path = self.incident_dir.join(incident.filename());
+            File::create(&path)
+                .and_then(|mut output| {
+                    use std::io::Write;
+                    writeln!(output, "{}", incident)
+                })
+                .unwrap_or_else(|err| error!("Unable to write {}: {}", path.display(), err));
+        }
+        if self.write_json_incident {
+            let path = self.incident_dir.join(incident.json_filename());
+            File::create(&path)
+                .and_then(|output| {
+                    serde_json::to_writer_pretty(output, &incident.to_json()).map_err(Into::into)
+                })
+                .unwrap_or_else(|err| error!("Unable to write {}: {}", path.display(), err));
+        }
+    }
+}
+
+/// Tracks the ancestry of recent slots and the tower of every monitored validator, checking each
+/// vote against its validator's lockouts. Votes are processed by `VotalizerConfig::vote_workers`
+/// shards, each owning the towers of a fixed subset of validators
+pub struct Votalizer {
+    shared: Arc<Shared>,
+    shards: Vec<ShardHandle>,
+    validator_directory: Option<ValidatorDirectory>,
+    delinquency_monitor: Option<DelinquencyMonitor>,
+    connected_at: Instant,
+    // When the most recent slot was first observed, and the known slots resent since
+    last_slot_advance: Instant,
+    duplicate_slots: usize,
+    stalled: bool,
+    last_status_report: Instant,
+    last_notifier_status_report: Instant,
+    last_tower_snapshot: Instant,
+    last_incident_cleanup: Instant,
+    digest: Option<Digest>,
+    // The epoch of the most recent slot, and the vote and incident counts when it was first
+    // observed
+    epoch: Option<Epoch>,
+    epoch_start_counts: (u64, usize),
+    // Oldest first, with the validators that have a vote among them. A validator's later votes
+    // are held behind its pending vote, to be processed in order
+    pending_votes: VecDeque<PendingVote>,
+    pending_validators: HashSet<Pubkey>,
+    // The longest a notification waited to be processed since the last status report, and the
+    // notifications dropped for waiting too long
+    max_notification_lag: Duration,
+    dropped_notifications: u64,
+    // While processing is falling behind the websocket, the dropped notification count when it
+    // started to
+    falling_behind: Option<u64>,
+}
+
+impl Votalizer {
+    /// Must be called from within a tokio runtime, which runs the shards unless there is only one
+    pub fn new(
+        config: VotalizerConfig,
+        notifier: Notifier,
+        metrics: Arc<Metrics>,
+        statsd: Statsd,
+    ) -> Self {
+        let mut towers = HashMap::new();
+        if let Some(tower_snapshot) = config.tower_snapshot.as_ref().filter(|path| path.exists()) {
+            match load_towers(
+                tower_snapshot,
+                Utc::now().timestamp(),
+                config.tower_snapshot_max_age,
+                config.max_lockout_history,
+            ) {
+                Ok(saved_towers) => {
+                    info!(
+                        "Restored {} towers from {}",
+                        saved_towers.len(),
+                        tower_snapshot.display()
+                    );
+                    towers = saved_towers;
+                    if let Some(vote_account_filter) = &config.vote_account_filter {
+                        towers.retain(|vote_account_address, _| {
+                            vote_account_filter.contains(vote_account_address)
+                        });
+                    }
+                }
+                Err(err) => warn!(
+                    "Ignoring tower snapshot {}: {}",
+                    tower_snapshot.display(),
+                    err
+                ),
+            }
+        }
+
+        config.remove_expired_incidents(SystemTime::now());
+
+        let vote_workers = config.vote_workers.max(1);
+        let digest = config.digest_hour.map(|hour| Digest::new(hour, 0, 0));
+        // The delinquency check shares the validator directory's queries of the same RPC node
+        let (validator_directory, delinquency_monitor) = match (
+            config.validator_info_url.clone(),
+            config.delinquency_check_url.clone(),
+        ) {
+            (Some(validator_info_url), Some(delinquency_check_url))
+                if validator_info_url == delinquency_check_url =>
+            {
+                let (delinquency_monitor, vote_accounts_sink) = DelinquencyMonitor::new();
+                let validator_directory = ValidatorDirectory::start(
+                    validator_info_url,
+                    Some((config.delinquency_check_interval, vote_accounts_sink)),
+                );
+                (Some(validator_directory), Some(delinquency_monitor))
+            }
+            (validator_info_url, delinquency_check_url) => (
+                validator_info_url
+                    .map(|json_rpc_url| ValidatorDirectory::start(json_rpc_url, None)),
+                delinquency_check_url.map(|json_rpc_url| {
+                    DelinquencyMonitor::start(json_rpc_url, config.delinquency_check_interval)
+                }),
+            ),
+        };
+        let slot_ancestors = RwLock::new(SlotTree::new(config.max_tracked_ancestors));
+        let shared = Arc::new(Shared {
+            config,
+            notifier,
+            metrics,
+            statsd,
+            slot_ancestors,
+            current_slot: AtomicU64::new(0),
+        });
+
+        let mut shard_towers = (0..vote_workers)
+            .map(|_| HashMap::new())
+            .collect::<Vec<_>>();
+        for (vote_account_address, tower) in towers {
+            shard_towers[shard_index(&vote_account_address, vote_workers)]
+                .insert(vote_account_address, tower);
+        }
+        let shards = shard_towers
+            .into_iter()
+            .map(|towers| {
+                shard::start(
+                    shared.clone(),
+                    validator_directory.clone(),
+                    towers,
+                    vote_workers == 1,
+                )
+            })
+            .collect();
+
+        let now = Instant::now();
+        Self {
+            shared,
+            shards,
+            validator_directory,
+            delinquency_monitor,
+            connected_at: now,
+            last_slot_advance: now,
+            duplicate_slots: 0,
+            stalled: false,
+            last_status_report: now,
+            last_notifier_status_report: now,
+            last_tower_snapshot: now,
+            last_incident_cleanup: now,
+            digest,
+            epoch: None,
+            epoch_start_counts: (0, 0),
+            pending_votes: VecDeque::new(),
+            pending_validators: HashSet::new(),
+            max_notification_lag: Duration::ZERO,
+            dropped_notifications: 0,
+            falling_behind: None,
+        }
+    }
+
+    pub fn notifier(&self) -> &Notifier {
+        &self.shared.notifier
+    }
+
+    fn shard(&self, vote_account_address: &Pubkey) -> &ShardHandle {
+        &self.shards[shard_index(vote_account_address, self.shards.len())]
+    }
+
+    /// Calls `f` with the validator's tower, once every vote queued before has been processed
+    pub async fn with_tower<R: Send + 'static>(
+        &self,
+        vote_account_address: &Pubkey,
+        f: impl FnOnce(&Tower) -> R + Send + 'static,
+    ) -> Option<R> {
+        let (reply, result) = oneshot::channel();
+        self.shard(vote_account_address)
+            .send(shard::Request::WithTower(
+                *vote_account_address,
+                Box::new(move |tower| {
+                    let _ = reply.send(tower.map(f));
+                }),
+            ))
+            .await;
+        result.await.ok().flatten()
+    }
+
+    // Once every vote queued before has been processed
+    async fn counts(&self) -> (u64, usize) {
+        let mut counts = (0, 0);
+        for shard in &self.shards {
+            let (reply, result) = oneshot::channel();
+            shard.send(shard::Request::Counts(reply)).await;
+            if let Ok((processed_votes, incidents)) = result.await {
+                counts.0 += processed_votes;
+                counts.1 += incidents;
+            }
+        }
+        counts
+    }
+
+    /// The number of vote slots processed, once every vote queued has been
+    pub async fn processed_vote_count(&self) -> u64 {
+        self.counts().await.0
+    }
+
+    /// The number of incidents observed, once every vote queued has been processed
+    pub async fn incident_count(&self) -> usize {
+        self.counts().await.1
+    }
+
+    /// Whether the RPC feed has stopped producing new slots, see `check_stall`
+    pub fn stalled(&self) -> bool {
+        self.stalled
+    }
+
+    /// Records that notifications are arriving again, after connecting or reconnecting. Votes
+    /// missed while disconnected say nothing about validator liveness, nor slots missed about
+    /// the RPC feed
+    pub fn connected(&mut self) {
+        self.connected_at = Instant::now();
+        self.last_slot_advance = self.connected_at;
+        self.duplicate_slots = 0;
+        self.shared.metrics.connected.store(true, Ordering::Relaxed);
+        self.shared.metrics.slot_advanced();
+    }
+
+    /// Records that the notification stream ended, failing the health check until reconnected
+    pub fn disconnected(&self) {
+        self.shared
+            .metrics
+            .connected
+            .store(false, Ordering::Relaxed);
+    }
+
+    /// Checks how long a notification received at `received_at` waited to be processed, returning
+    /// whether to process it. Once processing falls more than `max_notification_lag` behind, as
+    /// when a slowdown backs notifications up in memory, they are dropped oldest first until it
+    /// catches up
+    pub fn check_notification_lag(&mut self, received_at: Instant, now: Instant) -> bool {
+        let lag = now.saturating_duration_since(received_at);
+        self.max_notification_lag = self.max_notification_lag.max(lag);
+        let max_notification_lag = self.shared.config.max_notification_lag;
+        if lag <= max_notification_lag.unwrap_or(NOTIFICATION_LAG_WARNING) {
+            if let Some(dropped_notifications) = self.falling_behind.take() {
+                info!(
+                    "Processing caught up with the websocket{}",
+                    match self.dropped_notifications - dropped_notifications {
+                        0 => "".into(),
+                        dropped => format!(", {} notifications dropped", dropped),
+                    }
+                );
+            }
+            return true;
+        }
+        if self.falling_behind.is_none() {
+            warn!(
+                "Processing is {:.1}s behind the websocket{}",
+                lag.as_secs_f64(),
+                max_notification_lag
+                    .map(|max_notification_lag| format!(
+                        ", dropping notifications older than {}s",
+                        max_notification_lag.as_secs()
+                    ))
+                    .unwrap_or_default()
+            );
+            self.falling_behind = Some(self.dropped_notifications);
+        }
+        if max_notification_lag.is_none() {
+            return true;
+        }
+        self.dropped_notifications += 1;
+        self.shared
+            .metrics
+            .dropped_notifications
+            .fetch_add(1, Ordering::Relaxed);
+        false
+    }
+
+    /// Notifies once when the RPC feed appears stalled, having produced no new slot within the
+    /// stall threshold or only resent known slots. Every lockout check depends on new slots, so
+    /// a stalled feed silently disables detection. Should be called periodically, as a stalled
+    /// feed may produce no notifications at all
+    pub fn check_stall(&mut self, now: Instant) {
+        let Some(stall_threshold) = self.shared.config.stall_threshold else {
+            return;
+        };
+        if self.stalled {
+            return;
+        }
+
+        let stalled_for = now.duration_since(self.last_slot_advance);
+        let reason = if stalled_for > stall_threshold {
+            format!("no new slot in {} seconds", stalled_for.as_secs())
+        } else if self.duplicate_slots >= STALL_DUPLICATE_SLOTS {
+            format!(
+                "{} known slots resent without a new slot",
+                self.duplicate_slots
+            )
+        } else {
+            return;
+        };
+        self.stalled = true;
+        let msg = format!(
+            "votalizer: RPC feed looks stalled at slot {}, {}. Lockout detection is paused",
+            self.shared.current_slot.load(Ordering::Relaxed),
+            reason
+        );
+        warn!("{}", msg);
+        self.shared
+            .notifier
+            .send_with_severity(&msg, Severity::Warning, Utc::now());
+    }
+
+    pub async fn process_notification(&mut self, notification: PubsubNotification) {
+        match notification {
+            PubsubNotification::Slot(slot_info) => self.process_slot(&slot_info).await,
+            PubsubNotification::Vote(vote) => self.process_vote(vote).await,
+        }
+    }
+
+    pub async fn process_slot(&mut self, slot_info: &SlotInfo) {
+        let now = Instant::now();
+        let shared = self.shared.clone();
+        let config = &shared.config;
+        if slot_info.slot > shared.current_slot.load(Ordering::Relaxed) {
+            if self.stalled {
+                let msg = format!(
+                    "votalizer: RPC feed resumed at slot {} after {} seconds",
+                    slot_info.slot,
+                    now.duration_since(self.last_slot_advance).as_secs()
+                );
+                info!("{}", msg);
+                shared
+                    .notifier
+                    .send_with_severity(&msg, Severity::Info, Utc::now());
+                self.stalled = false;
+            }
+            shared.current_slot.store(slot_info.slot, Ordering::Relaxed);
+            self.last_slot_advance = now;
+            shared.metrics.slot_advanced();
+            if let Some(validator_directory) = &self.validator_directory {
+                validator_directory.observe_slot(slot_info.slot);
+            }
+            self.duplicate_slots = 0;
+
+            if let Some(epoch_schedule) = &config.epoch_schedule {
+                let epoch = epoch_schedule.get_epoch(slot_info.slot);
+                if self.epoch != Some(epoch) {
+                    if self.epoch.is_some() {
+                        info!("Epoch {} started at slot {}", epoch, slot_info.slot);
+                    }
+                    self.epoch = Some(epoch);
+                    // Votes queued before the first slot of the epoch count towards the last
+                    self.epoch_start_counts = self.counts().await;
+                }
+            }
+        }
+
+        let tracked_ancestors = {
+            let mut slot_ancestors = shared.slot_ancestors.write().unwrap();
+            let tracked_ancestors = slot_ancestors.insert(slot_info.slot, slot_info.parent);
+            slot_ancestors.prune(config.max_tracked_slots);
+            tracked_ancestors
+        };
+        let Some(tracked_ancestors) = tracked_ancestors else {
+            warn!(
+                "slot {} already present in slot_ancestors. RPC node stuck?",
+                slot_info.slot
+            );
+            self.duplicate_slots += 1;
+            self.check_stall(now);
+            return;
+        };
+
+        self.release_pending_votes(now).await;
+
+        shared
+            .metrics
+            .tracked_ancestors
+            .store(tracked_ancestors as u64, Ordering::Relaxed);
+        event!(
+            Level::Info,
+            "slot",
+            json!({
+                "slot": slot_info.slot,
+                "parent": slot_info.parent,
+                "tracked_ancestors": tracked_ancestors,
+            }),
+            "slot: {} (parent: {}, {} tracked ancestors)",
+            slot_info.slot,
+            slot_info.parent,
+            tracked_ancestors
+        );
+
+        if now.duration_since(self.last_status_report) > config.status_interval {
+            self.report_status(now).await;
+            self.last_status_report = now;
+        }
+
+        if config.tower_snapshot.is_some()
+            && now.duration_since(self.last_tower_snapshot) > Duration::from_secs(60)
+        {
+            self.save_tower_snapshot().await;
+            self.last_tower_snapshot = now;
+        }
+
+        if config.incident_retention.is_some()
+            && now.duration_since(self.last_incident_cleanup) > INCIDENT_CLEANUP_INTERVAL
+        {
+            config.remove_expired_incidents(SystemTime::now());
+            self.last_incident_cleanup = now;
+        }
+
+        // Votes missed while disconnected say nothing about validator liveness
+        if let Some(liveness_threshold) = config.liveness_threshold.filter(|liveness_threshold| {
+            now.duration_since(self.connected_at) > *liveness_threshold
+        }) {
+            for shard in &self.shards {
+                shard
+                    .send(shard::Request::CheckLiveness {
+                        now,
+                        threshold: liveness_threshold,
+                    })
+                    .await;
+            }
+        }
+
+        if let Some(vote_account_statuses) = self
+            .delinquency_monitor
+            .as_ref()
+            .and_then(DelinquencyMonitor::take_update)
+        {
+            for shard in &self.shards {
+                shard
+                    .send(shard::Request::CheckDelinquency(
+                        vote_account_statuses.clone(),
+                    ))
+                    .await;
+            }
+        }
+    }
+
+    /// Logs the status report at once, followed by the health of each notifier backend, and also
+    /// notifies them if `notify`. For operators who don't want to wait for the next status report
+    pub async fn dump_status(&mut self, notify: bool) {
+        let (status_report, status) = self.status_report(Instant::now()).await;
+        let notifier_health = self.shared.notifier.health();
+        info!("notifier backends: {}", notifier_health);
+        if notify {
+            self.shared.notifier.send_status(
+                &format!("{}\nnotifier backends: {}", status_report, notifier_health),
+                &self.status_fields(&status),
+                Utc::now(),
+            );
+        }
+    }
+
+    // The values of the status template's placeholders
+    fn status_fields(&self, status: &ShardStatus) -> Vec<(&'static str, String)> {
+        vec![
+            ("validators", status.validators.to_string()),
+            ("votes_processed", status.processed_votes.to_string()),
+            ("incidents", status.incidents.to_string()),
+            (
+                "epoch",
+                self.epoch
+                    .map(|epoch| epoch.to_string())
+                    .unwrap_or_default(),
+            ),
+        ]
+    }
+
+    async fn report_status(&mut self, now: Instant) {
+        let (status_report, status) = self.status_report(now).await;
+        if let Some(digest) = self.digest.as_mut() {
+            if let Some(msg) =
+                digest.update(status.validators, status.processed_votes, status.incidents)
+            {
+                self.shared
+                    .notifier
+                    .send_with_severity(&msg, Severity::Info, Utc::now());
+            }
+        } else if now.duration_since(self.last_notifier_status_report)
+            > self.shared.config.status_notify_interval
+        {
+            self.shared.notifier.send_status(
+                &status_report,
+                &self.status_fields(&status),
+                Utc::now(),
+            );
+            self.last_notifier_status_report = now;
+        }
+    }
+
+    /// Gathers the status of every shard and logs it, returning the status report
+    async fn status_report(&mut self, now: Instant) -> (String, ShardStatus) {
+        let mut status = ShardStatus::default();
+        for shard in &self.shards {
+            let (reply, result) = oneshot::channel();
+            shard.send(shard::Request::Status { now, reply }).await;
+            let Ok(shard_status) = result.await else {
+                continue;
+            };
+            status.validators += shard_status.validators;
+            status.activated_stake += shard_status.activated_stake;
+            status.low_stake_validators += shard_status.low_stake_validators;
+            status.tower_memory += shard_status.tower_memory;
+            status.processed_votes += shard_status.processed_votes;
+            status.incidents += shard_status.incidents;
+            status.latency_sum += shard_status.latency_sum;
+            status.latency_count += shard_status.latency_count;
+            status.latency_max = status.latency_max.max(shard_status.latency_max);
+            status.top_offenders.extend(shard_status.top_offenders);
+            status.roots.extend(shard_status.roots);
+        }
+        shard::sort_offenders(&mut status.top_offenders);
+        let max_notification_lag = mem::take(&mut self.max_notification_lag);
+        let dropped_notifications = self.dropped_notifications;
+        let ShardStatus {
+            validators,
+            tower_memory,
+            processed_votes,
+            incidents,
+            latency_sum,
+            latency_count,
+            latency_max,
+            ref top_offenders,
+            ref roots,
+            ..
+        } = status;
+        let (median_root_slot, lagging_count, lagging_roots) = self
+            .shared
+            .config
+            .max_root_lag
+            .map(|max_root_lag| lagging_roots(roots, max_root_lag))
+            .unwrap_or_default();
+        let activated_stake = self
+            .validator_directory
+            .as_ref()
+            .map(|_| status.activated_stake);
+        let low_stake_validators = self
+            .shared
+            .config
+            .min_stake
+            .map(|_| status.low_stake_validators);
+
+        // Counts since the epoch started, or the votalizer did if later
+        let epoch_counts = self.epoch.map(|epoch| {
+            (
+                epoch,
+                processed_votes - self.epoch_start_counts.0,
+                incidents - self.epoch_start_counts.1,
+            )
+        });
+
+        self.shared
+            .metrics
+            .validators_tracked
+            .store(validators as u64, Ordering::Relaxed);
+        self.shared
+            .statsd
+            .gauge("votalizer.validators_tracked", validators as u64);
+
+        let status_report = format!(
+            "{}tracking {} validators{}{}, towers using {:.1} MiB, {} votes processed{}{}, \
+            notification lag max {:.1}s{}{}{}{}",
+            epoch_counts
+                .map(|(epoch, _, _)| format!("epoch {}: ", epoch))
+                .unwrap_or_default(),
+            validators,
+            activated_stake
+                .map(|activated_stake| format!(
+                    " ({:.0} SOL staked)",
+                    lamports_to_sol(activated_stake)
+                ))
+                .unwrap_or_default(),
+            low_stake_validators
+                .map(|low_stake_validators| format!(
+                    ", ignoring {} below the minimum stake",
+                    low_stake_validators
+                ))
+                .unwrap_or_default(),
+            tower_memory as f64 / (1024 * 1024) as f64,
+            processed_votes,
+            epoch_counts
+                .map(|(_, epoch_votes, _)| format!(" ({} this epoch)", epoch_votes))
+                .unwrap_or_default(),
+            if latency_count > 0 {
+                format!(
+                    ", vote latency mean {:.1} slots (max {})",
+                    latency_sum / latency_count as f64,
+                    latency_max
+                )
+            } else {
+                "".into()
+            },
+            max_notification_lag.as_secs_f64(),
+            if dropped_notifications > 0 {
+                format!(", {} notifications dropped", dropped_notifications)
+            } else {
+                "".into()
+            },
+            if incidents > 0 {
+                format!(
+                    ", {} incident{} observed{}",
+                    incidents,
+                    if incidents > 1 { "s" } else { "" },
+                    epoch_counts
+                        .map(|(_, _, epoch_incidents)| format!(" ({} this epoch)", epoch_incidents))
+                        .unwrap_or_default()
+                )
+            } else {
+                "".into()
+            },
+            if top_offenders.is_empty() {
+                "".into()
+            } else {
+                format!(
+                    ", top offenders: {}",
+                    top_offenders
+                        .iter()
+                        .map(|(vote_account_address, incidents)| format!(
+                            "{} ({})",
+                            self.shared.config.validator_name(vote_account_address),
+                            incidents
+                        ))
+                        .join(", ")
+                )
+            },
+            if lagging_count == 0 {
+                "".into()
+            } else {
+                format!(
+                    ", {} root{} lagging the median root slot {}: {}",
+                    lagging_count,
+                    if lagging_count > 1 { "s" } else { "" },
+                    median_root_slot.unwrap_or_default(),
+                    lagging_roots
+                        .iter()
+                        .map(|lagging_root| format!(
+                            "{} ({} slots behind, {})",
+                            self.shared
+                                .config
+                                .validator_name(&lagging_root.vote_account_address),
+                            lagging_root.slots_behind,
+                            lagging_root
+                                .root_advancement_rate
+                                .map(|rate| format!("rooting {:.1} slots/s", rate))
+                                .unwrap_or_else(|| "not rooting".into())
+                        ))
+                        .join(", ")
+                )
+            }
+        );
+
+        event!(
+            Level::Info,
+            "status",
+            json!({
+                "validators": validators,
+                "activated_stake": activated_stake,
+                "low_stake_validators": low_stake_validators,
+                "tower_memory_bytes": tower_memory,
+                "votes_processed": processed_votes,
+                "incidents": incidents,
+                "epoch": epoch_counts.map(|(epoch, _, _)| epoch),
+                "epoch_votes_processed": epoch_counts.map(|(_, epoch_votes, _)| epoch_votes),
+                "epoch_incidents": epoch_counts.map(|(_, _, epoch_incidents)| epoch_incidents),
+                "vote_latency_mean": (latency_count > 0)
+                    .then(|| latency_sum / latency_count as f64),
+                "vote_latency_max": (latency_count > 0).then_some(latency_max),
+                "notification_lag_max_secs": max_notification_lag.as_secs_f64(),
+                "dropped_notifications": dropped_notifications,
+                "top_offenders": top_offenders
+                    .iter()
+                    .map(|(vote_account_address, incidents)| json!({
+                        "vote_account": vote_account_address.to_string(),
+                        "incidents": incidents,
+                    }))
+                    .collect::<Vec<_>>(),
+                "median_root_slot": median_root_slot,
+                "lagging_roots": lagging_roots
+                    .iter()
+                    .map(|lagging_root| json!({
+                        "vote_account": lagging_root.vote_account_address.to_string(),
+                        "root_slot": lagging_root.root_slot,
+                        "slots_behind": lagging_root.slots_behind,
+                        "root_advancement_rate": lagging_root.root_advancement_rate,
+                    }))
+                    .collect::<Vec<_>>(),
+            }),
+            "{}",
+            status_report
+        );
+        (status_report, status)
+    }
+
+    /// Queues `vote` for processing by the shard owning its validator's tower
+    pub async fn process_vote(&mut self, vote: Vote) {
+        let (vote_account_address, signature) = match parse_vote_ids(&vote) {
+            Ok(ids) => ids,
+            Err(err) => {
+                warn!("Ignoring malformed vote: {}", err);
+                return;
+            }
+        };
+        if self
+            .shared
+            .config
+            .vote_account_filter
+            .as_ref()
+            .is_some_and(|vote_account_filter| !vote_account_filter.contains(&vote_account_address))
+        {
+            return;
+        }
+
+        let Some(slot) = vote.slots.last().copied() else {
+            return;
+        };
+        let pending_vote = PendingVote {
+            vote,
+            vote_account_address,
+            signature,
+            slot,
+            received_at: Instant::now(),
+        };
+        let hold = self.shared.config.pending_vote_timeout.is_some()
+            && (self.pending_validators.contains(&vote_account_address)
+                || !self.shared.slot_ancestors.read().unwrap().contains(slot));
+        if !hold {
+            self.dispatch_vote(pending_vote).await;
+            return;
+        }
+        if self.pending_votes.len() == MAX_PENDING_VOTES {
+            if let Some(oldest) = self.pending_votes.pop_front() {
+                debug!(
+                    "Too many pending votes, processing {} without waiting for slot {}",
+                    oldest.signature, oldest.slot
+                );
+                self.dispatch_vote(oldest).await;
+            }
+        }
+        self.pending_validators.insert(vote_account_address);
+        self.pending_votes.push_back(pending_vote);
+    }
+
+    /// Processes the held votes whose slots have since been observed, and those held for longer
+    /// than the pending vote timeout, in the order they were received. Should be called
+    /// periodically, as a stalled feed may produce no new slots to release them
+    pub async fn release_pending_votes(&mut self, now: Instant) {
+        if self.pending_votes.is_empty() {
+            return;
+        }
+        let timeout = self.shared.config.pending_vote_timeout.unwrap_or_default();
+        let mut released = vec![];
+        self.pending_validators.clear();
+        {
+            let slot_ancestors = self.shared.slot_ancestors.read().unwrap();
+            for pending_vote in mem::take(&mut self.pending_votes) {
+                let observed = slot_ancestors.contains(pending_vote.slot);
+                let expired = now.duration_since(pending_vote.received_at) >= timeout;
+                if self
+                    .pending_validators
+                    .contains(&pending_vote.vote_account_address)
+                    || !(observed || expired)
+                {
+                    self.pending_validators
+                        .insert(pending_vote.vote_account_address);
+                    self.pending_votes.push_back(pending_vote);
+                    continue;
+                }
+                if !observed {
+                    debug!(
+                        "Slot {} not observed within the pending vote timeout, processing {} \
+                        without a lockout check",
+                        pending_vote.slot, pending_vote.signature
+                    );
+                }
+                released.push(pending_vote);
+            }
+        }
+        for pending_vote in released {
+            self.dispatch_vote(pending_vote).await;
+        }
+    }
+
+    /// Processes every held vote, whether or not its slot has been observed
+    pub async fn flush_pending_votes(&mut self) {
+        self.pending_validators.clear();
+        for pending_vote in mem::take(&mut self.pending_votes) {
+            self.dispatch_vote(pending_vote).await;
+        }
+    }
+
+    async fn dispatch_vote(&self, pending_vote: PendingVote) {
+        self.shard(&pending_vote.vote_account_address)
+            .send(shard::Request::Vote {
+                vote: pending_vote.vote,
+                vote_account_address: pending_vote.vote_account_address,
+                signature: pending_vote.signature,
+            })
+            .await;
+    }
+
+    /// Saves every tower to the configured tower snapshot, if any, once every vote queued has
+    /// been processed
+    pub async fn save_tower_snapshot(&self) {
+        let Some(tower_snapshot) = &self.shared.config.tower_snapshot else {
+            return;
+        };
+        let mut saved_towers = vec![];
+        for shard in &self.shards {
+            let (reply, result) = oneshot::channel();
+            shard.send(shard::Request::SaveTowers(reply)).await;
+            saved_towers.extend(result.await.unwrap_or_default());
+        }
+        write_saved_towers(
+            tower_snapshot,
+            saved_towers,
+            self.shared.config.max_lockout_history,
+            Utc::now().timestamp(),
+        )
+        .unwrap_or_else(|err| error!("Unable to write {}: {}", tower_snapshot.display(), err));
+    }
+}
+
+/// A validator's root slot and how many slots it lags the median root slot by
+struct LaggingRoot {
+    vote_account_address: Pubkey,
+    root_slot: Slot,
+    slots_behind: Slot,
+    root_advancement_rate: Option<f64>,
+}
+
+/// The median of the root slots in `roots`, with the validators whose roots lag it by more than
+/// `max_root_lag` slots, furthest behind first and keeping the first `TOP_OFFENDERS`. A validator
+/// stuck on a minority fork stops rooting slots without violating its lockouts
+fn lagging_roots(
+    roots: &[(Pubkey, Slot, Option<f64>)],
+    max_root_lag: Slot,
+) -> (Option<Slot>, usize, Vec<LaggingRoot>) {
+    let mut root_slots = roots
+        .iter()
+        .map(|(_, root_slot, _)| *root_slot)
+        .collect::<Vec<_>>();
+    root_slots.sort_unstable();
+    let Some(median_root_slot) = root_slots.get(root_slots.len() / 2).copied() else {
+        return (None, 0, vec![]);
+    };
+    let mut lagging = roots
+        .iter()
+        .filter(|(_, root_slot, _)| median_root_slot.saturating_sub(*root_slot) > max_root_lag)
+        .map(
+            |(vote_account_address, root_slot, root_advancement_rate)| LaggingRoot {
+                vote_account_address: *vote_account_address,
+                root_slot: *root_slot,
+                slots_behind: median_root_slot - root_slot,
+                root_advancement_rate: *root_advancement_rate,
+            },
+        )
+        .collect::<Vec<_>>();
+    let lagging_count = lagging.len();
+    lagging.sort_unstable_by(|a, b| {
+        b.slots_behind
+            .cmp(&a.slots_behind)
+            .then(a.vote_account_address.cmp(&b.vote_account_address))
+    });
+    lagging.truncate(shard::TOP_OFFENDERS);
+    (Some(median_root_slot), lagging_count, lagging)
+}
+
+/// The shard owning the tower of `vote_account_address`, out of `shards`
+fn shard_index(vote_account_address: &Pubkey, shards: usize) -> usize {
+    let bytes = vote_account_address.to_bytes();
+    (u64::from_le_bytes(bytes[..8].try_into().unwrap()) % shards as u64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rpc_vote(vote_pubkey: &str, signature: &str) -> Vote {
+        Vote {
+            vote_pubkey: vote_pubkey.to_string(),
+            slots: vec![1],
+            hash: String::new(),
+            timestamp: None,
+            signature: signature.to_string(),
+            root: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_vote_ids() {
+        let vote_account_address = Pubkey::new_unique();
+        let signature = Signature::new_unique();
+        assert_eq!(
+            parse_vote_ids(&rpc_vote(
+                &vote_account_address.to_string(),
+                &signature.to_string()
+            )),
+            Ok((vote_account_address, signature))
+        );
+
+        assert!(parse_vote_ids(&rpc_vote("not a pubkey", &signature.to_string())).is_err());
+        assert!(parse_vote_ids(&rpc_vote(&vote_account_address.to_string(), "0OIl")).is_err());
+    }
+
+    fn new_votalizer(config: VotalizerConfig) -> Votalizer {
+        Votalizer::new(
+            VotalizerConfig {
+                write_text_incident: false,
+                ..config
+            },
+            Notifier::new(NotifierConfig::default()),
+            Arc::default(),
+            Statsd::new(None, None).unwrap(),
+        )
+    }
+
+    fn slot_info(slot: Slot, parent: Slot) -> SlotInfo {
+        SlotInfo {
+            slot,
+            parent,
+            root: 0,
+        }
+    }
+
+    #[test]
+    fn test_remove_expired_incidents() {
+        let incident_dir = std::env::temp_dir().join(format!("incidents-{}", Pubkey::new_unique()));
+        fs::create_dir_all(&incident_dir).unwrap();
+        for filename in ["incident-a-b.log", "incident-a-b.json", "other.log"] {
+            fs::write(incident_dir.join(filename), "").unwrap();
+        }
+        let config = VotalizerConfig {
+            incident_dir: incident_dir.clone(),
+            incident_retention: Some(Duration::from_secs(86400)),
+            ..VotalizerConfig::default()
+        };
+        assert_eq!(config.remove_expired_incidents(SystemTime::now()), 0);
+        assert_eq!(
+            config.remove_expired_incidents(SystemTime::now() + Duration::from_secs(2 * 86400)),
+            2
+        );
+        assert!(!incident_dir.join("incident-a-b.log").exists());
+        assert!(incident_dir.join("other.log").exists());
+
+        // A named instance only removes its own incident files
+        for filename in ["incident-a-b.log", "mainnet-incident-a-b.log"] {
+            fs::write(incident_dir.join(filename), "").unwrap();
+        }
+        let config = VotalizerConfig {
+            instance_name: Some("mainnet".to_string()),
+            ..config
+        };
+        assert_eq!(
+            config.remove_expired_incidents(SystemTime::now() + Duration::from_secs(2 * 86400)),
+            1
+        );
+        assert!(incident_dir.join("incident-a-b.log").exists());
+        fs::remove_dir_all(&incident_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_process_lockout_violation() {
+        let mut votalizer = new_votalizer(VotalizerConfig::default());
+        let vote_account_address = Pubkey::new_unique();
+        let vote = |slot| {
+            let mut vote = rpc_vote(
+                &vote_account_address.to_string(),
+                &Signature::new_unique().to_string(),
+            );
+            vote.slots = vec![slot];
+            vote
+        };
+
+        for slot in 1..=40 {
+            votalizer.process_slot(&slot_info(slot, slot - 1)).await;
+            votalizer.process_vote(vote(slot)).await;
+        }
+        assert_eq!(votalizer.processed_vote_count().await, 40);
+        assert_eq!(votalizer.incident_count().await, 0);
+
+        // Slot 45 forks off at slot 35, which the validator is still locked out of abandoning
+        votalizer.process_slot(&slot_info(45, 35)).await;
+        votalizer.process_vote(vote(45)).await;
+        assert_eq!(votalizer.incident_count().await, 1);
+        assert_eq!(
+            votalizer
+                .with_tower(&vote_account_address, Tower::last_voted_slot)
+                .await,
+            Some(Some(45))
+        );
+        assert_eq!(
+            votalizer
+                .with_tower(&vote_account_address, Tower::incident_count)
+                .await,
+            Some(1)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unordered_vote_slots() {
+        let mut votalizer = new_votalizer(VotalizerConfig::default());
+        let vote_account_address = Pubkey::new_unique();
+        for slot in 1..=3 {
+            votalizer.process_slot(&slot_info(slot, slot - 1)).await;
+        }
+        for slots in [vec![1, 1, 3], vec![3, 2]] {
+            let mut vote = rpc_vote(
+                &vote_account_address.to_string(),
+                &Signature::new_unique().to_string(),
+            );
+            vote.slots = slots;
+            votalizer.process_vote(vote).await;
+        }
+        let mut vote = rpc_vote(
+            &vote_account_address.to_string(),
+            &Signature::new_unique().to_string(),
+        );
+        vote.slots = vec![2, 3];
+        vote.root = Some(2);
+        votalizer.process_vote(vote).await;
+
+        // Rejected without tracking the validator
+        assert_eq!(votalizer.processed_vote_count().await, 0);
+        assert_eq!(
+            votalizer
+                .with_tower(&vote_account_address, Tower::last_voted_slot)
+                .await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pending_votes() {
+        let mut votalizer = new_votalizer(VotalizerConfig::default());
+        let vote_account_address = Pubkey::new_unique();
+        let vote = |slot| {
+            let mut vote = rpc_vote(
+                &vote_account_address.to_string(),
+                &Signature::new_unique().to_string(),
+            );
+            vote.slots = vec![slot];
+            vote
+        };
+
+        for slot in 1..=40 {
+            votalizer.process_slot(&slot_info(slot, slot - 1)).await;
+            votalizer.process_vote(vote(slot)).await;
+        }
+
+        // The votes arrive before their slots, and are held
+        votalizer.process_vote(vote(45)).await;
+        votalizer.process_vote(vote(46)).await;
+        assert_eq!(votalizer.processed_vote_count().await, 40);
+
+        // Slot 45 releases its vote, which is still checked, but not the vote held behind it
+        votalizer.process_slot(&slot_info(45, 35)).await;
+        assert_eq!(votalizer.processed_vote_count().await, 41);
+        assert_eq!(votalizer.incident_count().await, 1);
+
+        // Slot 46 is never observed
+        votalizer
+            .release_pending_votes(Instant::now() + Duration::from_secs(31))
+            .await;
+        assert_eq!(votalizer.processed_vote_count().await, 42);
+        assert_eq!(
+            votalizer
+                .with_tower(&vote_account_address, Tower::last_voted_slot)
+                .await,
+            Some(Some(46))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_notification_lag() {
+        let now = Instant::now();
+        let mut votalizer = new_votalizer(VotalizerConfig::default());
+        // Without a maximum lag, late notifications are still processed
+        assert!(votalizer.check_notification_lag(now, now + Duration::from_secs(60)));
+        assert_eq!(votalizer.max_notification_lag, Duration::from_secs(60));
+
+        let mut votalizer = new_votalizer(VotalizerConfig {
+            max_notification_lag: Some(Duration::from_secs(5)),
+            ..VotalizerConfig::default()
+        });
+        assert!(votalizer.check_notification_lag(now, now + Duration::from_secs(5)));
+        assert!(!votalizer.check_notification_lag(now, now + Duration::from_secs(6)));
+        assert!(!votalizer.check_notification_lag(now, now + Duration::from_secs(7)));
+        assert!(votalizer.falling_behind.is_some());
+        assert!(votalizer
+            .check_notification_lag(now + Duration::from_secs(7), now + Duration::from_secs(7)));
+        assert!(votalizer.falling_behind.is_none());
+        assert_eq!(votalizer.dropped_notifications, 2);
+
+        // The maximum lag is reported once
+        let (status_report, _) = votalizer.status_report(now).await;
+        assert!(status_report.contains(", notification lag max 7.0s, 2 notifications dropped"));
+        assert_eq!(votalizer.max_notification_lag, Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_process_votes_across_shards() {
+        let mut votalizer = new_votalizer(VotalizerConfig::default());
+        let vote_account_addresses = (0..8).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let vote = |vote_account_address: &Pubkey, slot| {
+            let mut vote = rpc_vote(
+                &vote_account_address.to_string(),
+                &Signature::new_unique().to_string(),
+            );
+            vote.slots = vec![slot];
+            vote
+        };
+
+        for slot in 1..=40 {
+            votalizer.process_slot(&slot_info(slot, slot - 1)).await;
+            for vote_account_address in &vote_account_addresses {
+                votalizer
+                    .process_vote(vote(vote_account_address, slot))
+                    .await;
+            }
+        }
+        votalizer.process_slot(&slot_info(45, 35)).await;
+        votalizer
+            .process_vote(vote(&vote_account_addresses[0], 45))
+            .await;
+
+        assert_eq!(votalizer.processed_vote_count().await, 8 * 40 + 1);
+        assert_eq!(votalizer.incident_count().await, 1);
+        for vote_account_address in &vote_account_addresses[1..] {
+            assert_eq!(
+                votalizer
+                    .with_tower(vote_account_address, Tower::last_voted_slot)
+                    .await,
+                Some(Some(40))
+            );
+        }
+    }
+
+    #[test]
+    fn test_lagging_roots() {
+        assert_eq!(lagging_roots(&[], 10).0, None);
+
+        let vote_account_addresses = (0..4).map(|_| Pubkey::new_unique()).collect::<Vec<_>>();
+        let roots = [
+            (vote_account_addresses[0], 100, Some(2.)),
+            (vote_account_addresses[1], 101, Some(2.)),
+            (vote_account_addresses[2], 50, Some(0.)),
+            (vote_account_addresses[3], 95, None),
+        ];
+        let (median_root_slot, lagging_count, lagging) = lagging_roots(&roots, 10);
+        assert_eq!(median_root_slot, Some(100));
+        assert_eq!(lagging_count, 1);
+        assert_eq!(lagging[0].vote_account_address, vote_account_addresses[2]);
+        assert_eq!(lagging[0].slots_behind, 50);
+
+        let (_, lagging_count, lagging) = lagging_roots(&roots, 1);
+        assert_eq!(lagging_count, 2);
+        assert_eq!(lagging[1].vote_account_address, vote_account_addresses[3]);
+    }
+
+    #[tokio::test]
+    async fn test_epoch_counts() {
+        let mut votalizer = new_votalizer(VotalizerConfig {
+            epoch_schedule: Some(EpochSchedule::custom(32, 32, false)),
+            ..VotalizerConfig::default()
+        });
+        let vote_account_address = Pubkey::new_unique().to_string();
+        for slot in 1..=40 {
+            votalizer.process_slot(&slot_info(slot, slot - 1)).await;
+            assert_eq!(votalizer.epoch, Some(slot / 32));
+            let mut vote = rpc_vote(&vote_account_address, &Signature::new_unique().to_string());
+            vote.slots = vec![slot];
+            votalizer.process_vote(vote).await;
+        }
+        // The votes for slots 1 through 31 were processed in epoch 0
+        assert_eq!(votalizer.epoch_start_counts, (31, 0));
+        assert_eq!(votalizer.processed_vote_count().await, 40);
+    }
+
+    #[tokio::test]
+    async fn test_stall_detection() {
+        let mut votalizer = new_votalizer(VotalizerConfig {
+            stall_threshold: Some(Duration::from_secs(60)),
+            ..VotalizerConfig::default()
+        });
+        let start = Instant::now();
+        votalizer.process_slot(&slot_info(1, 0)).await;
+        votalizer.check_stall(start);
+        assert!(!votalizer.stalled());
+
+        // No new slot within the threshold
+        votalizer.check_stall(start + Duration::from_secs(61));
+        assert!(votalizer.stalled());
+        votalizer.process_slot(&slot_info(2, 1)).await;
+        assert!(!votalizer.stalled());
+
+        // Known slots resent
+        for _ in 1..STALL_DUPLICATE_SLOTS {
+            votalizer.process_slot(&slot_info(2, 1)).await;
+        }
+        assert!(!votalizer.stalled());
+        votalizer.process_slot(&slot_info(1, 0)).await;
+        assert!(votalizer.stalled());
+        votalizer.process_slot(&slot_info(3, 2)).await;
+        assert!(!votalizer.stalled());
+
+        // Disabled
+        let mut votalizer = new_votalizer(VotalizerConfig {
+            stall_threshold: None,
+            ..VotalizerConfig::default()
+        });
+        votalizer.check_stall(Instant::now() + Duration::from_secs(3600));
+        assert!(!votalizer.stalled());
+    }
+}
@@ -0,0 +1,100 @@
+use {
+    crate::error_reporting,
+    chrono::{SecondsFormat, Utc},
+    env_logger::{fmt::Color, WriteStyle},
+    log::Level,
+    serde_json::{json, Value},
+    std::{
+        env,
+        io::Write,
+        sync::atomic::{AtomicBool, Ordering},
+    },
+};
+
+/// Target of the records logged by `event!` in JSON mode
+pub const EVENT_TARGET: &str = "votalizer::event";
+
+static JSON: AtomicBool = AtomicBool::new(false);
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Configures logging with a default filter if RUST_LOG is not set. In JSON mode every record is
+/// written as a single JSON object per line. In text mode errors, which include incidents, are
+/// colored red and warnings yellow when logging to a terminal, unless NO_COLOR is set. Records
+/// are also passed on to Sentry, when enabled
+pub fn setup_with_default(filter: &str, json: bool) {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::new().default_filter_or(filter));
+    if !json {
+        if env::var_os("NO_COLOR").is_some_and(|no_color| !no_color.is_empty()) {
+            builder.write_style(WriteStyle::Never);
+        }
+        // Matches the `solana_logger` format, with the whole record colored by level
+        builder.format(|buf, record| {
+            let mut style = buf.style();
+            match record.level() {
+                Level::Error => {
+                    style.set_color(Color::Red).set_bold(true);
+                }
+                Level::Warn => {
+                    style.set_color(Color::Yellow);
+                }
+                _ => {}
+            }
+            writeln!(
+                buf,
+                "{}",
+                style.value(format_args!(
+                    "[{} {:<5} {}] {}",
+                    buf.timestamp_nanos(),
+                    record.level(),
+                    record.target(),
+                    record.args()
+                ))
+            )
+        });
+    } else {
+        JSON.store(true, Ordering::Relaxed);
+        builder.format(|buf, record| {
+            let mut entry = json!({
+                "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Nanos, true),
+                "level": record.level().as_str(),
+            });
+            let message = record.args().to_string();
+            match serde_json::from_str(&message) {
+                Ok(Value::Object(fields)) if record.target() == EVENT_TARGET => {
+                    entry.as_object_mut().unwrap().extend(fields);
+                }
+                _ => {
+                    entry["target"] = record.target().into();
+                    entry["message"] = message.into();
+                }
+            }
+            writeln!(buf, "{}", entry)
+        });
+    }
+
+    let logger = builder.build();
+    log::set_max_level(logger.filter());
+    log::set_boxed_logger(Box::new(error_reporting::wrap_logger(logger)))
+        .expect("logger already set");
+}
+
+/// Logs a structured event. In text mode only the formatted message is logged, while in JSON mode
+/// the event name, the message and `fields`, a JSON object, are
+macro_rules! event {
+    ($level:expr, $event:expr, $fields:expr, $($arg:tt)+) => {
+        if log::log_enabled!($level) {
+            if $crate::logging::is_json() {
+                let mut fields: serde_json::Value = $fields;
+                fields["event"] = $event.into();
+                fields["message"] = format!($($arg)+).into();
+                log::log!(target: $crate::logging::EVENT_TARGET, $level, "{}", fields);
+            } else {
+                log::log!($level, $($arg)+);
+            }
+        }
+    };
+}
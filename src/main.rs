@@ -1,24 +1,353 @@
 use {
-    crate::{notifier::*, tower::*},
-    clap::{crate_description, crate_name, crate_version, App, Arg},
-    futures_util::StreamExt,
-    itertools::Itertools,
+    chrono::{DateTime, Utc},
+    clap::{crate_description, crate_name, crate_version, App, Arg, SubCommand},
+    futures_util::{
+        future,
+        stream::{self, BoxStream},
+        StreamExt,
+    },
     log::*,
-    solana_clap_utils::input_validators::{is_url_or_moniker, normalize_to_url_if_moniker},
-    solana_client::nonblocking::pubsub_client::PubsubClient,
-    solana_sdk::{clock::Slot, pubkey::Pubkey, signature::Signature},
+    serde_json::Value,
+    solana_clap_utils::input_validators::{
+        is_parsable, is_pubkey, is_url, is_url_or_moniker, normalize_to_url_if_moniker,
+    },
+    solana_client::{nonblocking::rpc_client::RpcClient, rpc_response::SlotInfo},
+    solana_sdk::{
+        commitment_config::CommitmentLevel, native_token::sol_to_lamports, pubkey::Pubkey,
+    },
+    solana_vote_program::vote_state::MAX_LOCKOUT_HISTORY,
     std::{
-        collections::{BTreeMap, HashMap, HashSet},
-        fs::File,
+        collections::{btree_map::Entry, BTreeMap, HashMap, HashSet},
+        ffi::OsStr,
+        fs,
+        net::SocketAddr,
+        path::{Path, PathBuf},
+        sync::{atomic::Ordering, Arc},
         time::{Duration, Instant},
     },
+    tokio::sync::mpsc,
+    votalizer::{
+        commitment::CommittedSlots,
+        endpoint::Endpoints,
+        error_reporting, logging,
+        metrics::{self, Metrics, TowerQuery},
+        notifier::*,
+        pubsub::{self, Headers, PubsubClient, PubsubClientError, TlsConfig, UnsubscribeFn},
+        replay::{self, PubsubNotification, Recorder},
+        statsd::Statsd,
+        templates::MessageTemplates,
+        tower::{incident_file_prefix, Tower},
+        Votalizer, VotalizerConfig,
+    },
 };
 
-mod notifier;
-mod tower;
+/// How long `--self-test` waits for each subscription to produce a notification
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Extension of the marker written beside an incident report once `resend-incidents` has sent it
+const ACKNOWLEDGED_EXTENSION: &str = "acked";
+
+/// Subscribes to slots as they reach `commitment`
+async fn slot_subscribe(
+    pubsub_client: &PubsubClient,
+    commitment: CommitmentLevel,
+) -> Result<(BoxStream<'_, (Instant, SlotInfo)>, UnsubscribeFn), PubsubClientError> {
+    if commitment == CommitmentLevel::Processed {
+        return pubsub_client.slot_subscribe().await;
+    }
+    let (slot_updates, unsubscribe) = pubsub_client.slot_updates_subscribe().await?;
+    let mut committed_slots = CommittedSlots::new(commitment);
+    Ok((
+        slot_updates
+            .flat_map(move |(received_at, update)| {
+                stream::iter(
+                    committed_slots
+                        .process(&update)
+                        .into_iter()
+                        .map(move |slot_info| (received_at, slot_info)),
+                )
+            })
+            .boxed(),
+        unsubscribe,
+    ))
+}
+
+/// Explains a websocket connection the RPC provider rejected for its credentials
+fn auth_rejection_message(err: &PubsubClientError) -> Option<String> {
+    pubsub::auth_rejection(err).map(|status| {
+        format!(
+            "connection rejected with HTTP {}, check --ws-header and --ws-token",
+            status
+        )
+    })
+}
+
+/// Checks that `websocket_url` delivers both vote and slot notifications
+async fn self_test_endpoint(
+    websocket_url: &str,
+    ws_headers: &Headers,
+    tls_config: Option<&TlsConfig>,
+    commitment: CommitmentLevel,
+) -> Result<(), String> {
+    let pubsub_client = PubsubClient::new(websocket_url, ws_headers, tls_config)
+        .await
+        .map_err(|err| auth_rejection_message(&err).unwrap_or_else(|| err.to_string()))?;
+    let (mut votes, votes_unsubscribe) = pubsub_client
+        .vote_subscribe()
+        .await
+        .map_err(|err| err.to_string())?;
+    let (mut slots, slots_unsubscribe) = match slot_subscribe(&pubsub_client, commitment).await {
+        Ok(slot_subscription) => slot_subscription,
+        Err(err) => {
+            votes_unsubscribe().await;
+            return Err(err.to_string());
+        }
+    };
+
+    let (vote, slot_info) = tokio::join!(
+        tokio::time::timeout(SELF_TEST_TIMEOUT, votes.next()),
+        tokio::time::timeout(SELF_TEST_TIMEOUT, slots.next()),
+    );
+    slots_unsubscribe().await;
+    votes_unsubscribe().await;
+
+    let missing = [
+        ("vote", matches!(vote, Ok(Some(_)))),
+        ("slot", matches!(slot_info, Ok(Some(_)))),
+    ]
+    .iter()
+    .filter(|(_, received)| !received)
+    .map(|(kind, _)| *kind)
+    .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "no {} notification received within {}s",
+            missing.join(" or "),
+            SELF_TEST_TIMEOUT.as_secs()
+        ))
+    }
+}
+
+/// Requests for an immediate status report, sent with SIGUSR1. Never yields on other platforms
+#[cfg(unix)]
+fn status_dump_requests() -> BoxStream<'static, ()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    match signal(SignalKind::user_defined1()) {
+        Ok(signal) => stream::unfold(signal, |mut signal| async move {
+            signal.recv().await.map(|()| ((), signal))
+        })
+        .boxed(),
+        Err(err) => {
+            warn!(
+                "Unable to handle SIGUSR1, status dumps unavailable: {}",
+                err
+            );
+            stream::pending().boxed()
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn status_dump_requests() -> BoxStream<'static, ()> {
+    stream::pending().boxed()
+}
+
+fn is_positive_seconds(value: String) -> Result<(), String> {
+    match value.parse::<u64>() {
+        Ok(secs) if secs > 0 => Ok(()),
+        _ => Err(format!("invalid number of seconds: {}", value)),
+    }
+}
+
+/// Instance names prefix file names, so are limited to letters, digits, `-`, `_` and `.`
+fn is_instance_name(value: String) -> Result<(), String> {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "invalid instance name {:?}, expected letters, digits, '-', '_' or '.'",
+            value
+        ))
+    }
+}
+
+/// Reads validator labels from `path`, one vote account address and label per line separated by
+/// whitespace. Blank lines and lines starting with `#` are ignored
+fn read_labels_file(path: &str) -> Result<HashMap<Pubkey, String>, String> {
+    fs::read_to_string(path)
+        .map_err(|err| format!("Unable to read {}: {}", path, err))?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (vote_account_address, label) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("Missing label in {}: {}", path, line))?;
+            let vote_account_address = vote_account_address.parse::<Pubkey>().map_err(|err| {
+                format!(
+                    "Invalid vote account in {}: {}: {}",
+                    path, vote_account_address, err
+                )
+            })?;
+            Ok((vote_account_address, label.trim().to_string()))
+        })
+        .collect()
+}
+
+/// The title and severity score of an incident report written to `path`, in either format
+fn parse_incident_report(path: &Path, contents: &str) -> Result<(String, Option<u64>), String> {
+    if path.extension() == Some(OsStr::new("json")) {
+        let incident = serde_json::from_str::<Value>(contents).map_err(|err| err.to_string())?;
+        let kind = incident["kind"].as_str().ok_or("no incident kind")?;
+        Ok((kind.replace('_', " "), incident["severity"].as_u64()))
+    } else {
+        let (title, _) = contents
+            .lines()
+            .next()
+            .and_then(|line| line.split_once(':'))
+            .ok_or("no incident kind")?;
+        let severity = contents
+            .lines()
+            .find_map(|line| line.strip_prefix("severity: "))
+            .and_then(|severity| severity.parse().ok());
+        Ok((title.to_string(), severity))
+    }
+}
+
+/// Resends each incident report in `incident_dir` not yet acknowledged, then acknowledges it by
+/// writing an empty marker beside it so that it is not sent again. A report written in both
+/// formats is sent once, as text. Returns the number of reports sent and the number that could
+/// not be
+async fn resend_incidents(
+    notifier: &Notifier,
+    incident_dir: &Path,
+    instance_name: Option<&str>,
+    critical_severity: u64,
+) -> Result<(usize, usize), String> {
+    // Each report by its file stem, `<PREFIX><VOTE_ACCOUNT_ADDRESS>-<SIGNATURE>`
+    let prefix = incident_file_prefix(instance_name);
+    let mut reports = BTreeMap::<String, PathBuf>::new();
+    for entry in fs::read_dir(incident_dir)
+        .map_err(|err| format!("Unable to read {}: {}", incident_dir.display(), err))?
+    {
+        let path = entry.map_err(|err| err.to_string())?.path();
+        let (Some(stem), Some(extension)) = (
+            path.file_stem().and_then(OsStr::to_str),
+            path.extension().and_then(OsStr::to_str),
+        ) else {
+            continue;
+        };
+        if !stem.starts_with(&prefix) || !matches!(extension, "log" | "json") {
+            continue;
+        }
+        match reports.entry(stem.to_string()) {
+            Entry::Vacant(entry) => {
+                entry.insert(path);
+            }
+            Entry::Occupied(mut entry) if extension == "log" => {
+                entry.insert(path);
+            }
+            Entry::Occupied(_) => {}
+        }
+    }
+
+    let (mut sent, mut failed) = (0, 0);
+    for (stem, path) in reports {
+        let marker = path.with_extension(ACKNOWLEDGED_EXTENSION);
+        if marker.exists() {
+            continue;
+        }
+        let Some((vote_account_address, signature)) = stem
+            .strip_prefix(&prefix)
+            .and_then(|ids| ids.split_once('-'))
+            .and_then(|(vote_account_address, signature)| {
+                Some((vote_account_address.parse::<Pubkey>().ok()?, signature))
+            })
+        else {
+            warn!("Skipping {}, not named for a vote account", path.display());
+            continue;
+        };
+        let report = fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                parse_incident_report(&path, &contents).map(|parsed| (contents, parsed))
+            });
+        let (contents, (title, severity)) = match report {
+            Ok(report) => report,
+            Err(err) => {
+                warn!("Unable to read {}: {}", path.display(), err);
+                failed += 1;
+                continue;
+            }
+        };
+        let severity = if severity.is_some_and(|severity| severity >= critical_severity) {
+            Severity::Critical
+        } else {
+            Severity::Warning
+        };
+        // Written when the incident was detected
+        let detected_at = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map_or_else(|_| Utc::now(), DateTime::<Utc>::from);
+        let msg = format!(
+            "{}: {} detected [{}] (resent)",
+            vote_account_address, title, signature
+        );
+        let filename = path.file_name().unwrap().to_string_lossy();
+        if notifier
+            .resend_incident_report(
+                &vote_account_address,
+                &msg,
+                severity,
+                detected_at,
+                &filename,
+                &contents,
+            )
+            .await
+        {
+            info!("Resent {}", path.display());
+            sent += 1;
+            fs::write(&marker, "")
+                .unwrap_or_else(|err| warn!("Unable to acknowledge {}: {}", marker.display(), err));
+        } else {
+            failed += 1;
+        }
+    }
+    Ok((sent, failed))
+}
+
+/// Reads vote account addresses from `path`, one per line. Blank lines and lines starting with
+/// `#` are ignored
+fn read_vote_accounts_file(path: &str) -> Result<Vec<Pubkey>, String> {
+    fs::read_to_string(path)
+        .map_err(|err| format!("Unable to read {}: {}", path, err))?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.parse::<Pubkey>()
+                .map_err(|err| format!("Invalid vote account in {}: {}: {}", path, line, err))
+        })
+        .collect()
+}
+
+/// The commit and time votalizer was built from, captured by build.rs
+const BUILD_INFO: &str = concat!(
+    "git ",
+    env!("VOTALIZER_GIT_HASH"),
+    ", built ",
+    env!("VOTALIZER_BUILD_TIMESTAMP")
+);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let default_max_lockout_history = MAX_LOCKOUT_HISTORY.to_string();
     let matches = App::new(crate_name!())
         .about(crate_description!())
         .version(crate_version!())
@@ -28,170 +357,1231 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .long("url")
                 .value_name("URL")
                 .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
                 .validator(is_url_or_moniker)
                 .default_value("localhost")
-                .help("JSON RPC URL for the cluster"),
+                .help(
+                    "JSON RPC URL for the cluster. May be specified multiple times to fail over \
+                    between RPC nodes",
+                ),
+        )
+        .arg(
+            Arg::with_name("ws_header")
+                .long("ws-header")
+                .value_name("KEY=VALUE")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(|header| pubsub::parse_header(&header).map(|_| ()))
+                .help(
+                    "Send this HTTP header when connecting to the websocket, such as an RPC \
+                    provider's API key. May be specified multiple times",
+                ),
+        )
+        .arg(
+            Arg::with_name("ws_token")
+                .long("ws-token")
+                .value_name("TOKEN")
+                .takes_value(true)
+                .validator(|token| pubsub::bearer_token_header(&token).map(|_| ()))
+                .help("Authenticate to the websocket with this bearer token"),
         )
+        .arg(
+            Arg::with_name("notifier_config")
+                .long("notifier-config")
+                .value_name("FILE")
+                .takes_value(true)
+                .help(
+                    "TOML file describing the notifier backends \
+                    [default: configure from environment variables]",
+                ),
+        )
+        .arg(
+            Arg::with_name("message_templates")
+                .long("message-templates")
+                .value_name("FILE")
+                .takes_value(true)
+                .validator(|path| MessageTemplates::load(Path::new(&path)).map(|_| ()))
+                .help(
+                    "TOML file of templates for the wording of incident and status \
+                    notifications [default: built-in wording]",
+                ),
+        )
+        .arg(
+            Arg::with_name("notifier_proxy")
+                .long("notifier-proxy")
+                .value_name("URL")
+                .takes_value(true)
+                .validator(is_url)
+                .help(
+                    "Send notifier requests through this proxy, except to hosts listed in \
+                    NO_PROXY [default: HTTPS_PROXY or HTTP_PROXY]",
+                ),
+        )
+        .arg(
+            Arg::with_name("ca_bundle")
+                .long("ca-bundle")
+                .value_name("FILE")
+                .takes_value(true)
+                .validator(|path| load_ca_bundle(Path::new(&path)).map(|_| ()))
+                .help(
+                    "PEM file of root certificates to trust for notifier requests, in addition \
+                    to the system's, and for the websocket connection, in addition to the \
+                    bundled Mozilla roots",
+                ),
+        )
+        .arg(
+            Arg::with_name("danger_accept_invalid_certs")
+                .long("danger-accept-invalid-certs")
+                .takes_value(false)
+                .help(
+                    "Accept any TLS certificate for notifier requests and the websocket \
+                    connection, even expired, self-signed or for another host. Leaves \
+                    notifications and votes open to interception and forgery",
+                ),
+        )
+        .arg(
+            Arg::with_name("build_info")
+                .long("build-info")
+                .takes_value(false)
+                .help("Print the version, git commit and build time, then exit"),
+        )
+        .arg(
+            Arg::with_name("self_test")
+                .long("self-test")
+                .takes_value(false)
+                .conflicts_with("replay")
+                .help(
+                    "Check that every URL delivers vote and slot notifications and that every \
+                    notifier backend is reachable, then exit",
+                ),
+        )
+        .arg(
+            Arg::with_name("validator_info")
+                .long("validator-info")
+                .takes_value(false)
+                .help(
+                    "Look up the node identity and stake of each validator with the first RPC \
+                    URL, for incident reports and the status report",
+                ),
+        )
+        .arg(
+            Arg::with_name("min_stake")
+                .long("min-stake")
+                .value_name("SOL")
+                .takes_value(true)
+                .validator(is_parsable::<f64>)
+                .help(
+                    "Ignore validators with less stake than this, looked up as with \
+                    --validator-info",
+                ),
+        )
+        .arg(
+            Arg::with_name("list_backends")
+                .long("list-backends")
+                .takes_value(false)
+                .help(
+                    "Print the notifier backends configured and their severity filters, then \
+                    exit without connecting",
+                ),
+        )
+        .arg(
+            Arg::with_name("delinquency_check")
+                .long("delinquency-check")
+                .takes_value(false)
+                .conflicts_with("replay")
+                .help(
+                    "Query the vote accounts with the first RPC URL periodically, and notify \
+                    when a validator becomes delinquent or stops being delinquent",
+                ),
+        )
+        .arg(
+            Arg::with_name("delinquency_check_interval")
+                .long("delinquency-check-interval")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .default_value("60")
+                .help("Seconds between vote account queries for --delinquency-check"),
+        )
+        .arg(
+            Arg::with_name("verify_notifiers")
+                .long("verify-notifiers")
+                .takes_value(false)
+                .help("Send a test message to every notifier backend at startup"),
+        )
+        .arg(
+            Arg::with_name("digest_hour")
+                .long("digest-hour")
+                .value_name("HOUR")
+                .takes_value(true)
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(hour) if hour < 24 => Ok(()),
+                    _ => Err(format!("invalid hour: {}", value)),
+                })
+                .help(
+                    "Send a daily digest notification at this UTC hour (0-23) \
+                    instead of a status notification every 12 hours",
+                ),
+        )
+        .arg(
+            Arg::with_name("incident_format")
+                .long("incident-format")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["text", "json", "both"])
+                .default_value("text")
+                .help("Format of the incident files written when a lockout violation is detected"),
+        )
+        .arg(
+            Arg::with_name("incident_cooldown")
+                .long("incident-cooldown")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .default_value("0")
+                .help(
+                    "After reporting an incident for a validator, suppress further incidents \
+                    for it for this many seconds",
+                ),
+        )
+        .arg(
+            Arg::with_name("tower_idle_timeout")
+                .long("tower-idle-timeout")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .default_value("86400")
+                .help(
+                    "Forget the tower of a validator that has not voted for this many seconds. \
+                    0 keeps every tower",
+                ),
+        )
+        .arg(
+            Arg::with_name("pending_vote_timeout")
+                .long("pending-vote-timeout")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .default_value("30")
+                .help(
+                    "Hold a vote for a slot not yet observed for up to this many seconds, so \
+                    that it can be checked against the validator's lockouts once the slot \
+                    arrives. 0 processes such votes without checking them",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_notification_lag")
+                .long("max-notification-lag")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_positive_seconds)
+                .help(
+                    "Drop notifications that waited longer than this many seconds to be \
+                    processed, so that processing catches up after a slowdown \
+                    [default: notifications are never dropped]",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_runtime")
+                .long("max-runtime")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .default_value("0")
+                .help(
+                    "Shut down cleanly after running for this many seconds, for a supervisor to \
+                    restart. 0 runs indefinitely",
+                ),
+        )
+        .arg(
+            Arg::with_name("liveness_threshold")
+                .long("liveness-threshold")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .default_value("60")
+                .help(
+                    "Notify when a validator that has voted before goes this long without \
+                    voting. 0 disables liveness monitoring",
+                ),
+        )
+        .arg(
+            Arg::with_name("stall_threshold")
+                .long("stall-threshold")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .default_value("60")
+                .help(
+                    "Notify when the RPC node produces no new slot for this many seconds. \
+                    0 disables stall detection",
+                ),
+        )
+        .arg(
+            Arg::with_name("timestamp_tolerance")
+                .long("timestamp-tolerance")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<i64>)
+                .default_value("120")
+                .help(
+                    "Notify when a vote timestamp differs from the local clock by more than \
+                    this many seconds",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_vote_latency")
+                .long("max-vote-latency")
+                .value_name("SLOTS")
+                .takes_value(true)
+                .validator(is_parsable::<f64>)
+                .help(
+                    "Notify when a validator's votes are received, on average, more than this \
+                    many slots after the current cluster slot",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_vote_gap")
+                .long("max-vote-gap")
+                .value_name("SLOTS")
+                .takes_value(true)
+                .validator(is_parsable::<usize>)
+                .help(
+                    "Notify when a validator skips voting on more than this many slots of its \
+                    fork between consecutive votes",
+                ),
+        )
+        .arg(
+            Arg::with_name("tower_reset_depth")
+                .long("tower-reset-depth")
+                .value_name("DEPTH")
+                .takes_value(true)
+                .validator(is_parsable::<usize>)
+                .default_value("16")
+                .help(
+                    "Report a vote that expires every vote of a tower at least this many votes \
+                    deep as a tower reset incident. 0 disables the check",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_root_lag")
+                .long("max-root-lag")
+                .value_name("SLOTS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .help(
+                    "List the validators whose root slot lags the median root slot of the \
+                    tracked validators by more than this many slots in the status report",
+                ),
+        )
+        .arg(
+            Arg::with_name("critical_severity")
+                .long("critical-severity")
+                .value_name("SCORE")
+                .takes_value(true)
+                .default_value("8")
+                .validator(is_parsable::<u64>)
+                .help(
+                    "Notify incidents with at least this severity score as critical, and the \
+                    rest as warnings",
+                ),
+        )
+        .arg(
+            Arg::with_name("vote_workers")
+                .long("vote-workers")
+                .value_name("COUNT")
+                .takes_value(true)
+                .default_value("1")
+                .validator(|value| match value.parse::<usize>() {
+                    Ok(count) if count > 0 => Ok(()),
+                    _ => Err(format!("invalid number of workers: {}", value)),
+                })
+                .help(
+                    "Process votes with this many workers concurrently, each tracking the \
+                    towers of a subset of validators",
+                ),
+        )
+        .arg(
+            Arg::with_name("tower_snapshot")
+                .long("tower-snapshot")
+                .value_name("FILE")
+                .takes_value(true)
+                .help(
+                    "Periodically save validator towers to this file, and restore them from it \
+                    at startup, so that lockout checks resume immediately after a restart",
+                ),
+        )
+        .arg(
+            Arg::with_name("tower_snapshot_max_age")
+                .long("tower-snapshot-max-age")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<i64>)
+                .default_value("600")
+                .help("Ignore a tower snapshot saved longer ago than this at startup"),
+        )
+        .arg(
+            Arg::with_name("max_reconnect_delay")
+                .long("max-reconnect-delay")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .default_value("60")
+                .help("Maximum delay between attempts to reconnect to the websocket"),
+        )
+        .arg(
+            Arg::with_name("vote_account")
+                .long("vote-account")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(is_pubkey)
+                .help(
+                    "Only monitor this vote account. May be specified multiple times \
+                    [default: monitor every vote account]",
+                ),
+        )
+        .arg(
+            Arg::with_name("vote_accounts_file")
+                .long("vote-accounts-file")
+                .value_name("FILE")
+                .takes_value(true)
+                .help("Only monitor the vote accounts listed in this file, one per line"),
+        )
+        .arg(
+            Arg::with_name("labels")
+                .long("labels")
+                .value_name("FILE")
+                .takes_value(true)
+                .help(
+                    "Name validators in incident reports and notifications with the labels in \
+                    this file, one vote account address and label per line",
+                ),
+        )
+        .arg(
+            Arg::with_name("explain")
+                .long("explain")
+                .value_name("PUBKEY")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(is_pubkey)
+                .help(
+                    "Log each step of the lockout check for votes from this vote account. May be \
+                    specified multiple times",
+                ),
+        )
+        .arg(
+            Arg::with_name("metrics_addr")
+                .long("metrics-addr")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .validator(is_parsable::<SocketAddr>)
+                .help(
+                    "Serve Prometheus metrics at http://HOST:PORT/metrics, and a health check \
+                    at http://HOST:PORT/healthz",
+                ),
+        )
+        .arg(
+            Arg::with_name("health_threshold")
+                .long("health-threshold")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .default_value("60")
+                .help(
+                    "Fail the health check when the RPC node produces no new slot for this \
+                    many seconds",
+                ),
+        )
+        .arg(
+            Arg::with_name("recent_incidents")
+                .long("recent-incidents")
+                .value_name("COUNT")
+                .takes_value(true)
+                .validator(is_parsable::<usize>)
+                .default_value("100")
+                .help(
+                    "Number of recent incidents served as JSON at http://HOST:PORT/incidents \
+                    by the metrics server",
+                ),
+        )
+        .arg(
+            Arg::with_name("statsd_addr")
+                .long("statsd-addr")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .validator(is_parsable::<SocketAddr>)
+                .help("Push metrics to the StatsD aggregator at HOST:PORT over UDP"),
+        )
+        .arg(
+            Arg::with_name("commitment")
+                .long("commitment")
+                .value_name("COMMITMENT_LEVEL")
+                .takes_value(true)
+                .possible_values(&["processed", "confirmed", "finalized"])
+                .default_value("processed")
+                .help("Only track slots once they reach this commitment level"),
+        )
+        .arg(
+            Arg::with_name("incident_dir")
+                .long("incident-dir")
+                .value_name("PATH")
+                .takes_value(true)
+                .default_value(".")
+                .help("Directory to write incident files to, created if it does not exist"),
+        )
+        .arg(
+            Arg::with_name("instance_name")
+                .long("instance-name")
+                .value_name("NAME")
+                .takes_value(true)
+                .validator(is_instance_name)
+                .help(
+                    "Name this votalizer to run several on one host: its incident files and \
+                    tower snapshot are prefixed with the name, its metrics labeled with it and \
+                    its notifications attributed to it",
+                ),
+        )
+        .arg(
+            Arg::with_name("incident_retention_days")
+                .long("incident-retention-days")
+                .value_name("DAYS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .help(
+                    "Remove incident files from the incident directory once they are this many \
+                    days old, at startup and hourly [default: keep incident files forever]",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_tracked_ancestors")
+                .long("max-tracked-ancestors")
+                .value_name("COUNT")
+                .takes_value(true)
+                .validator(is_parsable::<usize>)
+                .default_value("10240")
+                .help("Maximum number of ancestors tracked for each slot"),
+        )
+        .arg(
+            Arg::with_name("max_lockout_history")
+                .long("max-lockout-history")
+                .value_name("DEPTH")
+                .takes_value(true)
+                .default_value(&default_max_lockout_history)
+                .validator(|value| match value.parse::<usize>() {
+                    Ok(depth) if depth > 0 => Ok(()),
+                    _ => Err(format!("invalid tower depth: {}", value)),
+                })
+                .help(
+                    "Number of votes in a validator's tower before the oldest is rooted, to \
+                    monitor clusters running different lockout parameters",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_incident_history")
+                .long("max-incident-history")
+                .value_name("COUNT")
+                .takes_value(true)
+                .validator(is_parsable::<usize>)
+                .default_value("10")
+                .help("Maximum number of recent incidents remembered for each validator"),
+        )
+        .arg(
+            Arg::with_name("max_tracked_slots")
+                .long("max-tracked-slots")
+                .value_name("COUNT")
+                .takes_value(true)
+                .validator(is_parsable::<usize>)
+                .default_value("10240")
+                .help("Maximum number of recent slots to track ancestors for"),
+        )
+        .arg(
+            Arg::with_name("status_interval_secs")
+                .long("status-interval-secs")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_positive_seconds)
+                .default_value("30")
+                .help("Log a status report this often"),
+        )
+        .arg(
+            Arg::with_name("status_notify_interval_secs")
+                .long("status-notify-interval-secs")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .validator(is_positive_seconds)
+                .default_value("43200")
+                .help(
+                    "Send a status report to the notifier this often, unless --digest-hour \
+                    is given",
+                ),
+        )
+        .arg(
+            Arg::with_name("notify_status_dump")
+                .long("notify-status-dump")
+                .takes_value(false)
+                .help(
+                    "Also send the status report and notifier backend health logged on SIGUSR1 \
+                    to the notifier",
+                ),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .value_name("FILE")
+                .takes_value(true)
+                .conflicts_with("tower_snapshot")
+                .help(
+                    "Process the slot and vote notifications recorded in this file, in the \
+                    JSON-RPC form sent over the websocket, one per line, instead of \
+                    connecting to the cluster",
+                ),
+        )
+        .arg(
+            Arg::with_name("replay_realtime")
+                .long("replay-realtime")
+                .takes_value(false)
+                .requires("replay")
+                .help("Replay notifications as far apart as they were recorded by --record"),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .value_name("FILE")
+                .takes_value(true)
+                .help(
+                    "Append every slot and vote notification received to this file, \
+                    for later use with --replay",
+                ),
+        )
+        .arg(
+            Arg::with_name("log_format")
+                .long("log-format")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help("Format of log output"),
+        )
+        .subcommand(SubCommand::with_name("resend-incidents").about(
+            "Resend the incident reports in --incident-dir not yet resent through the \
+                    notifier backends, for example after fixing a broken webhook, then exit",
+        ))
         .get_matches();
 
-    let json_rpc_url = normalize_to_url_if_moniker(matches.value_of("json_rpc_url").unwrap());
-    let websocket_url = solana_cli_config::Config::compute_websocket_url(&json_rpc_url);
+    if matches.is_present("build_info") {
+        println!("{} {} ({})", crate_name!(), crate_version!(), BUILD_INFO);
+        return Ok(());
+    }
+
+    let websocket_urls = matches
+        .values_of("json_rpc_url")
+        .unwrap()
+        .map(|json_rpc_url| {
+            solana_cli_config::Config::compute_websocket_url(&normalize_to_url_if_moniker(
+                json_rpc_url,
+            ))
+        })
+        .collect::<Vec<_>>();
+    let mut ws_headers = matches
+        .values_of("ws_header")
+        .into_iter()
+        .flatten()
+        .map(|header| pubsub::parse_header(header).unwrap())
+        .collect::<Headers>();
+    if let Some(token) = matches.value_of("ws_token") {
+        ws_headers.push(pubsub::bearer_token_header(token).unwrap());
+    }
+    let incident_format = matches.value_of("incident_format").unwrap();
+    let write_text_incident = incident_format != "json";
+    let write_json_incident = incident_format != "text";
+    let incident_cooldown = Duration::from_secs(
+        matches
+            .value_of("incident_cooldown")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap(),
+    );
+    let tower_idle_timeout = Some(matches.value_of("tower_idle_timeout").unwrap())
+        .map(|secs| secs.parse::<u64>().unwrap())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    let pending_vote_timeout = Some(matches.value_of("pending_vote_timeout").unwrap())
+        .map(|secs| secs.parse::<u64>().unwrap())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    let max_notification_lag = matches
+        .value_of("max_notification_lag")
+        .map(|secs| Duration::from_secs(secs.parse().unwrap()));
+    let replaying = matches.is_present("replay");
+    let vote_workers = matches
+        .value_of("vote_workers")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    // Replayed votes arrive far faster than they were cast
+    let liveness_threshold = Some(matches.value_of("liveness_threshold").unwrap())
+        .filter(|_| !replaying)
+        .map(|secs| secs.parse::<u64>().unwrap())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    // Only the order of replayed notifications is meaningful, not their timing
+    let stall_threshold = Some(matches.value_of("stall_threshold").unwrap())
+        .filter(|_| !replaying)
+        .map(|secs| secs.parse::<u64>().unwrap())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    let timestamp_tolerance = matches
+        .value_of("timestamp_tolerance")
+        .unwrap()
+        .parse::<i64>()
+        .unwrap();
+    let max_vote_latency = matches
+        .value_of("max_vote_latency")
+        .map(|slots| slots.parse::<f64>().unwrap());
+    let max_vote_gap = matches
+        .value_of("max_vote_gap")
+        .map(|slots| slots.parse::<usize>().unwrap());
+    let tower_reset_depth = matches
+        .value_of("tower_reset_depth")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    let max_root_lag = matches
+        .value_of("max_root_lag")
+        .map(|slots| slots.parse::<u64>().unwrap());
+    let critical_severity = matches
+        .value_of("critical_severity")
+        .unwrap()
+        .parse::<u64>()
+        .unwrap();
+    let max_reconnect_delay = Duration::from_secs(
+        matches
+            .value_of("max_reconnect_delay")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap(),
+    );
+    let commitment = matches
+        .value_of("commitment")
+        .unwrap()
+        .parse::<CommitmentLevel>()
+        .unwrap();
+    let mut vote_account_filter = matches.values_of("vote_account").map(|values| {
+        values
+            .map(|value| value.parse::<Pubkey>().unwrap())
+            .collect::<HashSet<_>>()
+    });
+    if let Some(path) = matches.value_of("vote_accounts_file") {
+        vote_account_filter
+            .get_or_insert_with(HashSet::new)
+            .extend(read_vote_accounts_file(path)?);
+    }
+    let labels = match matches.value_of("labels") {
+        Some(path) => read_labels_file(path)?,
+        None => HashMap::new(),
+    };
+    let explain = matches
+        .values_of("explain")
+        .map(|values| {
+            values
+                .map(|value| value.parse::<Pubkey>().unwrap())
+                .collect::<HashSet<_>>()
+        })
+        .unwrap_or_default();
+    let incident_dir = Path::new(matches.value_of("incident_dir").unwrap());
+    fs::create_dir_all(incident_dir)
+        .map_err(|err| format!("Unable to create {}: {}", incident_dir.display(), err))?;
+    let instance_name = matches.value_of("instance_name").map(str::to_string);
+    // Namespaced like the incident files, so that instances can share a snapshot directory
+    let tower_snapshot = matches.value_of("tower_snapshot").map(|path| {
+        let path = PathBuf::from(path);
+        match (&instance_name, path.file_name()) {
+            (Some(instance_name), Some(file_name)) => {
+                path.with_file_name(format!("{}-{}", instance_name, file_name.to_string_lossy()))
+            }
+            _ => path,
+        }
+    });
+    let tower_snapshot_max_age = matches
+        .value_of("tower_snapshot_max_age")
+        .unwrap()
+        .parse::<i64>()
+        .unwrap();
+
+    let status_interval = Duration::from_secs(
+        matches
+            .value_of("status_interval_secs")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap(),
+    );
+    let status_notify_interval = Duration::from_secs(
+        matches
+            .value_of("status_notify_interval_secs")
+            .unwrap()
+            .parse::<u64>()
+            .unwrap(),
+    );
+
+    logging::setup_with_default("info", matches.value_of("log_format") == Some("json"));
+    let sentry_guard = error_reporting::init();
+    info!("{} {} ({})", crate_name!(), crate_version!(), BUILD_INFO);
 
-    let notifier = Notifier::default();
-    solana_logger::setup_with_default("info");
+    let max_tracked_ancestors = matches
+        .value_of("max_tracked_ancestors")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    let max_tracked_slots = matches
+        .value_of("max_tracked_slots")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    let max_incident_history = matches
+        .value_of("max_incident_history")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    let max_lockout_history = matches
+        .value_of("max_lockout_history")
+        .unwrap()
+        .parse::<usize>()
+        .unwrap();
+    // Lockout checks need the validator's root slot, at least `max_lockout_history` votes back, to
+    // still be tracked
+    for (flag, value) in [
+        ("--max-tracked-ancestors", max_tracked_ancestors),
+        ("--max-tracked-slots", max_tracked_slots),
+    ] {
+        if value < max_lockout_history {
+            warn!(
+                "*** {} {} is less than the maximum lockout history of {} slots, \
+                most lockout checks will be skipped ***",
+                flag, value, max_lockout_history
+            );
+        }
+    }
 
-    info!("websocket URL: {}", websocket_url);
+    let mut notifier_config = match matches.value_of("notifier_config") {
+        Some(path) => NotifierConfig::load(Path::new(path))?,
+        // Keep replayed incidents out of the backends configured for live monitoring
+        None if replaying => NotifierConfig::stdout(),
+        None => NotifierConfig::from_env(),
+    };
+    if let Some(proxy) = matches.value_of("notifier_proxy") {
+        notifier_config = notifier_config.with_proxy(proxy.to_string());
+    }
+    if let Some(ca_bundle) = matches.value_of("ca_bundle") {
+        notifier_config = notifier_config.with_ca_bundle(PathBuf::from(ca_bundle));
+    }
+    if matches.is_present("danger_accept_invalid_certs") {
+        notifier_config = notifier_config.with_danger_accept_invalid_certs();
+        warn!(
+            "*** TLS certificate verification is disabled for the websocket connection, \
+            anyone on the network path can intercept or forge votes ***"
+        );
+    }
+    let tls_config = pubsub::tls_config(
+        matches.value_of("ca_bundle").map(Path::new),
+        matches.is_present("danger_accept_invalid_certs"),
+    )?;
+    if let Some(instance_name) = &instance_name {
+        notifier_config = notifier_config.with_instance_name(instance_name.clone());
+    }
+    if let Some(path) = matches.value_of("message_templates") {
+        notifier_config =
+            notifier_config.with_message_templates(MessageTemplates::load(Path::new(path))?);
+    }
+    let notifier = Notifier::new(notifier_config);
 
-    let pubsub_client = PubsubClient::new(&websocket_url).await?;
-    let (mut votes, votes_unsubscribe) = pubsub_client.vote_subscribe().await?;
-    let (mut slots, slots_unsubscribe) = pubsub_client.slot_subscribe().await?;
-    notifier
-        .send(&format!("votalizer: connected to {}", websocket_url))
-        .await;
+    if matches.is_present("list_backends") {
+        println!("{}", notifier.backend_listing());
+        return Ok(());
+    }
 
-    let mut slot_ancestors = BTreeMap::<Slot, HashSet<Slot>>::new();
-    let mut towers = HashMap::<Pubkey, Tower>::new();
-    let mut processed_vote_counter = 0u64;
-    let mut incident_counter = 0;
-    let mut last_status_report = Instant::now();
-    let mut last_notifier_status_report = Instant::now();
+    if matches.subcommand_matches("resend-incidents").is_some() {
+        if notifier.is_empty() {
+            return Err("No notifier backends configured to resend incident reports to".into());
+        }
+        let (sent, failed) = resend_incidents(
+            &notifier,
+            incident_dir,
+            instance_name.as_deref(),
+            critical_severity,
+        )
+        .await?;
+        println!(
+            "{} incident reports resent, {} could not be resent",
+            sent, failed
+        );
+        error_reporting::flush(sentry_guard);
+        std::process::exit(if failed == 0 { 0 } else { 1 });
+    }
+
+    // Replays carry no record of the cluster they were recorded from
+    let epoch_schedule = if replaying {
+        None
+    } else {
+        let json_rpc_url = normalize_to_url_if_moniker(matches.value_of("json_rpc_url").unwrap());
+        match RpcClient::new(json_rpc_url).get_epoch_schedule().await {
+            Ok(epoch_schedule) => Some(epoch_schedule),
+            Err(err) => {
+                warn!(
+                    "Unable to get the epoch schedule, epochs will not be reported: {}",
+                    redact_client_error(err)
+                );
+                None
+            }
+        }
+    };
 
-    const MAX_TRACKED_ANCESTORS: usize = 10 * 1_024;
-    const MAX_TRACKED_SLOTS: usize = 10 * 1_024;
+    if matches.is_present("self_test") {
+        let mut passed = true;
+        for websocket_url in &websocket_urls {
+            let result =
+                self_test_endpoint(websocket_url, &ws_headers, tls_config.as_ref(), commitment)
+                    .await;
+            passed &= result.is_ok();
+            println!(
+                "{}: {}",
+                redact_url(websocket_url),
+                result.map_or_else(|err| format!("FAILED, {}", err), |()| "ok".into())
+            );
+        }
+        let notifiers_verified = notifier.verify().await;
+        passed &= notifiers_verified;
+        println!(
+            "notifier backends ({}): {}",
+            notifier.summary(),
+            if notifiers_verified { "ok" } else { "FAILED" }
+        );
+        println!("self-test {}", if passed { "passed" } else { "failed" });
+        error_reporting::flush(sentry_guard);
+        std::process::exit(if passed { 0 } else { 1 });
+    }
 
-    loop {
+    if matches.is_present("verify_notifiers") && !notifier.verify().await {
+        warn!("*** One or more notifier backends failed verification, alerts may be lost ***");
+    }
+
+    let metrics = Arc::new(Metrics {
+        instance_name: instance_name.clone(),
+        max_recent_incidents: matches
+            .value_of("recent_incidents")
+            .unwrap()
+            .parse()
+            .unwrap(),
+        ..Metrics::default()
+    });
+    let (tower_query_sender, mut tower_queries) = mpsc::channel::<TowerQuery>(16);
+    let statsd = Statsd::new(
+        matches
+            .value_of("statsd_addr")
+            .map(|statsd_addr| statsd_addr.parse().unwrap()),
+        instance_name.as_deref(),
+    )?;
+    if let Some(metrics_addr) = matches.value_of("metrics_addr") {
+        metrics::serve(
+            metrics_addr.parse().unwrap(),
+            metrics.clone(),
+            tower_query_sender,
+            Duration::from_secs(
+                matches
+                    .value_of("health_threshold")
+                    .unwrap()
+                    .parse::<u64>()
+                    .unwrap(),
+            ),
+        )?;
+    }
+
+    info!(
+        "Configuration: RPC URLs {}, websocket URLs {}, commitment {}, \
+        tracking up to {} slots with up to {} ancestors each, monitoring {}, \
+        notifier backends: {}",
+        matches
+            .values_of("json_rpc_url")
+            .unwrap()
+            .map(|json_rpc_url| redact_url(&normalize_to_url_if_moniker(json_rpc_url)))
+            .collect::<Vec<_>>()
+            .join(", "),
+        websocket_urls
+            .iter()
+            .map(|websocket_url| redact_url(websocket_url))
+            .collect::<Vec<_>>()
+            .join(", "),
+        matches.value_of("commitment").unwrap(),
+        max_tracked_slots,
+        max_tracked_ancestors,
+        match &vote_account_filter {
+            Some(vote_account_filter) => format!("{} vote accounts", vote_account_filter.len()),
+            None => "every vote account".into(),
+        },
+        notifier.summary(),
+    );
+    let mut endpoints = Endpoints::new(websocket_urls);
+
+    let mut votalizer = Votalizer::new(
+        VotalizerConfig {
+            max_tracked_ancestors,
+            max_tracked_slots,
+            vote_account_filter,
+            liveness_threshold,
+            stall_threshold,
+            timestamp_tolerance,
+            max_vote_latency,
+            max_vote_gap,
+            tower_reset_depth,
+            max_root_lag,
+            critical_severity,
+            incident_cooldown,
+            tower_idle_timeout,
+            pending_vote_timeout,
+            max_notification_lag,
+            incident_dir: incident_dir.to_path_buf(),
+            incident_retention: matches.value_of("incident_retention_days").map(|days| {
+                Duration::from_secs(days.parse::<u64>().unwrap().saturating_mul(86400))
+            }),
+            write_text_incident,
+            write_json_incident,
+            tower_snapshot,
+            tower_snapshot_max_age,
+            status_interval,
+            status_notify_interval,
+            digest_hour: matches
+                .value_of("digest_hour")
+                .map(|hour| hour.parse().unwrap()),
+            validator_info_url: (matches.is_present("validator_info")
+                || matches.is_present("min_stake"))
+            .then(|| normalize_to_url_if_moniker(matches.value_of("json_rpc_url").unwrap())),
+            min_stake: matches
+                .value_of("min_stake")
+                .map(|sol| sol_to_lamports(sol.parse().unwrap())),
+            delinquency_check_url: matches
+                .is_present("delinquency_check")
+                .then(|| normalize_to_url_if_moniker(matches.value_of("json_rpc_url").unwrap())),
+            delinquency_check_interval: Duration::from_secs(
+                matches
+                    .value_of("delinquency_check_interval")
+                    .unwrap()
+                    .parse()
+                    .unwrap(),
+            ),
+            replaying,
+            vote_workers,
+            max_lockout_history,
+            max_incident_history,
+            epoch_schedule,
+            labels,
+            explain,
+            instance_name,
+        },
+        notifier,
+        metrics.clone(),
+        statsd,
+    );
+
+    let max_runtime = Some(matches.value_of("max_runtime").unwrap())
+        .map(|secs| secs.parse::<u64>().unwrap())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs);
+    // Resolves once the votalizer should shut down, to true if it ran for `max_runtime`
+    let shutdown = async {
         tokio::select! {
-            Some(slot_info) = slots.next() => {
-                if slot_ancestors.contains_key(&slot_info.slot) {
-                    warn!("slot {} already present in slot_ancestors. RPC node stuck?", slot_info.slot);
-                } else {
-                    let parent_ancestors = slot_ancestors.entry(slot_info.parent).or_default();
-
-                    let mut ancestors = parent_ancestors.clone();
-                    ancestors.insert(slot_info.parent);
-                    while ancestors.len() > MAX_TRACKED_ANCESTORS {
-                        let min = *ancestors.iter().min().unwrap();
-                        ancestors.remove(&min);
-                    }
+            _ = tokio::signal::ctrl_c() => false,
+            _ = async {
+                match max_runtime {
+                    Some(max_runtime) => tokio::time::sleep(max_runtime).await,
+                    None => future::pending().await,
+                }
+            } => true,
+        }
+    };
+    tokio::pin!(shutdown);
+    let mut max_runtime_reached = false;
+    let mut stall_check = tokio::time::interval(Duration::from_secs(1));
+    let mut status_dumps = status_dump_requests();
+    let notify_status_dump = matches.is_present("notify_status_dump");
 
-                    info!(
-                        "slot: {} (parent: {}, {} tracked ancestors)",
-                        slot_info.slot,
-                        slot_info.parent,
-                        ancestors.len()
-                    );
-                    slot_ancestors.insert(slot_info.slot, ancestors);
-
-                    while slot_ancestors.len() > MAX_TRACKED_SLOTS {
-                        let slot_to_remove = *slot_ancestors.keys().next().unwrap();
-                        slot_ancestors.remove(&slot_to_remove);
-                    }
+    let mut replay = matches
+        .value_of("replay")
+        .map(|path| replay::read(Path::new(path), matches.is_present("replay_realtime")))
+        .transpose()?;
+    let mut recorder = matches
+        .value_of("record")
+        .map(|path| Recorder::open(Path::new(path)))
+        .transpose()?;
+    let mut reconnect_delay = Duration::from_secs(1);
+    let mut disconnected_at = None;
 
-                    let now = Instant::now();
-                    if now.duration_since(last_status_report) > Duration::from_secs(30) {
-                        let status_report = format!(
-                            "tracking {} validators, {} votes processed{}",
-                            towers.len(),
-                            processed_vote_counter,
-                            if incident_counter > 1 {
-                                format!(", {} incidents observed", incident_counter)
-                            } else if incident_counter > 0 {
-                                ", 1 incident observed".into()
-                            } else {
-                                "".into()
-                            }
+    loop {
+        let websocket_url = endpoints.websocket_url().to_string();
+        let pubsub_client;
+        let (mut notifications, unsubscribe) = match replay.take() {
+            Some(replay) => (
+                replay
+                    .map(|notification| Some((Instant::now(), notification)))
+                    .boxed(),
+                None,
+            ),
+            None => {
+                pubsub_client =
+                    PubsubClient::new(&websocket_url, &ws_headers, tls_config.as_ref()).await;
+                // Retrying won't fix credentials the provider rejects
+                if let Some(msg) = pubsub_client
+                    .as_ref()
+                    .err()
+                    .and_then(auth_rejection_message)
+                {
+                    return Err(format!("{}: {}", redact_url(&websocket_url), msg).into());
+                }
+                let subscriptions = match &pubsub_client {
+                    Ok(pubsub_client) => async {
+                        let slot_subscription = slot_subscribe(pubsub_client, commitment).await?;
+                        Ok::<_, PubsubClientError>((
+                            pubsub_client.vote_subscribe().await?,
+                            slot_subscription,
+                        ))
+                    }
+                    .await
+                    .map_err(|err| err.to_string()),
+                    Err(err) => Err(err.to_string()),
+                };
+                let ((votes, votes_unsubscribe), (slots, slots_unsubscribe)) = match subscriptions {
+                    Ok(subscriptions) => subscriptions,
+                    Err(err) => {
+                        warn!(
+                            "Unable to subscribe to {}: {}. Retrying in {}s",
+                            redact_url(&websocket_url),
+                            err,
+                            reconnect_delay.as_secs()
                         );
-
-                        info!("{}", status_report);
-                        if now.duration_since(last_notifier_status_report) > Duration::from_secs(60 * 60 * 12) {
-                            notifier.send(&status_report).await;
-                            last_notifier_status_report = now;
+                        tokio::select! {
+                            _ = tokio::time::sleep(reconnect_delay) => {}
+                            reached = &mut shutdown => {
+                                max_runtime_reached = reached;
+                                break;
+                            }
                         }
+                        reconnect_delay = (reconnect_delay * 2).min(max_reconnect_delay);
+                        endpoints.failed(Instant::now());
+                        continue;
+                    }
+                };
+                reconnect_delay = Duration::from_secs(1);
+                endpoints.succeeded();
 
-                        last_status_report = now;
+                let msg = match disconnected_at.take() {
+                    Some(disconnected_at) => {
+                        metrics.websocket_reconnects.fetch_add(1, Ordering::Relaxed);
+                        format!(
+                            "votalizer: reconnected to {} after {} seconds",
+                            redact_url(&websocket_url),
+                            Instant::now().duration_since(disconnected_at).as_secs()
+                        )
                     }
-                }
-            },
-            Some(mut vote) = votes.next() => {
-                let vote_account_address = vote.vote_pubkey.parse::<Pubkey>().unwrap();
-                let signature = vote.signature.parse::<Signature>().unwrap();
-
-                if vote.timestamp.is_none() {
-                    // TODO: if `timestamp.is_some()`, consider looking for unusual values
-                    debug!("{} did not publish a timestamp", vote.vote_pubkey);
-                }
+                    None => format!("votalizer: connected to {}", redact_url(&websocket_url)),
+                };
+                info!("{}", msg);
+                votalizer
+                    .notifier()
+                    .send_with_severity(&msg, Severity::Info, Utc::now());
 
-                let tower = towers.entry(vote_account_address).or_default();
+                // Either subscription ending is a disconnect, signalled by `None`
+                (
+                    stream::select(
+                        votes
+                            .map(|(received_at, vote)| {
+                                Some((received_at, PubsubNotification::Vote(vote)))
+                            })
+                            .chain(stream::once(future::ready(None))),
+                        slots
+                            .map(|(received_at, slot_info)| {
+                                Some((received_at, PubsubNotification::Slot(slot_info)))
+                            })
+                            .chain(stream::once(future::ready(None))),
+                    )
+                    .boxed(),
+                    Some((votes_unsubscribe, slots_unsubscribe)),
+                )
+            }
+        };
 
-                vote.slots.sort_unstable();
-                vote.slots.dedup();
-                for pair in vote.slots.chunks_exact(2) {
-                    if pair[0] >= pair[1] {
-                        panic!(
-                            "{}: Invalid vote pair, {:?}, in {}",
-                            vote_account_address, pair, signature
-                        );
+        votalizer.connected();
+        let mut shutting_down = false;
+        loop {
+            tokio::select! {
+                notification = notifications.next() => {
+                    let Some(Some((received_at, notification))) = notification else {
+                        break;
+                    };
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.record(&notification, Utc::now());
+                    }
+                    if votalizer.check_notification_lag(received_at, Instant::now()) {
+                        votalizer.process_notification(notification).await;
                     }
                 }
-
-                // Ignore votes for slots earlier than we already have votes for
-                let new_votes = vote
-                    .slots
-                    .iter()
-                    .cloned()
-                    .filter(|slot| {
-                        tower
-                            .last_voted_slot()
-                            .map_or(true, |last_voted_slot| *slot > last_voted_slot)
-                    })
-                    .collect::<Vec<_>>();
-
-                if !new_votes.is_empty() {
-                    trace!(
-                        "{:<44}: new votes: {} [{}]",
-                        vote_account_address,
-                        new_votes.iter().map(ToString::to_string).join(", "),
-                        signature
-                    );
-
-                    tower.record_vote_signature(signature, new_votes.clone());
-
-                    for slot in new_votes {
-                        processed_vote_counter += 1;
-
-                        if let Some(incident) = tower.process_vote_slot(
-                            &vote_account_address,
-                            slot,
-                            &signature,
-                            &slot_ancestors,
-                        ) {
-                            let msg = format!(
-                                "{}: Lockout violation detected [{}]",
-                                vote_account_address, signature
-                            );
-                            notifier.send(&msg).await;
-                            error!("{}\n{}", msg, incident);
-                            let filename =
-                                format!("incident-{}-{}.log", vote_account_address, signature);
-
-                            File::create(&filename)
-                                .and_then(|mut output| {
-                                    use std::io::Write;
-                                    writeln!(output, "{}", incident)
-                                })
-                                .unwrap_or_else(|err| error!("Unable to write {}: {}", filename, err));
-                            incident_counter += 1;
-                        }
+                Some(()) = status_dumps.next() => {
+                    votalizer.dump_status(notify_status_dump).await;
+                }
+                _ = stall_check.tick() => {
+                    votalizer.check_stall(Instant::now());
+                    votalizer.release_pending_votes(Instant::now()).await;
+                }
+                Some((vote_account_address, reply)) = tower_queries.recv() => {
+                    let _ = reply.send(votalizer.with_tower(&vote_account_address, Tower::to_json).await);
+                }
+                reached = &mut shutdown => {
+                    if reached {
+                        info!("Maximum runtime reached, shutting down");
+                    } else {
+                        info!("Interrupted, shutting down");
                     }
+                    max_runtime_reached = reached;
+                    shutting_down = true;
+                    break;
                 }
-            },
-            else => {
-                break;
             }
         }
+        votalizer.disconnected();
+        votalizer.flush_pending_votes().await;
+        if let Some((votes_unsubscribe, slots_unsubscribe)) = unsubscribe {
+            slots_unsubscribe().await;
+            votes_unsubscribe().await;
+        }
+
+        if replaying {
+            info!(
+                "Replay complete: {} votes processed, {} incidents observed",
+                votalizer.processed_vote_count().await,
+                votalizer.incident_count().await
+            );
+            break;
+        }
+        if shutting_down {
+            break;
+        }
+        warn!("Disconnected from {}", redact_url(&websocket_url));
+        disconnected_at = Some(Instant::now());
+        endpoints.failed(Instant::now());
     }
-    slots_unsubscribe().await;
-    votes_unsubscribe().await;
 
+    if let Some(max_runtime) = max_runtime.filter(|_| max_runtime_reached) {
+        let msg = format!(
+            "votalizer: shutting down for a scheduled restart after {} seconds",
+            max_runtime.as_secs()
+        );
+        votalizer
+            .notifier()
+            .send_with_severity(&msg, Severity::Info, Utc::now());
+    }
+    votalizer.save_tower_snapshot().await;
+    votalizer.notifier().flush().await;
     Ok(())
 }
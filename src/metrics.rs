@@ -0,0 +1,337 @@
+use {
+    chrono::{DateTime, SecondsFormat, Utc},
+    hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Method, Request, Response, Server, StatusCode,
+    },
+    log::*,
+    serde_json::Value,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::VecDeque,
+        convert::Infallible,
+        fmt::Write,
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicBool, AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+    tokio::sync::{mpsc, oneshot},
+};
+
+/// A request for the JSON representation of a validator's tower, answered by the main loop
+pub type TowerQuery = (Pubkey, oneshot::Sender<Option<Value>>);
+
+/// Counters and gauges exported in the Prometheus text format
+#[derive(Default)]
+pub struct Metrics {
+    pub validators_tracked: AtomicU64,
+    pub votes_processed: AtomicU64,
+    pub incidents: AtomicU64,
+    pub tracked_ancestors: AtomicU64,
+    pub websocket_reconnects: AtomicU64,
+    pub dropped_notifications: AtomicU64,
+    /// Whether the websocket is connected, for the health check
+    pub connected: AtomicBool,
+    /// Unix time in seconds when the slot stream last advanced, for the health check
+    pub last_slot_advance: AtomicU64,
+    /// Labels every metric with `instance`, to tell votalizers on the same host apart
+    pub instance_name: Option<String>,
+    /// Incidents remembered for `/incidents` [default: none]
+    pub max_recent_incidents: usize,
+    /// Summaries of the recent incidents, oldest first
+    pub recent_incidents: Mutex<VecDeque<Value>>,
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Escapes a label value as the Prometheus text format requires
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    /// Records that the slot stream advanced just now
+    pub fn slot_advanced(&self) {
+        self.last_slot_advance
+            .store(unix_timestamp(), Ordering::Relaxed);
+    }
+
+    /// Remembers the summary of an incident detected at `detected_at` for `/incidents`,
+    /// forgetting the oldest beyond `max_recent_incidents`
+    pub fn record_incident(&self, mut incident_summary: Value, detected_at: DateTime<Utc>) {
+        if self.max_recent_incidents == 0 {
+            return;
+        }
+        incident_summary["detected_at"] = detected_at
+            .to_rfc3339_opts(SecondsFormat::Secs, true)
+            .into();
+        let mut recent_incidents = self.recent_incidents.lock().unwrap();
+        if recent_incidents.len() == self.max_recent_incidents {
+            recent_incidents.pop_front();
+        }
+        recent_incidents.push_back(incident_summary);
+    }
+
+    /// Up to `limit` of the remembered incidents, newest first
+    fn recent_incidents(&self, limit: Option<usize>) -> Vec<Value> {
+        self.recent_incidents
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .take(limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    /// Whether the websocket is connected and the slot stream advanced within `threshold`,
+    /// otherwise the reason it is unhealthy
+    fn health(&self, threshold: Duration) -> Result<(), String> {
+        if !self.connected.load(Ordering::Relaxed) {
+            return Err("websocket disconnected".to_string());
+        }
+        let slot_age =
+            unix_timestamp().saturating_sub(self.last_slot_advance.load(Ordering::Relaxed));
+        if slot_age > threshold.as_secs() {
+            return Err(format!("no new slot for {}s", slot_age));
+        }
+        Ok(())
+    }
+
+    fn render(&self) -> String {
+        let labels = self
+            .instance_name
+            .as_ref()
+            .map(|instance_name| escape_label_value(instance_name))
+            .map(|instance_name| format!("{{instance=\"{}\"}}", instance_name))
+            .unwrap_or_default();
+        let mut output = String::new();
+        for (name, kind, help, value) in [
+            (
+                "votalizer_validators_tracked",
+                "gauge",
+                "Number of validators whose votes are being tracked",
+                &self.validators_tracked,
+            ),
+            (
+                "votalizer_votes_processed_total",
+                "counter",
+                "Number of vote slots processed",
+                &self.votes_processed,
+            ),
+            (
+                "votalizer_incidents_total",
+                "counter",
+                "Number of lockout violations detected",
+                &self.incidents,
+            ),
+            (
+                "votalizer_tracked_ancestors",
+                "gauge",
+                "Number of ancestors tracked for the most recent slot",
+                &self.tracked_ancestors,
+            ),
+            (
+                "votalizer_websocket_reconnects_total",
+                "counter",
+                "Number of times the websocket connection was re-established",
+                &self.websocket_reconnects,
+            ),
+            (
+                "votalizer_dropped_notifications_total",
+                "counter",
+                "Number of notifications dropped for waiting too long to be processed",
+                &self.dropped_notifications,
+            ),
+        ] {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} {}", name, kind);
+            let _ = writeln!(
+                output,
+                "{}{} {}",
+                name,
+                labels,
+                value.load(Ordering::Relaxed)
+            );
+        }
+        output
+    }
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn tower_response(
+    tower_queries: &mpsc::Sender<TowerQuery>,
+    vote_account_address: &str,
+) -> Response<Body> {
+    let Ok(vote_account_address) = vote_account_address.parse::<Pubkey>() else {
+        return status_response(StatusCode::BAD_REQUEST);
+    };
+    let (reply_sender, reply_receiver) = oneshot::channel();
+    if tower_queries
+        .send((vote_account_address, reply_sender))
+        .await
+        .is_err()
+    {
+        return status_response(StatusCode::SERVICE_UNAVAILABLE);
+    }
+    match reply_receiver.await {
+        Ok(Some(tower)) => Response::builder()
+            .header("Content-Type", "application/json")
+            .body(Body::from(tower.to_string()))
+            .unwrap(),
+        Ok(None) => status_response(StatusCode::NOT_FOUND),
+        Err(_) => status_response(StatusCode::SERVICE_UNAVAILABLE),
+    }
+}
+
+/// The recent incidents as a JSON array, newest first, limited by an optional `limit` query
+/// parameter
+fn incidents_response(metrics: &Metrics, query: Option<&str>) -> Response<Body> {
+    let limit = query
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|param| param.strip_prefix("limit="))
+        .map(str::parse::<usize>);
+    let limit = match limit.transpose() {
+        Ok(limit) => limit,
+        Err(_) => return status_response(StatusCode::BAD_REQUEST),
+    };
+    Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(
+            Value::from(metrics.recent_incidents(limit)).to_string(),
+        ))
+        .unwrap()
+}
+
+fn health_response(metrics: &Metrics, health_threshold: Duration) -> Response<Body> {
+    let (status, body) = match metrics.health(health_threshold) {
+        Ok(()) => (StatusCode::OK, "ok".to_string()),
+        Err(reason) => (StatusCode::SERVICE_UNAVAILABLE, reason),
+    };
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "text/plain")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn handle(
+    metrics: Arc<Metrics>,
+    tower_queries: mpsc::Sender<TowerQuery>,
+    health_threshold: Duration,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET {
+        return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    let path = request.uri().path();
+    Ok(if path == "/metrics" {
+        Response::builder()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .body(Body::from(metrics.render()))
+            .unwrap()
+    } else if path == "/healthz" {
+        health_response(&metrics, health_threshold)
+    } else if path == "/incidents" {
+        incidents_response(&metrics, request.uri().query())
+    } else if let Some(vote_account_address) = path.strip_prefix("/tower/") {
+        tower_response(&tower_queries, vote_account_address).await
+    } else {
+        status_response(StatusCode::NOT_FOUND)
+    })
+}
+
+/// Serves `metrics` at `http://<addr>/metrics`, validator towers at
+/// `http://<addr>/tower/<VOTE_ACCOUNT_ADDRESS>`, recent incidents at `http://<addr>/incidents`,
+/// and a health check at `http://<addr>/healthz`
+/// that fails once the slot stream has not advanced within `health_threshold`, until the process
+/// exits
+pub fn serve(
+    addr: SocketAddr,
+    metrics: Arc<Metrics>,
+    tower_queries: mpsc::Sender<TowerQuery>,
+    health_threshold: Duration,
+) -> Result<(), hyper::Error> {
+    let make_service = make_service_fn(move |_| {
+        let metrics = metrics.clone();
+        let tower_queries = tower_queries.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(
+                    metrics.clone(),
+                    tower_queries.clone(),
+                    health_threshold,
+                    request,
+                )
+            }))
+        }
+    });
+
+    let server = Server::try_bind(&addr)?.serve(make_service);
+    info!("Serving metrics at http://{}/metrics", addr);
+    tokio::spawn(async move {
+        if let Err(err) = server.await {
+            error!("Metrics server failed: {}", err);
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeZone, serde_json::json};
+
+    #[test]
+    fn test_recent_incidents() {
+        let metrics = Metrics {
+            max_recent_incidents: 2,
+            ..Metrics::default()
+        };
+        let detected_at = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+        for vote_slot in 1..=3 {
+            metrics.record_incident(json!({ "vote_slot": vote_slot }), detected_at);
+        }
+        assert_eq!(
+            metrics.recent_incidents(None),
+            vec![
+                json!({ "vote_slot": 3, "detected_at": "2023-11-14T22:13:20Z" }),
+                json!({ "vote_slot": 2, "detected_at": "2023-11-14T22:13:20Z" }),
+            ]
+        );
+        assert_eq!(metrics.recent_incidents(Some(1)).len(), 1);
+
+        // Nothing is remembered unless enabled
+        let metrics = Metrics::default();
+        metrics.record_incident(json!({ "vote_slot": 1 }), detected_at);
+        assert!(metrics.recent_incidents(None).is_empty());
+    }
+
+    #[test]
+    fn test_render_escapes_instance_name() {
+        let metrics = Metrics {
+            instance_name: Some("a\\b\"c\nd".into()),
+            ..Metrics::default()
+        };
+        assert!(metrics
+            .render()
+            .contains("votalizer_incidents_total{instance=\"a\\\\b\\\"c\\nd\"} 0\n"));
+    }
+}
@@ -1,47 +1,2259 @@
-use {reqwest::Client, serde_json::json, std::env};
+#[cfg(feature = "email")]
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport, Message,
+    Tokio1Executor,
+};
+#[cfg(feature = "opsgenie")]
+use reqwest::header::AUTHORIZATION;
+#[cfg(any(feature = "slack", feature = "discord"))]
+use reqwest::multipart::Form;
+#[cfg(any(
+    feature = "slack",
+    feature = "discord",
+    feature = "telegram",
+    feature = "pagerduty",
+    feature = "teams",
+    feature = "opsgenie",
+    feature = "matrix"
+))]
+use serde_json::json;
+#[cfg(feature = "discord")]
+use {
+    crate::tower::IncidentKind, reqwest::multipart::Part, solana_sdk::native_token::lamports_to_sol,
+};
+use {
+    crate::{templates::MessageTemplates, tower::Incident},
+    chrono::{DateTime, FixedOffset, NaiveTime, SecondsFormat, Utc},
+    futures_util::stream::{self, StreamExt},
+    log::*,
+    reqwest::{Certificate, Client, Proxy, RequestBuilder, Response, Url},
+    serde::{de::Error as _, Deserialize, Deserializer},
+    solana_client::client_error::ClientError,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        env, fmt, fs,
+        path::{Path, PathBuf},
+        str::FromStr,
+        sync::{
+            atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    },
+    tokio::sync::{
+        mpsc::{self, error::TrySendError},
+        oneshot,
+    },
+};
+#[cfg(feature = "webhook")]
+use {
+    hmac::{Hmac, Mac},
+    reqwest::header::CONTENT_TYPE,
+    sha2::Sha256,
+};
+#[cfg(feature = "sns")]
+use {
+    rusoto_core::Region,
+    rusoto_sns::{PublishInput, Sns, SnsClient},
+};
 
+#[cfg(feature = "slack")]
+const SLACK_FILES_UPLOAD_URL: &str = "https://slack.com/api/files.upload";
+
+// Attachments posted inline are truncated to this many characters to stay within
+// message size limits
+const INLINE_ATTACHMENT_MAX_LEN: usize = 3000;
+
+#[cfg(feature = "discord")]
+const DISCORD_EMBED_COLOR_RED: u32 = 0xe74c3c;
+
+#[cfg(feature = "pagerduty")]
+const PAGERDUTY_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+// Delay before the first retry, doubled on each subsequent retry
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[cfg(feature = "opsgenie")]
+const OPSGENIE_ALERTS_URL: &str = "https://api.opsgenie.com/v2/alerts";
+#[cfg(feature = "opsgenie")]
+const OPSGENIE_EU_ALERTS_URL: &str = "https://api.eu.opsgenie.com/v2/alerts";
+
+// Opsgenie truncates alert messages longer than this
+#[cfg(feature = "opsgenie")]
+const OPSGENIE_MAX_MESSAGE_LEN: usize = 130;
+
+#[cfg(feature = "webhook")]
+const WEBHOOK_SIGNATURE_HEADER: &str = "X-Votalizer-Signature";
+
+// Maximum notifications per minute across all backends
+const DEFAULT_RATE_LIMIT: u32 = 20;
+
+// Fraction of the rate limit reserved for `Critical` notifications
+const RATE_LIMIT_CRITICAL_RESERVE: f64 = 0.2;
+
+// Notifications waiting to be sent before further notifications are dropped
+const NOTIFICATION_QUEUE_DEPTH: usize = 1024;
+
+#[cfg(feature = "webhook")]
+const DEFAULT_WEBHOOK_TEMPLATE: &str = r#"{"text": "{message}"}"#;
+
+// PagerDuty rejects event summaries longer than this
+#[cfg(feature = "pagerduty")]
+const PAGERDUTY_MAX_SUMMARY_LEN: usize = 1024;
+
+// Error response bodies are truncated to this many characters when logged
+const ERROR_BODY_MAX_LEN: usize = 512;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "info" => Ok(Severity::Info),
+            "warning" => Ok(Severity::Warning),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(format!("unknown severity: {}", s)),
+        }
+    }
+}
+
+fn parse_env<T: FromStr>(var: &str) -> Option<T>
+where
+    T::Err: fmt::Display,
+{
+    let value = env::var(var).ok()?;
+    value
+        .parse()
+        .map_err(|err| warn!("Invalid {}: {}", var, err))
+        .ok()
+}
+
+// Reads a secret from the file named by `<var>_FILE`, as Docker and Kubernetes secrets are
+// mounted, or else from `var`. Keeps secrets out of the environment, where they are visible to
+// process listings
+#[cfg(any(
+    feature = "slack",
+    feature = "discord",
+    feature = "telegram",
+    feature = "pagerduty",
+    feature = "email",
+    feature = "teams",
+    feature = "opsgenie",
+    feature = "matrix",
+    feature = "webhook"
+))]
+fn secret_from_env(var: &str) -> Option<String> {
+    let file_var = format!("{}_FILE", var);
+    match env::var_os(&file_var) {
+        Some(path) => fs::read_to_string(&path)
+            .map(|secret| secret.trim_end().to_string())
+            .map_err(|err| {
+                warn!(
+                    "Unable to read {} {}: {}, ignoring",
+                    file_var,
+                    Path::new(&path).display(),
+                    err
+                )
+            })
+            .ok(),
+        None => env::var(var).ok(),
+    }
+}
+
+// Reads the minimum severity a backend should receive from `<prefix>_MIN_SEVERITY`
+fn min_severity_from_env(prefix: &str) -> Option<Severity> {
+    parse_env(&format!("{}_MIN_SEVERITY", prefix))
+}
+
+#[cfg(feature = "discord")]
+fn default_discord_username() -> String {
+    "votalizer".to_string()
+}
+
+#[cfg(feature = "email")]
+fn default_smtp_port() -> u16 {
+    587
+}
+
+#[cfg(feature = "webhook")]
+fn default_webhook_template() -> String {
+    DEFAULT_WEBHOOK_TEMPLATE.to_string()
+}
+
+/// The cargo feature building each backend, the environment variable configuring it, and
+/// whether the feature is enabled
+pub const BACKEND_FEATURES: &[(&str, &str, bool)] = &[
+    ("slack", "SLACK_WEBHOOK", cfg!(feature = "slack")),
+    ("discord", "DISCORD_WEBHOOK", cfg!(feature = "discord")),
+    ("telegram", "TELEGRAM_BOT_TOKEN", cfg!(feature = "telegram")),
+    (
+        "pagerduty",
+        "PAGERDUTY_ROUTING_KEY",
+        cfg!(feature = "pagerduty"),
+    ),
+    ("email", "SMTP_HOST", cfg!(feature = "email")),
+    ("teams", "TEAMS_WEBHOOK", cfg!(feature = "teams")),
+    ("opsgenie", "OPSGENIE_API_KEY", cfg!(feature = "opsgenie")),
+    ("matrix", "MATRIX_HOMESERVER", cfg!(feature = "matrix")),
+    ("webhook", "GENERIC_WEBHOOK_URL", cfg!(feature = "webhook")),
+    ("sns", "SNS_TOPIC_ARN", cfg!(feature = "sns")),
+];
+
+/// A notifier backend. Only the backends whose cargo features are enabled are available
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
 pub enum Config {
-    Slack { webhook: String },
-    Discord { webhook: String, username: String },
+    #[cfg(feature = "slack")]
+    Slack {
+        webhook: String,
+        /// Overrides the webhook's default channel, where the webhook allows it
+        channel: Option<String>,
+        bot_token: Option<String>,
+        upload_channel: Option<String>,
+        min_severity: Option<Severity>,
+    },
+    #[cfg(feature = "discord")]
+    Discord {
+        webhook: String,
+        #[serde(default = "default_discord_username")]
+        username: String,
+        min_severity: Option<Severity>,
+    },
+    #[cfg(feature = "telegram")]
+    Telegram {
+        bot_token: String,
+        chat_id: String,
+        min_severity: Option<Severity>,
+    },
+    #[cfg(feature = "pagerduty")]
+    PagerDuty {
+        routing_key: String,
+        min_severity: Option<Severity>,
+    },
+    #[cfg(feature = "email")]
+    Email {
+        smtp_host: String,
+        #[serde(default = "default_smtp_port")]
+        smtp_port: u16,
+        username: Option<String>,
+        password: Option<String>,
+        from: String,
+        to: Vec<String>,
+        min_severity: Option<Severity>,
+    },
+    #[cfg(feature = "teams")]
+    Teams {
+        webhook: String,
+        min_severity: Option<Severity>,
+    },
+    #[cfg(feature = "opsgenie")]
+    Opsgenie {
+        api_key: String,
+        #[serde(default)]
+        eu: bool,
+        min_severity: Option<Severity>,
+    },
+    #[cfg(feature = "matrix")]
+    Matrix {
+        homeserver: String,
+        access_token: String,
+        room_id: String,
+        min_severity: Option<Severity>,
+    },
+    #[cfg(feature = "webhook")]
+    Webhook {
+        url: String,
+        #[serde(default = "default_webhook_template")]
+        template: String,
+        secret: Option<String>,
+        min_severity: Option<Severity>,
+    },
+    /// Publishes to an AWS SNS topic, with credentials from the standard AWS credential chain
+    #[cfg(feature = "sns")]
+    Sns {
+        topic_arn: String,
+        /// Defaults to `AWS_DEFAULT_REGION` or `AWS_REGION`, else `us-east-1`
+        region: Option<String>,
+        min_severity: Option<Severity>,
+    },
+    Stdout {
+        min_severity: Option<Severity>,
+    },
 }
 
+impl Config {
+    fn min_severity(&self) -> Option<Severity> {
+        match self {
+            #[cfg(feature = "slack")]
+            Config::Slack { min_severity, .. } => *min_severity,
+            #[cfg(feature = "discord")]
+            Config::Discord { min_severity, .. } => *min_severity,
+            #[cfg(feature = "telegram")]
+            Config::Telegram { min_severity, .. } => *min_severity,
+            // Pages only for the most severe incidents unless told otherwise
+            #[cfg(feature = "pagerduty")]
+            Config::PagerDuty { min_severity, .. } => {
+                Some(min_severity.unwrap_or(Severity::Critical))
+            }
+            #[cfg(feature = "email")]
+            Config::Email { min_severity, .. } => *min_severity,
+            #[cfg(feature = "teams")]
+            Config::Teams { min_severity, .. } => *min_severity,
+            #[cfg(feature = "opsgenie")]
+            Config::Opsgenie { min_severity, .. } => *min_severity,
+            #[cfg(feature = "matrix")]
+            Config::Matrix { min_severity, .. } => *min_severity,
+            #[cfg(feature = "webhook")]
+            Config::Webhook { min_severity, .. } => *min_severity,
+            #[cfg(feature = "sns")]
+            Config::Sns { min_severity, .. } => *min_severity,
+            Config::Stdout { min_severity } => *min_severity,
+        }
+    }
+
+    fn service_name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "slack")]
+            Config::Slack { .. } => "Slack",
+            #[cfg(feature = "discord")]
+            Config::Discord { .. } => "Discord",
+            #[cfg(feature = "telegram")]
+            Config::Telegram { .. } => "Telegram",
+            #[cfg(feature = "pagerduty")]
+            Config::PagerDuty { .. } => "PagerDuty",
+            #[cfg(feature = "email")]
+            Config::Email { .. } => "email",
+            #[cfg(feature = "teams")]
+            Config::Teams { .. } => "Teams",
+            #[cfg(feature = "opsgenie")]
+            Config::Opsgenie { .. } => "Opsgenie",
+            #[cfg(feature = "matrix")]
+            Config::Matrix { .. } => "Matrix",
+            #[cfg(feature = "webhook")]
+            Config::Webhook { .. } => "webhook",
+            #[cfg(feature = "sns")]
+            Config::Sns { .. } => "SNS",
+            Config::Stdout { .. } => "stdout",
+        }
+    }
+
+    /// Names the backend and where it delivers to, without any secrets
+    fn description(&self) -> String {
+        match self {
+            #[cfg(feature = "slack")]
+            Config::Slack {
+                webhook,
+                channel: Some(channel),
+                ..
+            } => format!(
+                "{} ({}, {})",
+                self.service_name(),
+                redact_url(webhook),
+                channel
+            ),
+            #[cfg(feature = "slack")]
+            Config::Slack { webhook, .. } => {
+                format!("{} ({})", self.service_name(), redact_url(webhook))
+            }
+            #[cfg(feature = "discord")]
+            Config::Discord { webhook, .. } => {
+                format!("{} ({})", self.service_name(), redact_url(webhook))
+            }
+            #[cfg(feature = "teams")]
+            Config::Teams { webhook, .. } => {
+                format!("{} ({})", self.service_name(), redact_url(webhook))
+            }
+            #[cfg(feature = "webhook")]
+            Config::Webhook { url, .. } => {
+                format!("{} ({})", self.service_name(), redact_url(url))
+            }
+            #[cfg(feature = "matrix")]
+            Config::Matrix { homeserver, .. } => {
+                format!("{} ({})", self.service_name(), redact_url(homeserver))
+            }
+            #[cfg(feature = "email")]
+            Config::Email {
+                smtp_host,
+                smtp_port,
+                ..
+            } => format!("{} ({}:{})", self.service_name(), smtp_host, smtp_port),
+            #[cfg(feature = "opsgenie")]
+            Config::Opsgenie { eu: true, .. } => format!("{} (EU)", self.service_name()),
+            #[cfg(feature = "sns")]
+            Config::Sns { topic_arn, .. } => format!("{} ({})", self.service_name(), topic_arn),
+            _ => self.service_name().to_string(),
+        }
+    }
+}
+
+/// Reduces `url` to its scheme, host and port, as webhook and RPC URLs commonly embed secrets in
+/// their path or query
+pub fn redact_url(url: &str) -> String {
+    let Ok(url) = Url::parse(url) else {
+        return "<invalid URL>".into();
+    };
+    let host = url.host_str().unwrap_or_default();
+    match url.port() {
+        Some(port) => format!("{}://{}:{}", url.scheme(), host, port),
+        None => format!("{}://{}", url.scheme(), host),
+    }
+}
+
+/// Displays an RPC client error with every URL in it reduced as by `redact_url`. The request URL
+/// appears in the text of the client's errors, not just in those it wraps
+pub fn redact_client_error(err: ClientError) -> String {
+    let err = err.to_string();
+    let mut redacted = String::with_capacity(err.len());
+    let mut rest = err.as_str();
+    while let Some(separator) = rest.find("://") {
+        let start = rest[..separator]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !c.is_ascii_alphanumeric())
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let end = rest[separator..]
+            .find(|c: char| c.is_whitespace() || c == ')')
+            .map_or(rest.len(), |i| separator + i);
+        redacted.push_str(&rest[..start]);
+        redacted.push_str(&redact_url(&rest[start..end]));
+        rest = &rest[end..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+/// A daily window, in a fixed UTC offset, during which `Info` notifications are suppressed
+struct QuietHours {
+    start: NaiveTime,
+    end: NaiveTime,
+    offset: FixedOffset,
+}
+
+impl QuietHours {
+    // Parses a window of the form `22:00-07:00` and an optional UTC offset of the form `+02:00`
+    fn parse(window: &str, offset: Option<&str>) -> Result<Self, String> {
+        let (start, end) = window
+            .split_once('-')
+            .ok_or_else(|| format!("expected HH:MM-HH:MM, got {}", window))?;
+        let parse_time = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|err| format!("invalid time {}: {}", time, err))
+        };
+        let offset = match offset {
+            Some(offset) => parse_utc_offset(offset)?,
+            None => FixedOffset::east_opt(0).unwrap(),
+        };
+        Ok(Self {
+            start: parse_time(start)?,
+            end: parse_time(end)?,
+            offset,
+        })
+    }
+
+    fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            // The window wraps past midnight
+            time >= self.start || time < self.end
+        }
+    }
+
+    /// Whether a notification of `severity` is suppressed at `now`. Only `Info` notifications are,
+    /// so that incidents still get through
+    fn suppresses(&self, severity: Severity, now: DateTime<Utc>) -> bool {
+        severity == Severity::Info && self.contains(now.with_timezone(&self.offset).time())
+    }
+}
+
+fn parse_utc_offset(offset: &str) -> Result<FixedOffset, String> {
+    let invalid = || format!("expected a UTC offset like +02:00, got {}", offset);
+    let offset = offset.trim();
+    let (sign, rest) = match (offset.strip_prefix('+'), offset.strip_prefix('-')) {
+        (Some(rest), _) => (1, rest),
+        (_, Some(rest)) => (-1, rest),
+        _ => return Err(invalid()),
+    };
+    let (hours, minutes) = rest.split_once(':').ok_or_else(invalid)?;
+    let hours = hours
+        .parse::<u32>()
+        .ok()
+        .filter(|hours| *hours < 24)
+        .ok_or_else(invalid)?;
+    let minutes = minutes
+        .parse::<u32>()
+        .ok()
+        .filter(|minutes| *minutes < 60)
+        .ok_or_else(invalid)?;
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60) as i32).ok_or_else(invalid)
+}
+
+/// Token bucket limiting the rate of outbound notifications across all backends
+struct RateLimiter {
+    capacity: f64,
+    tokens: f64,
+    refill_per_sec: f64,
+    last_refill: Instant,
+    suppressed: usize,
+}
+
+impl RateLimiter {
+    fn new(per_minute: u32) -> Self {
+        Self {
+            capacity: per_minute as f64,
+            tokens: per_minute as f64,
+            refill_per_sec: per_minute as f64 / 60.,
+            last_refill: Instant::now(),
+            suppressed: 0,
+        }
+    }
+
+    /// Takes a token for a notification of `severity`, returning the number of notifications
+    /// suppressed since the last successful acquisition. Non-critical notifications may not use
+    /// the final `RATE_LIMIT_CRITICAL_RESERVE` of the bucket, so that lockout violations still get
+    /// through during a storm of lesser notifications
+    fn acquire(&mut self, severity: Severity) -> Result<usize, ()> {
+        let now = Instant::now();
+        self.tokens = (self.tokens
+            + now.duration_since(self.last_refill).as_secs_f64() * self.refill_per_sec)
+            .min(self.capacity);
+        self.last_refill = now;
+
+        let reserve = if severity == Severity::Critical {
+            0.
+        } else {
+            (self.capacity * RATE_LIMIT_CRITICAL_RESERVE).floor()
+        };
+        if self.tokens - reserve < 1. {
+            self.suppressed += 1;
+            return Err(());
+        }
+        self.tokens -= 1.;
+        Ok(std::mem::take(&mut self.suppressed))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Attachment<'a> {
+    #[cfg_attr(
+        not(any(feature = "slack", feature = "discord", feature = "pagerduty")),
+        allow(dead_code)
+    )]
+    filename: &'a str,
+    contents: &'a str,
+}
+
+#[derive(Clone, Copy)]
+struct Notification<'a> {
+    vote_account_address: Option<&'a Pubkey>,
+    msg: &'a str,
+    severity: Severity,
+    // When the event being notified occurred, which may be well before the message is sent
+    timestamp: DateTime<Utc>,
+    attachment: Option<Attachment<'a>>,
+    // For backends that format incidents specially
+    #[cfg_attr(not(any(feature = "discord", feature = "pagerduty")), allow(dead_code))]
+    incident: Option<&'a Incident>,
+}
+
+impl Notification<'_> {
+    // The message prefixed with its ISO-8601 event timestamp
+    fn text(&self) -> String {
+        format!(
+            "[{}] {}",
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Secs, true),
+            self.msg
+        )
+    }
+
+    // The message text with any attachment appended, truncated to `INLINE_ATTACHMENT_MAX_LEN`
+    fn inline_text(&self) -> String {
+        match &self.attachment {
+            Some(attachment) => {
+                let mut text = format!("{}\n\n", self.text());
+                match attachment
+                    .contents
+                    .char_indices()
+                    .nth(INLINE_ATTACHMENT_MAX_LEN)
+                {
+                    Some((end, _)) => {
+                        text.push_str(&attachment.contents[..end]);
+                        text.push_str("\n...(truncated)");
+                    }
+                    None => text.push_str(attachment.contents),
+                }
+                text
+            }
+            None => self.text(),
+        }
+    }
+}
+
+/// Queues notifications for a task that sends them to the configured backends, so that a
+/// slow or failing backend doesn't hold up the caller
 pub struct Notifier {
+    backends: Arc<Backends>,
+    templates: MessageTemplates,
+    queue: mpsc::Sender<Queued>,
+    // Notifications dropped for a full queue since the last was sent
+    dropped: Arc<AtomicUsize>,
+}
+
+enum Queued {
+    Notification(QueuedNotification),
+    // Answered once every notification queued before it has been sent
+    Flush(oneshot::Sender<()>),
+}
+
+struct QueuedNotification {
+    vote_account_address: Option<Pubkey>,
+    msg: String,
+    severity: Severity,
+    timestamp: DateTime<Utc>,
+    incident: Option<Box<Incident>>,
+}
+
+/// How a backend's notifications have fared since startup
+#[derive(Default)]
+struct BackendHealth {
+    sent: u64,
+    failed: u64,
+    last_error: Option<String>,
+}
+
+struct Backends {
+    // Unused by the backends that send without an HTTP request
+    #[cfg_attr(
+        not(any(
+            feature = "slack",
+            feature = "discord",
+            feature = "telegram",
+            feature = "pagerduty",
+            feature = "teams",
+            feature = "opsgenie",
+            feature = "matrix",
+            feature = "webhook"
+        )),
+        allow(dead_code)
+    )]
     client: Client,
     configs: Vec<Config>,
+    // Alternate backend sets for specific vote accounts, indexed by `route_by_vote_account`
+    routes: Vec<Vec<Config>>,
+    route_by_vote_account: HashMap<Pubkey, usize>,
+    max_attempts: u32,
+    // Backends sent a notification at once, all of them if `None`
+    max_concurrency: Option<usize>,
+    rate_limiter: Option<Mutex<RateLimiter>>,
+    quiet_hours: Option<QuietHours>,
+    // Matrix transaction ids, seeded from the wall clock so they remain unique across restarts
+    next_txn_id: AtomicU64,
+    // By the index of the backend's route, if any, and of the backend within its set, as backends
+    // of the same service and host share a description
+    health: Mutex<HashMap<(Option<usize>, usize), BackendHealth>>,
+    instance_name: Option<String>,
 }
 
-impl Notifier {
-    pub fn default() -> Self {
+/// Notifier configuration, loaded either from the environment or from a TOML file of the form:
+///
+/// ```toml
+/// max_attempts = 3
+/// rate_limit = 20
+/// quiet_hours = "22:00-07:00"
+/// quiet_hours_tz = "-05:00"
+///
+/// [[backend]]
+/// type = "slack"
+/// webhook = "https://hooks.slack.com/services/..."
+/// min_severity = "critical"
+/// ```
+#[derive(Default, Deserialize)]
+pub struct NotifierConfig {
+    max_attempts: Option<u32>,
+    /// Backends sent a notification at once. `0` or unset sends to every backend at once
+    max_concurrency: Option<usize>,
+    rate_limit: Option<u32>,
+    quiet_hours: Option<String>,
+    quiet_hours_tz: Option<String>,
+    /// Proxy for outbound requests in place of `HTTPS_PROXY` and `HTTP_PROXY`
+    proxy: Option<String>,
+    /// PEM file of root certificates trusted in addition to the system's
+    ca_bundle: Option<PathBuf>,
+    #[serde(default)]
+    danger_accept_invalid_certs: bool,
+    #[serde(default, rename = "backend")]
+    backends: Vec<Config>,
+    #[serde(default, rename = "route")]
+    routes: Vec<RouteConfig>,
+    /// Names the votalizer instance at the start of each notification
+    #[serde(skip)]
+    instance_name: Option<String>,
+    #[serde(skip)]
+    templates: MessageTemplates,
+}
+
+/// Backends that receive notifications concerning the listed vote accounts in place of the
+/// default backends
+#[derive(Deserialize)]
+struct RouteConfig {
+    #[serde(deserialize_with = "deserialize_pubkeys")]
+    vote_accounts: Vec<Pubkey>,
+    #[serde(rename = "backend")]
+    backends: Vec<Config>,
+}
+
+fn deserialize_pubkeys<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Pubkey>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pubkey| {
+            pubkey
+                .parse()
+                .map_err(|err| D::Error::custom(format!("invalid pubkey {}: {}", pubkey, err)))
+        })
+        .collect()
+}
+
+impl NotifierConfig {
+    pub fn from_env() -> Self {
+        for (feature, var, enabled) in BACKEND_FEATURES {
+            if !enabled
+                && (env::var_os(var).is_some() || env::var_os(format!("{}_FILE", var)).is_some())
+            {
+                warn!(
+                    "{} is set, but votalizer was built without the {} feature, ignoring",
+                    var, feature
+                );
+            }
+        }
+
         let mut configs = vec![];
-        if let Ok(webhook) = env::var("SLACK_WEBHOOK") {
-            configs.push(Config::Slack { webhook });
+        #[cfg(feature = "slack")]
+        if let Some(webhook) = secret_from_env("SLACK_WEBHOOK") {
+            configs.push(Config::Slack {
+                webhook,
+                channel: env::var("SLACK_CHANNEL").ok(),
+                bot_token: secret_from_env("SLACK_BOT_TOKEN"),
+                upload_channel: env::var("SLACK_UPLOAD_CHANNEL").ok(),
+                min_severity: min_severity_from_env("SLACK"),
+            });
         }
-        if let Ok(webhook) = env::var("DISCORD_WEBHOOK") {
+        #[cfg(feature = "discord")]
+        if let Some(webhook) = secret_from_env("DISCORD_WEBHOOK") {
             configs.push(Config::Discord {
                 webhook,
-                username: env::var("DISCORD_USERNAME").unwrap_or("votalizer".to_string()),
+                username: env::var("DISCORD_USERNAME").unwrap_or(default_discord_username()),
+                min_severity: min_severity_from_env("DISCORD"),
             })
         }
-        Notifier {
-            client: Client::new(),
-            configs,
+        #[cfg(feature = "telegram")]
+        match (
+            secret_from_env("TELEGRAM_BOT_TOKEN"),
+            env::var("TELEGRAM_CHAT_ID").ok(),
+        ) {
+            (Some(bot_token), Some(chat_id)) => configs.push(Config::Telegram {
+                bot_token,
+                chat_id,
+                min_severity: min_severity_from_env("TELEGRAM"),
+            }),
+            (Some(_), None) => warn!("TELEGRAM_BOT_TOKEN set without TELEGRAM_CHAT_ID, ignoring"),
+            (None, Some(_)) => warn!("TELEGRAM_CHAT_ID set without TELEGRAM_BOT_TOKEN, ignoring"),
+            (None, None) => {}
+        }
+        #[cfg(feature = "pagerduty")]
+        if let Some(routing_key) = secret_from_env("PAGERDUTY_ROUTING_KEY") {
+            configs.push(Config::PagerDuty {
+                routing_key,
+                min_severity: min_severity_from_env("PAGERDUTY"),
+            });
+        }
+        #[cfg(feature = "email")]
+        if let Ok(smtp_host) = env::var("SMTP_HOST") {
+            let smtp_port = match env::var("SMTP_PORT").map(|port| port.parse::<u16>()) {
+                Ok(Ok(port)) => Some(port),
+                Ok(Err(err)) => {
+                    warn!("Invalid SMTP_PORT: {}", err);
+                    None
+                }
+                Err(_) => Some(default_smtp_port()),
+            };
+            match (smtp_port, env::var("EMAIL_FROM"), env::var("EMAIL_TO")) {
+                (Some(smtp_port), Ok(from), Ok(to)) => configs.push(Config::Email {
+                    smtp_host,
+                    smtp_port,
+                    username: env::var("SMTP_USERNAME").ok(),
+                    password: secret_from_env("SMTP_PASSWORD"),
+                    from,
+                    to: to.split(',').map(|to| to.trim().to_string()).collect(),
+                    min_severity: min_severity_from_env("EMAIL"),
+                }),
+                (None, _, _) => {}
+                _ => warn!("SMTP_HOST set without EMAIL_FROM and EMAIL_TO, ignoring"),
+            }
+        }
+        #[cfg(feature = "teams")]
+        if let Some(webhook) = secret_from_env("TEAMS_WEBHOOK") {
+            configs.push(Config::Teams {
+                webhook,
+                min_severity: min_severity_from_env("TEAMS"),
+            });
+        }
+        #[cfg(feature = "opsgenie")]
+        if let Some(api_key) = secret_from_env("OPSGENIE_API_KEY") {
+            configs.push(Config::Opsgenie {
+                api_key,
+                eu: env::var("OPSGENIE_EU").as_deref() == Ok("1"),
+                min_severity: min_severity_from_env("OPSGENIE"),
+            });
+        }
+        #[cfg(feature = "matrix")]
+        match (
+            env::var("MATRIX_HOMESERVER").ok(),
+            secret_from_env("MATRIX_ACCESS_TOKEN"),
+            env::var("MATRIX_ROOM_ID").ok(),
+        ) {
+            (Some(homeserver), Some(access_token), Some(room_id)) => configs.push(Config::Matrix {
+                homeserver,
+                access_token,
+                room_id,
+                min_severity: min_severity_from_env("MATRIX"),
+            }),
+            (None, None, None) => {}
+            _ => warn!(
+                "MATRIX_HOMESERVER, MATRIX_ACCESS_TOKEN and MATRIX_ROOM_ID must all be set, ignoring"
+            ),
+        }
+        #[cfg(feature = "webhook")]
+        if let Some(url) = secret_from_env("GENERIC_WEBHOOK_URL") {
+            let template =
+                env::var("GENERIC_WEBHOOK_TEMPLATE").unwrap_or_else(|_| default_webhook_template());
+            configs.push(Config::Webhook {
+                url,
+                template,
+                secret: secret_from_env("GENERIC_WEBHOOK_SECRET"),
+                min_severity: min_severity_from_env("GENERIC_WEBHOOK"),
+            });
+        }
+        #[cfg(feature = "sns")]
+        if let Ok(topic_arn) = env::var("SNS_TOPIC_ARN") {
+            configs.push(Config::Sns {
+                topic_arn,
+                region: env::var("AWS_REGION").ok(),
+                min_severity: min_severity_from_env("SNS"),
+            });
+        }
+        if env::var("NOTIFIER_STDOUT").as_deref() == Ok("1") {
+            configs.push(Config::Stdout {
+                min_severity: min_severity_from_env("NOTIFIER_STDOUT"),
+            });
+        }
+        NotifierConfig {
+            max_attempts: parse_env("NOTIFIER_MAX_ATTEMPTS"),
+            max_concurrency: parse_env("NOTIFIER_MAX_CONCURRENCY"),
+            rate_limit: parse_env("NOTIFIER_RATE_LIMIT"),
+            quiet_hours: env::var("NOTIFIER_QUIET_HOURS").ok(),
+            quiet_hours_tz: env::var("NOTIFIER_QUIET_HOURS_TZ").ok(),
+            // reqwest picks up the proxy environment variables itself
+            proxy: None,
+            ca_bundle: None,
+            danger_accept_invalid_certs: false,
+            backends: configs,
+            routes: vec![],
+            instance_name: None,
+            templates: MessageTemplates::default(),
         }
     }
 
-    pub async fn send(&self, msg: &str) {
-        for config in &self.configs {
-            let (webhook, data, service_name) = match config {
-                Config::Slack { webhook } => (webhook, json!({ "text": msg }), "Slack"),
-                Config::Discord { webhook, username } => (
-                    webhook,
-                    json!({ "username": username, "content": msg }),
-                    "Discord",
-                ),
-            };
+    /// Configuration that prints every notification to standard output, without rate limiting
+    pub fn stdout() -> Self {
+        NotifierConfig {
+            rate_limit: Some(0),
+            backends: vec![Config::Stdout { min_severity: None }],
+            ..NotifierConfig::default()
+        }
+    }
+
+    /// Sends requests through `proxy`, overriding the configured proxy
+    pub fn with_proxy(self, proxy: String) -> Self {
+        Self {
+            proxy: Some(proxy),
+            ..self
+        }
+    }
+
+    /// Trusts the root certificates in `ca_bundle`, overriding the configured bundle
+    pub fn with_ca_bundle(self, ca_bundle: PathBuf) -> Self {
+        Self {
+            ca_bundle: Some(ca_bundle),
+            ..self
+        }
+    }
+
+    /// Prefixes each notification with the name of the votalizer instance sending it
+    pub fn with_instance_name(self, instance_name: String) -> Self {
+        Self {
+            instance_name: Some(instance_name),
+            ..self
+        }
+    }
+
+    /// Words incident and status notifications with `templates`
+    pub fn with_message_templates(self, templates: MessageTemplates) -> Self {
+        Self { templates, ..self }
+    }
+
+    /// Accepts any TLS certificate, even expired or for another host
+    pub fn with_danger_accept_invalid_certs(self) -> Self {
+        Self {
+            danger_accept_invalid_certs: true,
+            ..self
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Unable to read {}: {}", path.display(), err))?;
+        toml::from_str(&contents).map_err(|err| format!("Invalid {}: {}", path.display(), err))
+    }
+}
+
+/// A notifier configured from the environment
+impl Default for Notifier {
+    fn default() -> Self {
+        Self::new(NotifierConfig::from_env())
+    }
+}
+
+impl Notifier {
+    /// Starts the task sending queued notifications. Must be called from within a tokio runtime
+    pub fn new(mut config: NotifierConfig) -> Self {
+        let templates = std::mem::take(&mut config.templates);
+        let backends = Arc::new(Backends::new(config));
+        let (queue, queued) = mpsc::channel(NOTIFICATION_QUEUE_DEPTH);
+        let dropped = Arc::<AtomicUsize>::default();
+        tokio::spawn(send_queued(backends.clone(), queued, dropped.clone()));
+        Self {
+            backends,
+            templates,
+            queue,
+            dropped,
+        }
+    }
+
+    /// Describes the configured backends, for logging at startup
+    pub fn summary(&self) -> String {
+        self.backends.summary()
+    }
+
+    /// Lists the configured backends with their severity filters, one per line, followed by the
+    /// backend types this build supports. Secrets in URLs are redacted
+    pub fn backend_listing(&self) -> String {
+        self.backends.listing()
+    }
+
+    /// The notifications each backend has sent and failed to send since startup, with its most
+    /// recent error
+    pub fn health(&self) -> String {
+        self.backends.health()
+    }
 
-            if let Err(err) = self.client.post(webhook).json(&data).send().await {
-                eprintln!("Failed to send {service_name} message: {:?}", err);
+    /// Whether no backend is configured, not even for a routed vote account
+    pub fn is_empty(&self) -> bool {
+        self.backends.configs.is_empty() && self.backends.routes.iter().all(Vec::is_empty)
+    }
+
+    /// Sends `msg`, describing an event that occurred at `timestamp`
+    pub fn send_with_severity(&self, msg: &str, severity: Severity, timestamp: DateTime<Utc>) {
+        self.enqueue(QueuedNotification {
+            vote_account_address: None,
+            msg: msg.to_string(),
+            severity,
+            timestamp,
+            incident: None,
+        })
+    }
+
+    /// Sends `msg` concerning `vote_account_address`, honoring any route configured for it
+    pub fn send_for_vote_account(
+        &self,
+        vote_account_address: &Pubkey,
+        msg: &str,
+        severity: Severity,
+        timestamp: DateTime<Utc>,
+    ) {
+        self.enqueue(QueuedNotification {
+            vote_account_address: Some(*vote_account_address),
+            msg: msg.to_string(),
+            severity,
+            timestamp,
+            incident: None,
+        })
+    }
+
+    /// Sends an incident notification with the incident report attached. Backends that
+    /// do not support file uploads receive the report inline, truncated
+    pub fn send_incident(
+        &self,
+        incident: Incident,
+        severity: Severity,
+        detected_at: DateTime<Utc>,
+    ) {
+        self.enqueue(QueuedNotification {
+            vote_account_address: Some(incident.vote_account),
+            msg: self.templates.incident(&incident),
+            severity,
+            timestamp: detected_at,
+            incident: Some(Box::new(incident)),
+        })
+    }
+
+    /// Sends `status_report`, worded by the status template with `fields`, the values of its
+    /// other placeholders
+    pub fn send_status(
+        &self,
+        status_report: &str,
+        fields: &[(&str, String)],
+        timestamp: DateTime<Utc>,
+    ) {
+        self.send_with_severity(
+            &self.templates.status(status_report, fields),
+            Severity::Info,
+            timestamp,
+        )
+    }
+
+    /// Sends a test message to every configured backend, regardless of severity filters or
+    /// quiet hours, bypassing the queue. Returns false if any backend could not be reached
+    pub async fn verify(&self) -> bool {
+        self.backends.verify().await
+    }
+
+    /// Sends an incident report written earlier, `contents` named `filename`, bypassing the
+    /// queue, quiet hours and the rate limit. Returns false if any backend failed to send it
+    pub async fn resend_incident_report(
+        &self,
+        vote_account_address: &Pubkey,
+        msg: &str,
+        severity: Severity,
+        detected_at: DateTime<Utc>,
+        filename: &str,
+        contents: &str,
+    ) -> bool {
+        self.backends
+            .deliver(&Notification {
+                vote_account_address: Some(vote_account_address),
+                msg,
+                severity,
+                timestamp: detected_at,
+                attachment: Some(Attachment { filename, contents }),
+                incident: None,
+            })
+            .await
+    }
+
+    /// Waits until the notifications queued so far have been sent
+    pub async fn flush(&self) {
+        let (reply, flushed) = oneshot::channel();
+        if self.queue.send(Queued::Flush(reply)).await.is_ok() {
+            let _ = flushed.await;
+        }
+    }
+
+    fn enqueue(&self, notification: QueuedNotification) {
+        match self.queue.try_send(Queued::Notification(notification)) {
+            Ok(()) => {}
+            // Reported by the task once it catches up
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(TrySendError::Closed(_)) => error!("Notifier task stopped, notification lost"),
+        }
+    }
+}
+
+// Sends queued notifications in order, each to every backend before the next
+async fn send_queued(
+    backends: Arc<Backends>,
+    mut queued: mpsc::Receiver<Queued>,
+    dropped: Arc<AtomicUsize>,
+) {
+    while let Some(queued) = queued.recv().await {
+        let notification = match queued {
+            Queued::Notification(notification) => notification,
+            Queued::Flush(reply) => {
+                let _ = reply.send(());
+                continue;
+            }
+        };
+        let dropped = dropped.swap(0, Ordering::Relaxed);
+        if dropped > 0 {
+            let msg = format!(
+                "{} notifications dropped, the notification queue was full",
+                dropped
+            );
+            warn!("{}", msg);
+            backends
+                .dispatch(&Notification {
+                    vote_account_address: None,
+                    msg: &msg,
+                    severity: Severity::Warning,
+                    timestamp: Utc::now(),
+                    attachment: None,
+                    incident: None,
+                })
+                .await;
+        }
+
+        let attachment = notification
+            .incident
+            .as_ref()
+            .map(|incident| (incident.filename(), incident.to_string()));
+        backends
+            .dispatch(&Notification {
+                vote_account_address: notification.vote_account_address.as_ref(),
+                msg: &notification.msg,
+                severity: notification.severity,
+                timestamp: notification.timestamp,
+                attachment: attachment
+                    .as_ref()
+                    .map(|(filename, contents)| Attachment { filename, contents }),
+                incident: notification.incident.as_deref(),
+            })
+            .await;
+    }
+}
+
+impl Backends {
+    fn new(config: NotifierConfig) -> Self {
+        if config.danger_accept_invalid_certs {
+            warn!(
+                "*** TLS certificate verification is disabled for notifier requests, \
+                anyone on the network path can intercept or forge them ***"
+            );
+        }
+        let client = build_client(&config).unwrap_or_else(|err| {
+            warn!("Invalid notifier client configuration, ignoring: {}", err);
+            Client::new()
+        });
+        let mut routes = vec![];
+        let mut route_by_vote_account = HashMap::new();
+        for route in config.routes {
+            for vote_account in route.vote_accounts {
+                if route_by_vote_account
+                    .insert(vote_account, routes.len())
+                    .is_some()
+                {
+                    warn!("{} appears in multiple notifier routes", vote_account);
+                }
+            }
+            routes.push(route.backends);
+        }
+
+        #[cfg(feature = "webhook")]
+        for backend in config.backends.iter().chain(routes.iter().flatten()) {
+            if let Config::Webhook { template, .. } = backend {
+                if let Err(err) = render_webhook_template(template, "") {
+                    warn!("Generic webhook template is not valid JSON: {}", err);
+                }
+            }
+        }
+        let max_attempts = match config.max_attempts {
+            Some(0) => {
+                warn!("Invalid max attempts, using {}", DEFAULT_MAX_ATTEMPTS);
+                DEFAULT_MAX_ATTEMPTS
+            }
+            Some(max_attempts) => max_attempts,
+            None => DEFAULT_MAX_ATTEMPTS,
+        };
+        let max_concurrency = config
+            .max_concurrency
+            .filter(|max_concurrency| *max_concurrency > 0);
+        let rate_limiter = match config.rate_limit {
+            Some(0) => None,
+            rate_limit => Some(Mutex::new(RateLimiter::new(
+                rate_limit.unwrap_or(DEFAULT_RATE_LIMIT),
+            ))),
+        };
+        let quiet_hours = config.quiet_hours.and_then(|window| {
+            QuietHours::parse(&window, config.quiet_hours_tz.as_deref())
+                .map_err(|err| warn!("Invalid quiet hours, ignoring: {}", err))
+                .ok()
+        });
+        Backends {
+            client,
+            configs: config.backends,
+            routes,
+            route_by_vote_account,
+            max_attempts,
+            max_concurrency,
+            rate_limiter,
+            quiet_hours,
+            next_txn_id: AtomicU64::new(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_millis() as u64,
+            ),
+            health: Mutex::default(),
+            instance_name: config.instance_name,
+        }
+    }
+
+    fn health(&self) -> String {
+        let health = self.health.lock().unwrap();
+        let configs = self
+            .configs
+            .iter()
+            .enumerate()
+            .map(|(index, config)| ((None, index), config))
+            .chain(self.routes.iter().enumerate().flat_map(|(route, configs)| {
+                configs
+                    .iter()
+                    .enumerate()
+                    .map(move |(index, config)| ((Some(route), index), config))
+            }))
+            .collect::<Vec<_>>();
+        if configs.is_empty() {
+            return "none".into();
+        }
+        configs
+            .iter()
+            .map(|(key, config)| {
+                let backend_health = health.get(key);
+                format!(
+                    "{}: {} sent, {} failed{}",
+                    config.description(),
+                    backend_health.map_or(0, |backend_health| backend_health.sent),
+                    backend_health.map_or(0, |backend_health| backend_health.failed),
+                    backend_health
+                        .and_then(|backend_health| backend_health.last_error.as_ref())
+                        .map(|last_error| format!(" (last error: {})", last_error))
+                        .unwrap_or_default()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+
+    fn summary(&self) -> String {
+        let mut summary = if self.configs.is_empty() {
+            "none".to_string()
+        } else {
+            self.configs
+                .iter()
+                .map(Config::description)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if !self.routes.is_empty() {
+            summary += &format!(
+                "; {} vote accounts routed to {} other backends",
+                self.route_by_vote_account.len(),
+                self.routes.iter().map(Vec::len).sum::<usize>()
+            );
+        }
+        summary
+    }
+
+    fn listing(&self) -> String {
+        let describe = |config: &Config| {
+            format!(
+                "  {}: {}",
+                config.description(),
+                config
+                    .min_severity()
+                    .map(|min_severity| format!("{} and above", min_severity.as_str()))
+                    .unwrap_or_else(|| "every severity".into())
+            )
+        };
+        let mut lines = vec![];
+        if self.configs.is_empty() {
+            lines.push("no backends configured".to_string());
+        } else {
+            lines.push("backends:".to_string());
+            lines.extend(self.configs.iter().map(describe));
+        }
+        for (index, route) in self.routes.iter().enumerate() {
+            let mut vote_accounts = self
+                .route_by_vote_account
+                .iter()
+                .filter(|(_, route_index)| **route_index == index)
+                .map(|(vote_account, _)| vote_account.to_string())
+                .collect::<Vec<_>>();
+            vote_accounts.sort();
+            lines.push(format!("backends for {}:", vote_accounts.join(", ")));
+            lines.extend(route.iter().map(describe));
+        }
+        let backend_types = |built: bool| {
+            BACKEND_FEATURES
+                .iter()
+                .filter(|(_, _, enabled)| *enabled == built)
+                .map(|(feature, _, _)| *feature)
+                .collect::<Vec<_>>()
+        };
+        lines.push(format!(
+            "supported backend types: {}",
+            backend_types(true)
+                .into_iter()
+                .chain(["stdout"])
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+        let unsupported = backend_types(false);
+        if !unsupported.is_empty() {
+            lines.push(format!("not built with: {}", unsupported.join(", ")));
+        }
+        lines.join("\n")
+    }
+
+    async fn verify(&self) -> bool {
+        let notification = Notification {
+            vote_account_address: None,
+            msg: "votalizer connectivity test",
+            severity: Severity::Info,
+            timestamp: Utc::now(),
+            attachment: None,
+            incident: None,
+        };
+
+        let mut verified = true;
+        for config in self.configs.iter().chain(self.routes.iter().flatten()) {
+            match self.send_with_retry(config, &notification).await {
+                Ok(()) => info!("{} notifier verified", config.service_name()),
+                Err(err) => {
+                    error!(
+                        "{} notifier verification failed: {}",
+                        config.service_name(),
+                        err
+                    );
+                    verified = false;
+                }
+            }
+        }
+        verified
+    }
+
+    async fn dispatch(&self, notification: &Notification<'_>) {
+        if self
+            .quiet_hours
+            .as_ref()
+            .is_some_and(|quiet_hours| quiet_hours.suppresses(notification.severity, Utc::now()))
+        {
+            debug!("Quiet hours, suppressing: {}", notification.msg);
+            return;
+        }
+
+        if let Some(rate_limiter) = &self.rate_limiter {
+            let acquired = rate_limiter.lock().unwrap().acquire(notification.severity);
+            match acquired {
+                Ok(0) => {}
+                Ok(suppressed) => {
+                    let msg = format!("{} notifications suppressed due to rate limit", suppressed);
+                    self.deliver(&Notification {
+                        vote_account_address: None,
+                        msg: &msg,
+                        severity: Severity::Warning,
+                        timestamp: Utc::now(),
+                        attachment: None,
+                        incident: None,
+                    })
+                    .await;
+                }
+                Err(()) => {
+                    debug!("Rate limited, suppressing: {}", notification.msg);
+                    return;
+                }
+            }
+        }
+
+        self.deliver(notification).await;
+    }
+
+    // Returns whether every backend sent the notification
+    async fn deliver(&self, notification: &Notification<'_>) -> bool {
+        let delivered = AtomicBool::new(true);
+        let route = notification
+            .vote_account_address
+            .and_then(|vote_account_address| self.route_by_vote_account.get(vote_account_address))
+            .copied();
+        let configs = route.map_or(&self.configs, |route| &self.routes[route]);
+        // Sent to the backends concurrently, so that a slow backend doesn't delay the others
+        stream::iter(configs.iter().enumerate().filter(|(_, config)| {
+            config
+                .min_severity()
+                .is_none_or(|min_severity| notification.severity >= min_severity)
+        }))
+        .for_each_concurrent(self.max_concurrency, |(index, config)| {
+            let delivered = &delivered;
+            async move {
+                let result = self.send_with_retry(config, notification).await;
+                let mut health = self.health.lock().unwrap();
+                let backend_health = health.entry((route, index)).or_default();
+                match result {
+                    Ok(()) => backend_health.sent += 1,
+                    Err(err) => {
+                        error!("Failed to send {} message: {}", config.service_name(), err);
+                        backend_health.failed += 1;
+                        backend_health.last_error = Some(err);
+                        delivered.store(false, Ordering::Relaxed);
+                    }
+                }
+            }
+        })
+        .await;
+        delivered.into_inner()
+    }
+
+    async fn send_with_retry(
+        &self,
+        config: &Config,
+        notification: &Notification<'_>,
+    ) -> Result<(), String> {
+        // Every notification is attributed to the instance, the notifier's own notices included
+        let msg;
+        let notification = &match &self.instance_name {
+            Some(instance_name) => {
+                msg = format!("[{}] {}", instance_name, notification.msg);
+                Notification {
+                    msg: &msg,
+                    ..*notification
+                }
+            }
+            None => *notification,
+        };
+        // Allocated once per message so that retries are deduplicated by the homeserver
+        let txn_id = self.next_txn_id.fetch_add(1, Ordering::Relaxed);
+        let mut attempt = 1;
+        loop {
+            match self.send_to(config, notification, txn_id).await {
+                Ok(()) => return Ok(()),
+                Err(err) if attempt < self.max_attempts => {
+                    let delay = INITIAL_RETRY_DELAY * 2u32.pow(attempt - 1);
+                    debug!(
+                        "{} message attempt {} failed: {}. Retrying in {:?}",
+                        config.service_name(),
+                        attempt,
+                        err,
+                        delay
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    // `txn_id` is only used by Matrix
+    #[cfg_attr(not(feature = "matrix"), allow(unused_variables))]
+    async fn send_to(
+        &self,
+        config: &Config,
+        notification: &Notification<'_>,
+        txn_id: u64,
+    ) -> Result<(), String> {
+        let request: RequestBuilder = match config {
+            #[cfg(feature = "slack")]
+            Config::Slack {
+                webhook,
+                channel,
+                bot_token,
+                upload_channel,
+                ..
+            } => match (
+                &notification.attachment,
+                bot_token,
+                upload_channel.as_ref().or(channel.as_ref()),
+            ) {
+                (Some(attachment), Some(bot_token), Some(upload_channel)) => {
+                    return self
+                        .slack_upload(bot_token, upload_channel, &notification.text(), attachment)
+                        .await
+                }
+                _ => {
+                    let mut payload = json!({ "text": notification.inline_text() });
+                    if let Some(channel) = channel {
+                        payload["channel"] = channel.as_str().into();
+                    }
+                    self.client.post(webhook).json(&payload)
+                }
+            },
+            #[cfg(feature = "discord")]
+            Config::Discord {
+                webhook, username, ..
+            } => match &notification.attachment {
+                Some(attachment) => {
+                    let mut payload =
+                        json!({ "username": username, "content": notification.text() });
+                    if let Some(incident) = notification.incident {
+                        payload["embeds"] = json!([discord_incident_embed(incident)]);
+                    }
+                    let file = Part::text(attachment.contents.to_string())
+                        .file_name(attachment.filename.to_string());
+                    let form = Form::new()
+                        .text("payload_json", payload.to_string())
+                        .part("files[0]", file);
+                    self.client.post(webhook).multipart(form)
+                }
+                None => self
+                    .client
+                    .post(webhook)
+                    .json(&json!({ "username": username, "content": notification.text() })),
+            },
+            #[cfg(feature = "telegram")]
+            Config::Telegram {
+                bot_token, chat_id, ..
+            } => self
+                .client
+                .post(format!(
+                    "https://api.telegram.org/bot{}/sendMessage",
+                    bot_token
+                ))
+                .json(&json!({ "chat_id": chat_id, "text": notification.inline_text() })),
+            #[cfg(feature = "pagerduty")]
+            Config::PagerDuty { routing_key, .. } => {
+                let mut data = json!({
+                    "routing_key": routing_key,
+                    "event_action": "trigger",
+                    "payload": {
+                        "summary": notification.text().chars().take(PAGERDUTY_MAX_SUMMARY_LEN).collect::<String>(),
+                        "source": "votalizer",
+                        "severity": notification.severity.as_str(),
+                        "timestamp": notification
+                            .timestamp
+                            .to_rfc3339_opts(SecondsFormat::Secs, true),
+                    },
+                });
+                data["dedup_key"] = json!(pagerduty_dedup_key(notification));
+                if let Some(attachment) = &notification.attachment {
+                    data["payload"]["custom_details"] =
+                        json!({ attachment.filename: attachment.contents });
+                }
+                self.client.post(PAGERDUTY_EVENTS_URL).json(&data)
+            }
+            #[cfg(feature = "email")]
+            Config::Email {
+                smtp_host,
+                smtp_port,
+                username,
+                password,
+                from,
+                to,
+                ..
+            } => {
+                return send_email(
+                    smtp_host,
+                    *smtp_port,
+                    username.as_ref().zip(password.as_ref()),
+                    from,
+                    to,
+                    &notification.inline_text(),
+                )
+                .await
+            }
+            #[cfg(feature = "teams")]
+            Config::Teams { webhook, .. } => self.client.post(webhook).json(&json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "summary": notification.text().lines().next().unwrap_or_default(),
+                "text": notification.inline_text(),
+            })),
+            #[cfg(feature = "opsgenie")]
+            Config::Opsgenie { api_key, eu, .. } => {
+                let mut data = json!({
+                    "message": notification.text().chars().take(OPSGENIE_MAX_MESSAGE_LEN).collect::<String>(),
+                    "description": notification.inline_text(),
+                    "priority": match notification.severity {
+                        Severity::Info => "P5",
+                        Severity::Warning => "P3",
+                        Severity::Critical => "P1",
+                    },
+                    "source": "votalizer",
+                });
+                // Opsgenie deduplicates open alerts sharing an alias
+                if let Some(vote_account_address) = notification.vote_account_address {
+                    data["alias"] = json!(vote_account_address.to_string());
+                }
+                self.client
+                    .post(if *eu {
+                        OPSGENIE_EU_ALERTS_URL
+                    } else {
+                        OPSGENIE_ALERTS_URL
+                    })
+                    .header(AUTHORIZATION, format!("GenieKey {}", api_key))
+                    .json(&data)
             }
+            #[cfg(feature = "matrix")]
+            Config::Matrix {
+                homeserver,
+                access_token,
+                room_id,
+                ..
+            } => {
+                let mut url = Url::parse(homeserver).map_err(|err| err.to_string())?;
+                url.path_segments_mut()
+                    .map_err(|_| format!("invalid homeserver URL: {}", homeserver))?
+                    .pop_if_empty()
+                    .extend(&[
+                        "_matrix",
+                        "client",
+                        "v3",
+                        "rooms",
+                        room_id,
+                        "send",
+                        "m.room.message",
+                        &txn_id.to_string(),
+                    ]);
+                self.client
+                    .put(url)
+                    .bearer_auth(access_token)
+                    .json(&json!({ "msgtype": "m.text", "body": notification.inline_text() }))
+            }
+            #[cfg(feature = "webhook")]
+            Config::Webhook {
+                url,
+                template,
+                secret,
+                ..
+            } => {
+                let body =
+                    render_webhook_template(template, &notification.inline_text())?.to_string();
+                let mut request = self
+                    .client
+                    .post(url)
+                    .header(CONTENT_TYPE, "application/json");
+                if let Some(secret) = secret {
+                    request =
+                        request.header(WEBHOOK_SIGNATURE_HEADER, hmac_sha256_hex(secret, &body));
+                }
+                request.body(body)
+            }
+            #[cfg(feature = "sns")]
+            Config::Sns {
+                topic_arn, region, ..
+            } => {
+                return publish_sns(
+                    topic_arn,
+                    region.as_deref(),
+                    &format!("votalizer {}", notification.severity.as_str()),
+                    &notification.inline_text(),
+                )
+                .await
+            }
+            Config::Stdout { .. } => {
+                println!(
+                    "[{}] {}",
+                    notification.severity.as_str(),
+                    notification.inline_text()
+                );
+                return Ok(());
+            }
+        };
+
+        // Unreachable when only backends that send without an HTTP request are built
+        #[allow(unreachable_code)]
+        let response = request.send().await.map_err(request_error)?;
+        check_response(response).await.map(|_| ())
+    }
+
+    #[cfg(feature = "slack")]
+    async fn slack_upload(
+        &self,
+        bot_token: &str,
+        channel: &str,
+        msg: &str,
+        attachment: &Attachment<'_>,
+    ) -> Result<(), String> {
+        let form = Form::new()
+            .text("channels", channel.to_string())
+            .text("initial_comment", msg.to_string())
+            .text("filename", attachment.filename.to_string())
+            .text("content", attachment.contents.to_string());
+        let response = self
+            .client
+            .post(SLACK_FILES_UPLOAD_URL)
+            .bearer_auth(bot_token)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(request_error)?;
+        let response = check_response(response)
+            .await?
+            .json::<serde_json::Value>()
+            .await
+            .map_err(request_error)?;
+
+        // Slack reports API errors with a 200 status and `"ok": false`
+        if response["ok"].as_bool() == Some(true) {
+            Ok(())
+        } else {
+            Err(format!("files.upload failed: {}", response["error"]))
+        }
+    }
+}
+
+pub fn incident_summary(incident: &Incident) -> String {
+    let mut summary = format!(
+        "{}{}: {} detected [{}]",
+        incident.validator_name(),
+        incident
+            .validator_info
+            .as_ref()
+            .map(|validator_info| format!(" ({})", validator_info))
+            .unwrap_or_default(),
+        incident.kind.title(),
+        incident.signature
+    );
+    if let Some(late_backfill) = incident.late_backfill() {
+        summary += &format!(
+            " (late backfill: lockout expired {:.1}s earlier)",
+            late_backfill.as_secs_f64()
+        );
+    }
+    if incident.suppressed_incidents > 0 {
+        summary += &format!(
+            " ({} additional incidents suppressed)",
+            incident.suppressed_incidents
+        );
+    }
+    summary
+}
+
+#[cfg(feature = "discord")]
+fn discord_incident_embed(incident: &Incident) -> serde_json::Value {
+    let mut fields = vec![
+        json!({ "name": "Validator", "value": incident.validator_name() }),
+        json!({ "name": "Vote slot", "value": incident.vote_slot.to_string(), "inline": true }),
+        json!({ "name": "Root slot", "value": incident.root_slot.to_string(), "inline": true }),
+        json!({ "name": "Slot age", "value": incident.slot_age().to_string(), "inline": true }),
+    ];
+    if let Some(validator_info) = &incident.validator_info {
+        fields.insert(
+            1,
+            json!({ "name": "Identity", "value": validator_info.identity.to_string() }),
+        );
+        fields.insert(
+            2,
+            json!({
+                "name": "Stake",
+                "value": format!("{:.0} SOL", lamports_to_sol(validator_info.activated_stake)),
+                "inline": true,
+            }),
+        );
+    }
+    match &incident.kind {
+        IncidentKind::LockoutViolation(violation) => fields.push(json!({
+            "name": "Last lockout slot",
+            "value": violation.last_lockout_slot.to_string(),
+            "inline": true,
+        })),
+        IncidentKind::NonMonotonicRoot { previous_root_slot } => fields.push(json!({
+            "name": "Previous root slot",
+            "value": previous_root_slot.to_string(),
+            "inline": true,
+        })),
+        IncidentKind::TowerRollback {
+            previous_root_slot,
+            previous_depth,
+            depth,
+        } => {
+            fields.push(json!({
+                "name": "Previous root slot",
+                "value": previous_root_slot
+                    .map(|previous_root_slot| previous_root_slot.to_string())
+                    .unwrap_or_else(|| "none".into()),
+                "inline": true,
+            }));
+            fields.push(json!({
+                "name": "Tower depth",
+                "value": format!("{} -> {}", previous_depth, depth),
+                "inline": true,
+            }));
+        }
+        IncidentKind::VoteBelowRoot => {}
+    }
+    fields.push(json!({ "name": "Signature", "value": incident.signature.to_string() }));
+
+    json!({
+        "title": format!("{} detected", incident.kind.title()),
+        "color": DISCORD_EMBED_COLOR_RED,
+        "fields": fields,
+    })
+}
+
+// The client for notifier requests. An explicit proxy is bypassed for hosts listed in
+// `NO_PROXY`, as reqwest only applies `NO_PROXY` to the proxies it finds in the environment
+fn build_client(config: &NotifierConfig) -> Result<Client, String> {
+    let mut builder = Client::builder();
+    if let Some(proxy) = &config.proxy {
+        let proxy = Url::parse(proxy).map_err(|err| format!("proxy {}: {}", proxy, err))?;
+        let no_proxy = env::var("NO_PROXY")
+            .or_else(|_| env::var("no_proxy"))
+            .unwrap_or_default();
+        builder = builder.proxy(Proxy::custom(move |url| {
+            let bypass = url
+                .host_str()
+                .is_some_and(|host| no_proxy_matches(&no_proxy, host));
+            (!bypass).then(|| proxy.clone())
+        }));
+    }
+    if let Some(ca_bundle) = &config.ca_bundle {
+        for certificate in load_ca_bundle(ca_bundle)? {
+            builder = builder.add_root_certificate(certificate);
+        }
+    }
+    builder
+        .danger_accept_invalid_certs(config.danger_accept_invalid_certs)
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+/// Reads every certificate in the PEM file at `path`
+pub fn load_ca_bundle(path: &Path) -> Result<Vec<Certificate>, String> {
+    const PEM_END: &str = "-----END CERTIFICATE-----";
+    let contents = fs::read_to_string(path)
+        .map_err(|err| format!("Unable to read {}: {}", path.display(), err))?;
+    // The TLS backend only parses the first certificate of a PEM block
+    let certificates = contents
+        .split_inclusive(PEM_END)
+        .filter(|pem| pem.contains(PEM_END))
+        .map(|pem| {
+            Certificate::from_pem(pem.as_bytes())
+                .map_err(|err| format!("Invalid certificate in {}: {}", path.display(), err))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if certificates.is_empty() {
+        return Err(format!("No certificates found in {}", path.display()));
+    }
+    Ok(certificates)
+}
+
+// Whether `host` is listed in `no_proxy`, a comma-separated list where `*` matches every host and
+// a domain matches itself and its subdomains
+fn no_proxy_matches(no_proxy: &str, host: &str) -> bool {
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().trim_start_matches('.'))
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            entry == "*"
+                || host.eq_ignore_ascii_case(entry)
+                || host
+                    .to_ascii_lowercase()
+                    .ends_with(&format!(".{}", entry.to_ascii_lowercase()))
+        })
+}
+
+// Groups the PagerDuty events of a notification into an incident. Votalizer never resolves them,
+// so each of its incidents is kept apart from the validator's earlier ones
+#[cfg(feature = "pagerduty")]
+fn pagerduty_dedup_key(notification: &Notification) -> String {
+    match (notification.incident, notification.vote_account_address) {
+        (Some(incident), _) => format!(
+            "votalizer-{}-{}-{}",
+            incident.vote_account,
+            incident.signature,
+            incident.kind.name()
+        ),
+        (None, Some(vote_account_address)) => format!("votalizer-{}", vote_account_address),
+        // Concerning no validator in particular, so grouped into a single incident
+        (None, None) => "votalizer-status".into(),
+    }
+}
+
+// The cause of a failed request, without its URL, as webhook and bot API URLs embed their secrets
+// and errors are logged and reported in the status of every backend
+fn request_error(err: reqwest::Error) -> String {
+    format!("{:?}", err.without_url())
+}
+
+// Fails with the status and body of an unsuccessful response, which services such as Slack and
+// Discord use to explain why a message was rejected
+async fn check_response(response: Response) -> Result<Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let body = body.trim();
+    if body.is_empty() {
+        Err(format!("HTTP {}", status))
+    } else {
+        Err(format!(
+            "HTTP {}: {}",
+            status,
+            body.chars().take(ERROR_BODY_MAX_LEN).collect::<String>()
+        ))
+    }
+}
+
+// Substitutes the JSON-escaped `msg` for each `{message}` placeholder in `template`
+#[cfg(feature = "webhook")]
+fn render_webhook_template(template: &str, msg: &str) -> Result<serde_json::Value, String> {
+    let escaped = serde_json::to_string(msg).map_err(|err| err.to_string())?;
+    let escaped = &escaped[1..escaped.len() - 1];
+    serde_json::from_str(&template.replace("{message}", escaped)).map_err(|err| err.to_string())
+}
+
+// Hex-encoded HMAC-SHA256 of `body`, keyed by `secret`
+#[cfg(feature = "webhook")]
+fn hmac_sha256_hex(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body.as_bytes());
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(feature = "email")]
+async fn send_email(
+    smtp_host: &str,
+    smtp_port: u16,
+    credentials: Option<(&String, &String)>,
+    from: &str,
+    to: &[String],
+    msg: &str,
+) -> Result<(), String> {
+    let subject = msg.lines().next().unwrap_or_default();
+    let from = from
+        .parse()
+        .map_err(|err| format!("invalid from address {}: {}", from, err))?;
+    let mut builder = Message::builder().from(from).subject(subject);
+    for to in to {
+        let to = to
+            .parse()
+            .map_err(|err| format!("invalid to address {}: {}", to, err))?;
+        builder = builder.to(to);
+    }
+    let email = builder
+        .body(msg.to_string())
+        .map_err(|err| err.to_string())?;
+
+    let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(smtp_host)
+        .map_err(|err| err.to_string())?
+        .port(smtp_port);
+    if let Some((username, password)) = credentials {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    transport
+        .build()
+        .send(email)
+        .await
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(feature = "sns")]
+async fn publish_sns(
+    topic_arn: &str,
+    region: Option<&str>,
+    subject: &str,
+    msg: &str,
+) -> Result<(), String> {
+    let region = match region {
+        Some(region) => region.parse::<Region>().map_err(|err| err.to_string())?,
+        None => Region::default(),
+    };
+    SnsClient::new(region)
+        .publish(PublishInput {
+            topic_arn: Some(topic_arn.to_string()),
+            subject: Some(subject.to_string()),
+            message: msg.to_string(),
+            ..PublishInput::default()
+        })
+        .await
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        // 2 of the 10 tokens are reserved for critical notifications
+        let mut rate_limiter = RateLimiter::new(10);
+        for _ in 0..8 {
+            assert_eq!(rate_limiter.acquire(Severity::Warning), Ok(0));
+        }
+        assert_eq!(rate_limiter.acquire(Severity::Warning), Err(()));
+        assert_eq!(rate_limiter.acquire(Severity::Info), Err(()));
+
+        // Critical notifications may use the reserve, reporting those suppressed meanwhile
+        assert_eq!(rate_limiter.acquire(Severity::Critical), Ok(2));
+        assert_eq!(rate_limiter.acquire(Severity::Critical), Ok(0));
+        assert_eq!(rate_limiter.acquire(Severity::Critical), Err(()));
+
+        // A token is refilled every 6 seconds
+        rate_limiter.last_refill -= Duration::from_secs(6);
+        assert_eq!(rate_limiter.acquire(Severity::Info), Err(()));
+        assert_eq!(rate_limiter.acquire(Severity::Critical), Ok(2));
+
+        // But never beyond the bucket's capacity
+        rate_limiter.last_refill -= Duration::from_secs(3600);
+        for _ in 0..8 {
+            assert!(rate_limiter.acquire(Severity::Info).is_ok());
+        }
+        assert_eq!(rate_limiter.acquire(Severity::Info), Err(()));
+    }
+
+    #[test]
+    fn test_quiet_hours() {
+        assert!(QuietHours::parse("22:00", None).is_err());
+        assert!(QuietHours::parse("22:00-25:00", None).is_err());
+        assert!(QuietHours::parse("22:00-06:00", Some("05:00")).is_err());
+        for offset in [
+            "",
+            " ",
+            "−05:00",
+            "+02:99",
+            "+-02:00",
+            "+24:00",
+            "+99999999:00",
+        ] {
+            assert!(parse_utc_offset(offset).is_err(), "{:?}", offset);
         }
+        assert_eq!(
+            parse_utc_offset(" +05:30 "),
+            Ok(FixedOffset::east_opt(5 * 3600 + 30 * 60).unwrap())
+        );
+        let time = |time| NaiveTime::parse_from_str(time, "%H:%M").unwrap();
+
+        let quiet_hours = QuietHours::parse("22:00-06:00", None).unwrap();
+        // The window wraps past midnight, starting at its first minute and ending before its last
+        assert!(!quiet_hours.contains(time("21:59")));
+        assert!(quiet_hours.contains(time("22:00")));
+        assert!(quiet_hours.contains(time("00:00")));
+        assert!(quiet_hours.contains(time("05:59")));
+        assert!(!quiet_hours.contains(time("06:00")));
+        assert!(!quiet_hours.contains(time("12:00")));
+
+        let quiet_hours = QuietHours::parse(" 09:00 - 17:00 ", None).unwrap();
+        assert!(!quiet_hours.contains(time("08:59")));
+        assert!(quiet_hours.contains(time("09:00")));
+        assert!(quiet_hours.contains(time("16:59")));
+        assert!(!quiet_hours.contains(time("17:00")));
+        assert!(!quiet_hours.contains(time("23:00")));
+
+        // 23:30 UTC is 18:30 at -05:00
+        let quiet_hours = QuietHours::parse("18:00-07:00", Some("-05:00")).unwrap();
+        let now = DateTime::parse_from_rfc3339("2024-01-01T23:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert!(quiet_hours.suppresses(Severity::Info, now));
+        assert!(!quiet_hours.suppresses(Severity::Warning, now));
+        assert!(!quiet_hours.suppresses(Severity::Critical, now));
+        let later = now + chrono::Duration::hours(13);
+        assert!(!quiet_hours.suppresses(Severity::Info, later));
+    }
+
+    #[test]
+    fn test_inline_text() {
+        let notification = |contents| Notification {
+            vote_account_address: None,
+            msg: "msg",
+            severity: Severity::Info,
+            timestamp: DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            attachment: Some(Attachment {
+                filename: "incident.log",
+                contents,
+            }),
+            incident: None,
+        };
+        // Multi-byte characters longer in bytes than the limit but not in characters
+        let contents = "é".repeat(INLINE_ATTACHMENT_MAX_LEN);
+        assert_eq!(
+            notification(&contents).inline_text(),
+            format!("[2024-01-01T00:00:00Z] msg\n\n{}", contents)
+        );
+
+        let contents = "é".repeat(INLINE_ATTACHMENT_MAX_LEN + 1);
+        assert_eq!(
+            notification(&contents).inline_text(),
+            format!(
+                "[2024-01-01T00:00:00Z] msg\n\n{}\n...(truncated)",
+                "é".repeat(INLINE_ATTACHMENT_MAX_LEN)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_error() {
+        // Nothing listens on port 1
+        let err = Client::new()
+            .post("http://127.0.0.1:1/services/T000/B000/sekrit")
+            .send()
+            .await
+            .unwrap_err();
+        let err = request_error(err);
+        assert!(!err.contains("sekrit"), "{}", err);
+        assert!(err.contains("onnect"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_redact_client_error() {
+        let err = solana_client::nonblocking::rpc_client::RpcClient::new(
+            "http://127.0.0.1:1/sekrit".into(),
+        )
+        .get_vote_accounts()
+        .await
+        .unwrap_err();
+        let err = redact_client_error(err);
+        assert!(!err.contains("sekrit"), "{}", err);
+        assert!(err.contains("(http://127.0.0.1:1)"), "{}", err);
+        assert!(err.contains("onnect"), "{}", err);
+
+        let err = redact_client_error(
+            solana_client::client_error::ClientErrorKind::Custom("bad https://a/b:c".into()).into(),
+        );
+        assert_eq!(err, "Custom: bad https://a");
+    }
+
+    #[test]
+    fn test_no_proxy_matches() {
+        let no_proxy = "localhost, .example.com,internal.net,";
+        assert!(no_proxy_matches(no_proxy, "localhost"));
+        assert!(no_proxy_matches(no_proxy, "example.com"));
+        assert!(no_proxy_matches(no_proxy, "hooks.Example.com"));
+        assert!(no_proxy_matches(no_proxy, "a.b.internal.net"));
+        assert!(no_proxy_matches(no_proxy, "INTERNAL.NET"));
+        // Proxied
+        assert!(!no_proxy_matches(no_proxy, "hooks.slack.com"));
+        assert!(!no_proxy_matches(no_proxy, "notexample.com"));
+        assert!(!no_proxy_matches(no_proxy, "example.com.evil.io"));
+        assert!(!no_proxy_matches(no_proxy, "localhost.localdomain"));
+        assert!(!no_proxy_matches("", "localhost"));
+
+        assert!(no_proxy_matches("*", "hooks.slack.com"));
+        assert!(no_proxy_matches("example.com, *", "hooks.slack.com"));
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn test_hmac_sha256_hex() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hmac_sha256_hex("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[cfg(feature = "webhook")]
+    #[test]
+    fn test_render_webhook_template() {
+        let msg = "Lockout violation by \"v1\"\n\tC:\\votes";
+        assert_eq!(
+            render_webhook_template(DEFAULT_WEBHOOK_TEMPLATE, msg).unwrap(),
+            serde_json::json!({ "text": msg })
+        );
+        assert_eq!(
+            render_webhook_template(
+                r#"{"title": "votalizer", "body": "{message}", "copy": "{message}", "n": 1}"#,
+                msg
+            )
+            .unwrap(),
+            serde_json::json!({ "title": "votalizer", "body": msg, "copy": msg, "n": 1 })
+        );
+        assert!(render_webhook_template(r#"{"text": {message}}"#, msg).is_err());
+    }
+
+    #[cfg(feature = "pagerduty")]
+    #[test]
+    fn test_pagerduty_dedup_key() {
+        let vote_account_address = Pubkey::new_unique();
+        let incident = |signature| Incident {
+            kind: IncidentKind::VoteBelowRoot,
+            vote_account: vote_account_address,
+            signature,
+            vote_slot: 1,
+            root_slot: 2,
+            current_slot: 3,
+            suppressed_incidents: 0,
+            validator_info: None,
+            label: None,
+            instance_name: None,
+            epoch: None,
+            severity: 1,
+            tower: vec![],
+            vote_history: vec![],
+        };
+        let notification = |vote_account_address, incident| Notification {
+            vote_account_address,
+            msg: "msg",
+            severity: Severity::Critical,
+            timestamp: Utc::now(),
+            attachment: None,
+            incident,
+        };
+
+        // Each incident is kept apart from the validator's earlier ones
+        let signatures = [
+            solana_sdk::signature::Signature::new_unique(),
+            solana_sdk::signature::Signature::new_unique(),
+        ];
+        let (first, second) = (incident(signatures[0]), incident(signatures[1]));
+        assert_eq!(
+            pagerduty_dedup_key(&notification(Some(&vote_account_address), Some(&first))),
+            format!(
+                "votalizer-{}-{}-vote_below_root",
+                vote_account_address, signatures[0]
+            )
+        );
+        assert_ne!(
+            pagerduty_dedup_key(&notification(Some(&vote_account_address), Some(&first))),
+            pagerduty_dedup_key(&notification(Some(&vote_account_address), Some(&second)))
+        );
+
+        assert_eq!(
+            pagerduty_dedup_key(&notification(Some(&vote_account_address), None)),
+            format!("votalizer-{}", vote_account_address)
+        );
+        assert_eq!(
+            pagerduty_dedup_key(&notification(None, None)),
+            "votalizer-status"
+        );
+    }
+
+    #[cfg(feature = "pagerduty")]
+    #[test]
+    fn test_pagerduty_min_severity() {
+        let pagerduty = |min_severity| Config::PagerDuty {
+            routing_key: String::new(),
+            min_severity,
+        };
+        assert_eq!(pagerduty(None).min_severity(), Some(Severity::Critical));
+        assert_eq!(
+            pagerduty(Some(Severity::Info)).min_severity(),
+            Some(Severity::Info)
+        );
     }
 }
@@ -0,0 +1,389 @@
+use {
+    crate::vote::Vote,
+    futures_util::{
+        future::{ready, BoxFuture, FutureExt},
+        stream::{self, BoxStream},
+        SinkExt, StreamExt,
+    },
+    log::*,
+    rustls::{
+        client::{ServerCertVerified, ServerCertVerifier},
+        Certificate, ClientConfig, OwnedTrustAnchor, RootCertStore, ServerName,
+    },
+    serde::de::DeserializeOwned,
+    serde_json::{json, Value},
+    solana_client::rpc_response::{SlotInfo, SlotUpdate},
+    std::{
+        collections::HashMap,
+        fs::File,
+        io::BufReader,
+        path::Path,
+        sync::Arc,
+        time::{Instant, SystemTime},
+    },
+    tokio::{
+        net::TcpStream,
+        sync::{mpsc, oneshot},
+        task::JoinHandle,
+        time::{sleep, Duration},
+    },
+    tokio_tungstenite::{
+        connect_async_tls_with_config,
+        tungstenite::{
+            client::IntoClientRequest,
+            http::{
+                header::{HeaderName, HeaderValue, AUTHORIZATION},
+                StatusCode,
+            },
+            Error, Message,
+        },
+        Connector, MaybeTlsStream, WebSocketStream,
+    },
+};
+
+pub use solana_client::nonblocking::pubsub_client::{PubsubClientError, PubsubClientResult};
+
+/// How long the connection may be idle before it is pinged, to keep it open through proxies
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+pub type UnsubscribeFn = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+/// Notifications with the time each was received, to tell how far behind their consumer is
+type SubscribeResult<'a, T> = PubsubClientResult<(BoxStream<'a, (Instant, T)>, UnsubscribeFn)>;
+type SubscribeResponse =
+    PubsubClientResult<(mpsc::UnboundedReceiver<(Instant, Value)>, UnsubscribeFn)>;
+
+/// Extra HTTP headers sent when connecting, such as an RPC provider's API key
+pub type Headers = Vec<(HeaderName, HeaderValue)>;
+
+/// Parses a `KEY=VALUE` header
+pub fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = header
+        .split_once('=')
+        .ok_or_else(|| format!("Invalid header {:?}, expected KEY=VALUE", header))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .map_err(|err| format!("Invalid header name {:?}: {}", name, err))?;
+    let mut value = HeaderValue::from_str(value.trim())
+        .map_err(|err| format!("Invalid value for header {}: {}", name, err))?;
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// The `Authorization` header for a bearer token
+pub fn bearer_token_header(token: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {}", token.trim()))
+        .map_err(|err| format!("Invalid websocket token: {}", err))?;
+    value.set_sensitive(true);
+    Ok((AUTHORIZATION, value))
+}
+
+/// TLS settings of the connection to the RPC node, in place of trusting the bundled Mozilla roots
+pub type TlsConfig = Arc<ClientConfig>;
+
+/// The TLS settings for trusting the certificates in the PEM file `ca_bundle` in addition to the
+/// bundled Mozilla roots, or for accepting any certificate at all. `None` if neither is asked for
+pub fn tls_config(
+    ca_bundle: Option<&Path>,
+    danger_accept_invalid_certs: bool,
+) -> Result<Option<TlsConfig>, String> {
+    if ca_bundle.is_none() && !danger_accept_invalid_certs {
+        return Ok(None);
+    }
+    let mut root_store = RootCertStore::empty();
+    root_store.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(
+        |trust_anchor| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                trust_anchor.subject,
+                trust_anchor.spki,
+                trust_anchor.name_constraints,
+            )
+        },
+    ));
+    if let Some(ca_bundle) = ca_bundle {
+        let file = File::open(ca_bundle)
+            .map_err(|err| format!("Unable to read {}: {}", ca_bundle.display(), err))?;
+        let certificates = rustls_pemfile::certs(&mut BufReader::new(file))
+            .map_err(|err| format!("Unable to read {}: {}", ca_bundle.display(), err))?;
+        if certificates.is_empty() {
+            return Err(format!("No certificates found in {}", ca_bundle.display()));
+        }
+        for certificate in certificates {
+            root_store.add(&Certificate(certificate)).map_err(|err| {
+                format!("Invalid certificate in {}: {}", ca_bundle.display(), err)
+            })?;
+        }
+    }
+    let mut config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(root_store)
+        .with_no_client_auth();
+    if danger_accept_invalid_certs {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(AcceptAnyCertificate));
+    }
+    Ok(Some(Arc::new(config)))
+}
+
+// Accepts every server certificate, still checking that the server holds its key
+struct AcceptAnyCertificate;
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+/// The HTTP status of a connection rejected for its credentials, if that is why `err` occurred
+pub fn auth_rejection(err: &PubsubClientError) -> Option<StatusCode> {
+    match err {
+        PubsubClientError::ConnectionError(Error::Http(response))
+            if matches!(
+                response.status(),
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+            ) =>
+        {
+            Some(response.status())
+        }
+        _ => None,
+    }
+}
+
+enum Request {
+    Subscribe {
+        operation: &'static str,
+        response: oneshot::Sender<SubscribeResponse>,
+    },
+    Unsubscribe {
+        operation: &'static str,
+        subscription: u64,
+    },
+}
+
+/// A websocket pubsub client for the subscriptions the votalizer uses. Unlike `solana_client`'s,
+/// it sends extra headers when connecting, for RPC providers that require authentication, and
+/// may trust other certificates than the bundled Mozilla roots
+pub struct PubsubClient {
+    requests: mpsc::UnboundedSender<Request>,
+    ws: JoinHandle<()>,
+}
+
+impl Drop for PubsubClient {
+    fn drop(&mut self) {
+        // Ends the subscriptions, as dropping `solana_client`'s client does not
+        self.ws.abort();
+    }
+}
+
+impl PubsubClient {
+    pub async fn new(
+        url: &str,
+        headers: &Headers,
+        tls_config: Option<&TlsConfig>,
+    ) -> PubsubClientResult<Self> {
+        let mut request = url
+            .into_client_request()
+            .map_err(PubsubClientError::ConnectionError)?;
+        request.headers_mut().extend(headers.iter().cloned());
+        let connector = tls_config.map(|tls_config| Connector::Rustls(tls_config.clone()));
+        let (ws, _response) = connect_async_tls_with_config(request, None, connector)
+            .await
+            .map_err(PubsubClientError::ConnectionError)?;
+
+        let (requests, requests_receiver) = mpsc::unbounded_channel();
+        Ok(Self {
+            ws: tokio::spawn(Self::run_ws(ws, requests.clone(), requests_receiver)),
+            requests,
+        })
+    }
+
+    async fn subscribe<'a, T>(&self, operation: &'static str) -> SubscribeResult<'a, T>
+    where
+        T: DeserializeOwned + Send + 'a,
+    {
+        let (response, response_receiver) = oneshot::channel();
+        self.requests
+            .send(Request::Subscribe {
+                operation,
+                response,
+            })
+            .map_err(|err| PubsubClientError::ConnectionClosed(err.to_string()))?;
+        let (notifications, unsubscribe) = response_receiver
+            .await
+            .map_err(|err| PubsubClientError::ConnectionClosed(err.to_string()))??;
+        Ok((
+            stream::unfold(notifications, |mut notifications| async move {
+                notifications
+                    .recv()
+                    .await
+                    .map(|notification| (notification, notifications))
+            })
+            .filter_map(|(received_at, value)| {
+                ready(
+                    serde_json::from_value::<T>(value)
+                        .ok()
+                        .map(|notification| (received_at, notification)),
+                )
+            })
+            .boxed(),
+            unsubscribe,
+        ))
+    }
+
+    pub async fn slot_subscribe(&self) -> SubscribeResult<'_, SlotInfo> {
+        self.subscribe("slot").await
+    }
+
+    pub async fn slot_updates_subscribe(&self) -> SubscribeResult<'_, SlotUpdate> {
+        self.subscribe("slotsUpdates").await
+    }
+
+    pub async fn vote_subscribe(&self) -> SubscribeResult<'_, Vote> {
+        self.subscribe("vote").await
+    }
+
+    /// Relays requests to the websocket and notifications from it until it closes, ending the
+    /// subscriptions
+    async fn run_ws(
+        mut ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+        requests: mpsc::UnboundedSender<Request>,
+        mut requests_receiver: mpsc::UnboundedReceiver<Request>,
+    ) {
+        let mut request_id: u64 = 0;
+        let mut pending_subscriptions = HashMap::new();
+        let mut subscriptions = HashMap::<u64, mpsc::UnboundedSender<(Instant, Value)>>::new();
+
+        loop {
+            let request = tokio::select! {
+                () = sleep(KEEPALIVE_INTERVAL) => Some(Message::Ping(vec![])),
+                Some(request) = requests_receiver.recv() => {
+                    request_id += 1;
+                    Some(Message::Text(match request {
+                        Request::Subscribe { operation, response } => {
+                            pending_subscriptions.insert(request_id, (operation, response));
+                            json!({
+                                "jsonrpc": "2.0",
+                                "id": request_id,
+                                "method": format!("{}Subscribe", operation),
+                            })
+                            .to_string()
+                        }
+                        Request::Unsubscribe { operation, subscription } => {
+                            subscriptions.remove(&subscription);
+                            json!({
+                                "jsonrpc": "2.0",
+                                "id": request_id,
+                                "method": format!("{}Unsubscribe", operation),
+                                "params": [subscription],
+                            })
+                            .to_string()
+                        }
+                    }))
+                }
+                message = ws.next() => match message {
+                    Some(Ok(Message::Text(text))) => {
+                        let Ok(mut message) = serde_json::from_str::<Value>(&text) else {
+                            warn!("Ignoring malformed websocket message: {}", text);
+                            continue;
+                        };
+                        // A subscription notification
+                        if let Some(params) = message.get_mut("params") {
+                            let subscription = params.get("subscription").and_then(Value::as_u64);
+                            if let Some(notifications) =
+                                subscription.and_then(|subscription| subscriptions.get(&subscription))
+                            {
+                                let _ = notifications.send((Instant::now(), params["result"].take()));
+                            }
+                            continue;
+                        }
+                        // The response to a subscribe request
+                        let Some((operation, response)) = message
+                            .get("id")
+                            .and_then(Value::as_u64)
+                            .and_then(|id| pending_subscriptions.remove(&id))
+                        else {
+                            continue;
+                        };
+                        let result = match message["result"].as_u64() {
+                            Some(subscription) => {
+                                let (notifications, notifications_receiver) =
+                                    mpsc::unbounded_channel();
+                                subscriptions.insert(subscription, notifications);
+                                let requests = requests.clone();
+                                let unsubscribe: UnsubscribeFn = Box::new(move || {
+                                    // Nothing to do if the websocket already closed
+                                    let _ = requests.send(Request::Unsubscribe {
+                                        operation,
+                                        subscription,
+                                    });
+                                    ready(()).boxed()
+                                });
+                                Ok((notifications_receiver, unsubscribe))
+                            }
+                            None => Err(PubsubClientError::SubscribeFailed {
+                                reason: message["error"]["message"]
+                                    .as_str()
+                                    .unwrap_or("invalid `result` field")
+                                    .to_string(),
+                                message: text,
+                            }),
+                        };
+                        let _ = response.send(result);
+                        None
+                    }
+                    // Pings are answered by the websocket itself
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => None,
+                },
+            };
+            if let Some(request) = request {
+                if let Err(err) = ws.send(request).await {
+                    debug!("Websocket send failed: {}", err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_header() {
+        let (name, value) = parse_header("X-Api-Key = abc=123").unwrap();
+        assert_eq!(name, "x-api-key");
+        assert_eq!(value, "abc=123");
+        assert!(value.is_sensitive());
+
+        assert!(parse_header("X-Api-Key").is_err());
+        assert!(parse_header("X Api Key=abc").is_err());
+        assert!(parse_header("X-Api-Key=abc\n").is_ok());
+        assert!(parse_header("X-Api-Key=a\nb").is_err());
+
+        let (name, value) = bearer_token_header("sekrit\n").unwrap();
+        assert_eq!(name, AUTHORIZATION);
+        assert_eq!(value, "Bearer sekrit");
+    }
+
+    #[test]
+    fn test_tls_config() {
+        assert!(tls_config(None, false).unwrap().is_none());
+        assert!(tls_config(None, true).unwrap().is_some());
+
+        let ca_bundle = std::env::temp_dir().join(format!("ca-bundle-{}.pem", std::process::id()));
+        assert!(tls_config(Some(&ca_bundle), false).is_err());
+        std::fs::write(&ca_bundle, "not a certificate").unwrap();
+        let err = tls_config(Some(&ca_bundle), false).err().unwrap();
+        std::fs::remove_file(&ca_bundle).unwrap();
+        assert!(err.starts_with("No certificates found"));
+    }
+}
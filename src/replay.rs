@@ -0,0 +1,198 @@
+use {
+    crate::vote::Vote,
+    chrono::{DateTime, Utc},
+    futures_util::{
+        stream::{self, BoxStream},
+        StreamExt,
+    },
+    log::*,
+    serde::Deserialize,
+    serde_json::{json, Value},
+    solana_client::rpc_response::SlotInfo,
+    std::{
+        fs::{File, OpenOptions},
+        io::{BufRead, BufReader, LineWriter, Write},
+        path::{Path, PathBuf},
+        time::Duration,
+    },
+};
+
+/// A notification from the RPC node's pubsub service, received live or replayed from a file
+pub enum PubsubNotification {
+    Slot(SlotInfo),
+    Vote(Vote),
+}
+
+#[derive(Deserialize)]
+struct NotificationParams {
+    result: Value,
+}
+
+#[derive(Deserialize)]
+struct JsonRpcNotification {
+    method: String,
+    params: NotificationParams,
+    // Milliseconds since the UNIX epoch, added when recording
+    #[serde(rename = "receivedAt")]
+    received_at: Option<i64>,
+}
+
+impl PubsubNotification {
+    /// Parses a `slotNotification` or `voteNotification` in the JSON-RPC form sent over the
+    /// websocket, along with the time it was received if it was recorded by `Recorder`
+    pub fn parse(line: &str) -> Result<(Self, Option<i64>), String> {
+        let notification =
+            serde_json::from_str::<JsonRpcNotification>(line).map_err(|err| err.to_string())?;
+        let received_at = notification.received_at;
+        match notification.method.as_str() {
+            "slotNotification" => {
+                serde_json::from_value(notification.params.result).map(PubsubNotification::Slot)
+            }
+            "voteNotification" => {
+                serde_json::from_value(notification.params.result).map(PubsubNotification::Vote)
+            }
+            method => return Err(format!("unsupported method {}", method)),
+        }
+        .map(|notification| (notification, received_at))
+        .map_err(|err| err.to_string())
+    }
+
+    fn to_json(&self, received_at: DateTime<Utc>) -> Value {
+        let (method, result) = match self {
+            PubsubNotification::Slot(slot_info) => ("slotNotification", json!(slot_info)),
+            PubsubNotification::Vote(vote) => ("voteNotification", json!(vote)),
+        };
+        json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": { "result": result },
+            "receivedAt": received_at.timestamp_millis(),
+        })
+    }
+}
+
+/// Appends notifications to a file as they are received, one per line in the form read by `read`
+pub struct Recorder {
+    path: PathBuf,
+    // Flushed after every line, so that a crash leaves at most the last line incomplete
+    output: LineWriter<File>,
+}
+
+impl Recorder {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| format!("Unable to open {}: {}", path.display(), err))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            output: LineWriter::new(file),
+        })
+    }
+
+    pub fn record(&mut self, notification: &PubsubNotification, received_at: DateTime<Utc>) {
+        writeln!(self.output, "{}", notification.to_json(received_at))
+            .unwrap_or_else(|err| error!("Unable to write {}: {}", self.path.display(), err));
+    }
+}
+
+/// Reads the notifications in `path`, one per line, in the order they were received. Blank lines
+/// are skipped, as are malformed lines with a warning. If `realtime`, notifications are yielded
+/// as far apart as they were received, when recorded with their receive times
+pub fn read(path: &Path, realtime: bool) -> Result<BoxStream<'static, PubsubNotification>, String> {
+    let file =
+        File::open(path).map_err(|err| format!("Unable to open {}: {}", path.display(), err))?;
+    let path = path.to_path_buf();
+    let notifications =
+        BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter_map(move |(index, line)| {
+                let line = line
+                    .map_err(|err| warn!("Unable to read {}: {}", path.display(), err))
+                    .ok()?;
+                if line.trim().is_empty() {
+                    return None;
+                }
+                PubsubNotification::parse(&line)
+                    .map_err(|err| {
+                        warn!("Ignoring line {} of {}: {}", index + 1, path.display(), err)
+                    })
+                    .ok()
+            });
+    if !realtime {
+        return Ok(stream::iter(notifications.map(|(notification, _)| notification)).boxed());
+    }
+
+    let mut last_received_at = None;
+    Ok(stream::iter(notifications)
+        .then(move |(notification, received_at)| {
+            let delay =
+                last_received_at
+                    .zip(received_at)
+                    .map(|(last_received_at, received_at): (i64, i64)| {
+                        Duration::from_millis(
+                            received_at.saturating_sub(last_received_at).max(0) as u64
+                        )
+                    })
+                    .unwrap_or_default();
+            last_received_at = received_at.or(last_received_at);
+            async move {
+                tokio::time::sleep(delay).await;
+                notification
+            }
+        })
+        .boxed())
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, chrono::TimeZone};
+
+    #[test]
+    fn test_parse() {
+        match PubsubNotification::parse(
+            r#"{"jsonrpc":"2.0","method":"slotNotification","params":{"result":{"parent":1,"root":0,"slot":2},"subscription":0}}"#,
+        ) {
+            Ok((PubsubNotification::Slot(slot_info), None)) => {
+                assert_eq!(
+                    (slot_info.slot, slot_info.parent, slot_info.root),
+                    (2, 1, 0)
+                )
+            }
+            _ => panic!("expected a slot notification"),
+        }
+
+        match PubsubNotification::parse(
+            r#"{"jsonrpc":"2.0","method":"voteNotification","params":{"result":{"votePubkey":"Vote111111111111111111111111111111111111111","slots":[1,2],"hash":"11111111111111111111111111111111","timestamp":null,"signature":"1111111111111111111111111111111111111111111111111111111111111111"},"subscription":1}}"#,
+        ) {
+            Ok((PubsubNotification::Vote(vote), None)) => assert_eq!(vote.slots, vec![1, 2]),
+            _ => panic!("expected a vote notification"),
+        }
+
+        // A recorded notification round trips along with its receive time
+        let received_at = Utc.timestamp_millis(1_650_000_000_123);
+        match PubsubNotification::parse(
+            &PubsubNotification::Slot(SlotInfo {
+                slot: 3,
+                parent: 2,
+                root: 1,
+            })
+            .to_json(received_at)
+            .to_string(),
+        ) {
+            Ok((PubsubNotification::Slot(slot_info), Some(timestamp))) => {
+                assert_eq!(slot_info.slot, 3);
+                assert_eq!(timestamp, received_at.timestamp_millis());
+            }
+            _ => panic!("expected a recorded slot notification"),
+        }
+
+        assert!(PubsubNotification::parse(
+            r#"{"jsonrpc":"2.0","method":"rootNotification","params":{"result":1,"subscription":2}}"#
+        )
+        .is_err());
+        assert!(PubsubNotification::parse("not json").is_err());
+    }
+}
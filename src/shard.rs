@@ -0,0 +1,705 @@
+use {
+    crate::{
+        delinquency::VoteAccountStatuses, error_reporting::with_incident_scope, metrics::Metrics,
+        notifier::*, slot_tree::SlotTree, statsd::Statsd, tower::*,
+        validator_info::ValidatorDirectory, vote::Vote, VotalizerConfig,
+    },
+    chrono::Utc,
+    itertools::Itertools,
+    log::*,
+    serde_json::json,
+    solana_sdk::{
+        clock::Slot, hash::Hash, native_token::lamports_to_sol, pubkey::Pubkey,
+        signature::Signature,
+    },
+    std::{
+        collections::{HashMap, HashSet, VecDeque},
+        mem,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex, RwLock,
+        },
+        time::{Duration, Instant},
+    },
+    tokio::sync::{mpsc, oneshot},
+};
+
+/// Requests queued for a shard before the sender waits
+const SHARD_QUEUE_DEPTH: usize = 1024;
+
+/// Validators with the most incidents listed in the status report
+pub(crate) const TOP_OFFENDERS: usize = 5;
+
+/// Incidents remembered by each shard to suppress duplicate reports
+const REPORTED_INCIDENTS_CAPACITY: usize = 1024;
+
+/// State shared by `Votalizer` and every shard. Only `Votalizer` updates the slots
+pub(crate) struct Shared {
+    pub config: VotalizerConfig,
+    pub notifier: Notifier,
+    pub metrics: Arc<Metrics>,
+    pub statsd: Statsd,
+    pub slot_ancestors: RwLock<SlotTree>,
+    pub current_slot: AtomicU64,
+}
+
+/// A shard's contribution to the status report
+#[derive(Default)]
+pub(crate) struct ShardStatus {
+    pub validators: usize,
+    pub activated_stake: u64,
+    pub low_stake_validators: usize,
+    pub tower_memory: usize,
+    pub processed_votes: u64,
+    pub incidents: usize,
+    pub latency_sum: f64,
+    pub latency_count: usize,
+    pub latency_max: Slot,
+    /// Validators with the most incidents and their incident counts, most first
+    pub top_offenders: Vec<(Pubkey, u64)>,
+    /// Each validator's root slot and root advancement rate in slots per second
+    pub roots: Vec<(Pubkey, Slot, Option<f64>)>,
+}
+
+/// Called with a validator's tower, if it has one
+pub(crate) type TowerVisitor = Box<dyn FnOnce(Option<&Tower>) + Send>;
+
+pub(crate) enum Request {
+    Vote {
+        vote: Vote,
+        vote_account_address: Pubkey,
+        signature: Signature,
+    },
+    CheckLiveness {
+        now: Instant,
+        threshold: Duration,
+    },
+    /// Notifies of the validators that became delinquent, or stopped being, in the RPC node's view
+    CheckDelinquency(VoteAccountStatuses),
+    /// Evicts idle towers, then reports the shard's status
+    Status {
+        now: Instant,
+        reply: oneshot::Sender<ShardStatus>,
+    },
+    /// The number of votes processed and incidents observed
+    Counts(oneshot::Sender<(u64, usize)>),
+    WithTower(Pubkey, TowerVisitor),
+    SaveTowers(oneshot::Sender<Vec<(Pubkey, SavedTower)>>),
+}
+
+/// Owns the towers of the validators assigned to it, processing their votes in order. A
+/// validator's tower is independent of every other's, so shards process votes concurrently
+pub(crate) struct Shard {
+    shared: Arc<Shared>,
+    validator_directory: Option<ValidatorDirectory>,
+    // Validators whose votes are ignored for having less than the minimum stake
+    low_stake_validators: HashSet<Pubkey>,
+    towers: HashMap<Pubkey, Tower>,
+    processed_vote_counter: u64,
+    incident_counter: usize,
+    reported_incidents: ReportedIncidents,
+}
+
+/// The most recently reported incidents, by vote account, vote signature and kind, so that a vote
+/// processed again after its tower is rebuilt doesn't report the same incident twice
+struct ReportedIncidents {
+    reported: HashSet<(Pubkey, Signature, &'static str)>,
+    // Oldest first, to forget the oldest once `capacity` are remembered
+    order: VecDeque<(Pubkey, Signature, &'static str)>,
+    capacity: usize,
+}
+
+impl ReportedIncidents {
+    fn new(capacity: usize) -> Self {
+        Self {
+            reported: HashSet::new(),
+            order: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Remembers an incident of the kind named `kind`, returning `false` if it was already reported
+    fn insert(
+        &mut self,
+        vote_account_address: Pubkey,
+        signature: Signature,
+        kind: &'static str,
+    ) -> bool {
+        let key = (vote_account_address, signature, kind);
+        if !self.reported.insert(key) {
+            return false;
+        }
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.reported.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// The first pair of adjacent slots in `slots` that are out of order or repeated, if any. The vote
+/// program rejects such votes, so they never land
+fn unordered_vote_slots(slots: &[Slot]) -> Option<[Slot; 2]> {
+    slots
+        .windows(2)
+        .find(|pair| pair[0] >= pair[1])
+        .map(|pair| [pair[0], pair[1]])
+}
+
+/// Orders validators by incident count, most first, keeping the first `TOP_OFFENDERS`
+pub(crate) fn sort_offenders(offenders: &mut Vec<(Pubkey, u64)>) {
+    offenders.sort_unstable_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+    offenders.truncate(TOP_OFFENDERS);
+}
+
+/// Where a shard's requests are handled
+pub(crate) enum ShardHandle {
+    /// By the caller. A lone shard has no other to run concurrently with, so handing it each vote
+    /// through a queue would only add to the cost of processing the vote
+    Inline(Box<Mutex<Shard>>),
+    /// By the shard's own task, in the order queued
+    Task(mpsc::Sender<Request>),
+}
+
+impl ShardHandle {
+    /// Handles `request`, or queues it for the shard's task, waiting while the queue is full
+    pub async fn send(&self, request: Request) {
+        match self {
+            Self::Inline(shard) => shard.lock().unwrap().handle(request),
+            Self::Task(requests) => {
+                if requests.send(request).await.is_err() {
+                    error!("Vote processing shard exited");
+                }
+            }
+        }
+    }
+}
+
+/// Starts a shard owning `towers`. Unless `inline`, its requests are handled by a task of its
+/// own, which must be spawned from within a tokio runtime
+pub(crate) fn start(
+    shared: Arc<Shared>,
+    validator_directory: Option<ValidatorDirectory>,
+    towers: HashMap<Pubkey, Tower>,
+    inline: bool,
+) -> ShardHandle {
+    let shard = Shard {
+        shared,
+        validator_directory,
+        low_stake_validators: HashSet::new(),
+        towers,
+        processed_vote_counter: 0,
+        incident_counter: 0,
+        reported_incidents: ReportedIncidents::new(REPORTED_INCIDENTS_CAPACITY),
+    };
+    if inline {
+        return ShardHandle::Inline(Box::new(Mutex::new(shard)));
+    }
+    let (sender, requests) = mpsc::channel(SHARD_QUEUE_DEPTH);
+    tokio::spawn(shard.run(requests));
+    ShardHandle::Task(sender)
+}
+
+impl Shard {
+    async fn run(mut self, mut requests: mpsc::Receiver<Request>) {
+        while let Some(request) = requests.recv().await {
+            self.handle(request);
+        }
+    }
+
+    fn handle(&mut self, request: Request) {
+        match request {
+            Request::Vote {
+                vote,
+                vote_account_address,
+                signature,
+            } => self.process_vote(vote, vote_account_address, signature),
+            Request::CheckLiveness { now, threshold } => self.check_liveness(now, threshold),
+            Request::CheckDelinquency(vote_account_statuses) => {
+                self.check_delinquency(&vote_account_statuses)
+            }
+            Request::Status { now, reply } => {
+                let _ = reply.send(self.status(now));
+            }
+            Request::Counts(reply) => {
+                let _ = reply.send((self.processed_vote_counter, self.incident_counter));
+            }
+            Request::WithTower(vote_account_address, f) => {
+                f(self.towers.get(&vote_account_address))
+            }
+            Request::SaveTowers(reply) => {
+                let _ = reply.send(
+                    self.towers
+                        .iter()
+                        .map(|(vote_account_address, tower)| (*vote_account_address, tower.save()))
+                        .collect(),
+                );
+            }
+        }
+    }
+
+    fn check_liveness(&mut self, now: Instant, threshold: Duration) {
+        for (vote_account_address, tower) in self.towers.iter_mut() {
+            if let Some(silence) = tower.check_liveness(now, threshold) {
+                let msg = format!(
+                    "validator {} has not voted in {} seconds",
+                    self.shared.config.validator_name(vote_account_address),
+                    silence.as_secs()
+                );
+                warn!("{}", msg);
+                self.shared.notifier.send_for_vote_account(
+                    vote_account_address,
+                    &msg,
+                    Severity::Warning,
+                    Utc::now(),
+                );
+            }
+        }
+    }
+
+    fn check_delinquency(&mut self, vote_account_statuses: &VoteAccountStatuses) {
+        let describe = |delinquent: Option<bool>| match delinquent {
+            Some(true) => "delinquent",
+            Some(false) => "current",
+            None => "unknown",
+        };
+        for (vote_account_address, tower) in self.towers.iter_mut() {
+            // Not a vote account the RPC node knows of, as on a different cluster
+            let Some(status) = vote_account_statuses.get(vote_account_address) else {
+                continue;
+            };
+            if let Some(previous) = tower.record_delinquency(status.delinquent) {
+                let msg = format!(
+                    "validator {} is {} according to the RPC node, last voted on slot {} \
+                    (previously {})",
+                    self.shared.config.validator_name(vote_account_address),
+                    if status.delinquent {
+                        "delinquent"
+                    } else {
+                        "no longer delinquent"
+                    },
+                    status.last_vote,
+                    describe(previous)
+                );
+                warn!("{}", msg);
+                self.shared.notifier.send_for_vote_account(
+                    vote_account_address,
+                    &msg,
+                    Severity::Warning,
+                    Utc::now(),
+                );
+            }
+        }
+    }
+
+    fn status(&mut self, now: Instant) -> ShardStatus {
+        let config = &self.shared.config;
+        if let Some(tower_idle_timeout) = config.tower_idle_timeout {
+            // Evicting a tower within the incident cooldown would report its next incident early
+            let idle_timeout = tower_idle_timeout.max(config.incident_cooldown);
+            let tracked = self.towers.len();
+            self.towers
+                .retain(|_, tower| !tower.is_idle(now, idle_timeout));
+            if self.towers.len() < tracked {
+                info!(
+                    "Evicted {} towers idle for over {} seconds",
+                    tracked - self.towers.len(),
+                    idle_timeout.as_secs()
+                );
+            }
+        }
+
+        let mut status = ShardStatus {
+            validators: self.towers.len(),
+            low_stake_validators: self.low_stake_validators.len(),
+            tower_memory: self.towers.capacity() * mem::size_of::<(Pubkey, Tower)>()
+                + self.towers.values().map(Tower::heap_size).sum::<usize>(),
+            processed_votes: self.processed_vote_counter,
+            incidents: self.incident_counter,
+            ..ShardStatus::default()
+        };
+        for tower in self.towers.values() {
+            let vote_latency = tower.vote_latency();
+            if let (Some(mean), Some(max)) = (vote_latency.mean(), vote_latency.max()) {
+                status.latency_sum += mean;
+                status.latency_count += 1;
+                status.latency_max = status.latency_max.max(max);
+            }
+        }
+        status.top_offenders = self
+            .towers
+            .iter()
+            .filter(|(_, tower)| tower.incident_count() > 0)
+            .map(|(vote_account_address, tower)| (*vote_account_address, tower.incident_count()))
+            .collect();
+        sort_offenders(&mut status.top_offenders);
+        status.roots = self
+            .towers
+            .iter()
+            .filter_map(|(vote_account_address, tower)| {
+                let root_slot = tower.root_slot().filter(|root_slot| *root_slot > 0)?;
+                Some((
+                    *vote_account_address,
+                    root_slot,
+                    tower.root_advancement_rate(now),
+                ))
+            })
+            .collect();
+        // The stake of the validators looked up so far, if looking them up
+        if let Some(validator_directory) = &self.validator_directory {
+            status.activated_stake = self
+                .towers
+                .keys()
+                .filter_map(|vote_account_address| validator_directory.get(vote_account_address))
+                .map(|validator_info| validator_info.activated_stake)
+                .sum();
+        }
+        status
+    }
+
+    fn process_vote(&mut self, vote: Vote, vote_account_address: Pubkey, signature: Signature) {
+        let shared = &*self.shared;
+        let config = &shared.config;
+        let notifier = &shared.notifier;
+
+        if let Some(validator_directory) = self.validator_directory.as_mut() {
+            validator_directory.lookup(&vote_account_address);
+            // Votes are processed until the validator's stake is known
+            if let Some((min_stake, validator_info)) = config
+                .min_stake
+                .zip(validator_directory.get(&vote_account_address))
+            {
+                if validator_info.activated_stake < min_stake {
+                    if self.low_stake_validators.insert(vote_account_address) {
+                        debug!(
+                            "Ignoring {}, with {:.0} SOL staked",
+                            vote_account_address,
+                            lamports_to_sol(validator_info.activated_stake)
+                        );
+                        self.towers.remove(&vote_account_address);
+                    }
+                    return;
+                }
+                self.low_stake_validators.remove(&vote_account_address);
+            }
+        }
+        // Rejected before the validator is tracked or marked live, as such votes never land
+        if let Some(pair) = unordered_vote_slots(&vote.slots) {
+            let msg = format!(
+                "{}: Vote slots not strictly increasing, {:?}, in {}",
+                config.validator_name(&vote_account_address),
+                pair,
+                signature
+            );
+            warn!("{}", msg);
+            notifier.send_for_vote_account(
+                &vote_account_address,
+                &msg,
+                Severity::Warning,
+                Utc::now(),
+            );
+            return;
+        }
+        if let Some(root) = vote.misplaced_root() {
+            let msg = format!(
+                "{}: Vote root {} not below its first slot {}, in {}",
+                config.validator_name(&vote_account_address),
+                root,
+                vote.slots[0],
+                signature
+            );
+            warn!("{}", msg);
+            notifier.send_for_vote_account(
+                &vote_account_address,
+                &msg,
+                Severity::Warning,
+                Utc::now(),
+            );
+            return;
+        }
+
+        let tower = self
+            .towers
+            .entry(vote_account_address)
+            .or_insert_with(|| Tower::new(config.max_lockout_history));
+
+        if let Some(timestamp) = vote.timestamp {
+            // Only the ordering of replayed timestamps can be checked
+            let now = if config.replaying {
+                timestamp
+            } else {
+                Utc::now().timestamp()
+            };
+            if let Some(anomaly) = tower.check_timestamp(timestamp, now, config.timestamp_tolerance)
+            {
+                let msg = format!(
+                    "{}: Suspicious vote timestamp {}, {} [{}]",
+                    config.validator_name(&vote_account_address),
+                    timestamp,
+                    anomaly,
+                    signature
+                );
+                warn!("{}", msg);
+                notifier.send_for_vote_account(
+                    &vote_account_address,
+                    &msg,
+                    Severity::Warning,
+                    Utc::now(),
+                );
+            }
+        } else {
+            debug!("{} did not publish a timestamp", vote.vote_pubkey);
+        }
+        if let Some(silence) = tower.record_vote_time(Instant::now()) {
+            let msg = format!(
+                "validator {} resumed voting after {} seconds",
+                config.validator_name(&vote_account_address),
+                silence.as_secs()
+            );
+            info!("{}", msg);
+            notifier.send_for_vote_account(&vote_account_address, &msg, Severity::Info, Utc::now());
+        }
+
+        // Ignore votes for slots earlier than we already have votes for
+        let new_votes = vote
+            .slots
+            .iter()
+            .cloned()
+            .filter(|slot| {
+                tower
+                    .last_voted_slot()
+                    .is_none_or(|last_voted_slot| *slot > last_voted_slot)
+            })
+            .collect::<Vec<_>>();
+
+        match (vote.slots.last(), vote.hash.parse::<Hash>()) {
+            (Some(vote_slot), Ok(hash)) => {
+                if let Some(conflicting_signature) =
+                    tower.record_vote_hash(*vote_slot, hash, signature)
+                {
+                    let msg = format!(
+                        "{}: Conflicting votes for slot {} [{}] [{}]",
+                        config.validator_name(&vote_account_address),
+                        vote_slot,
+                        conflicting_signature,
+                        signature
+                    );
+                    warn!("{}", msg);
+                    notifier.send_for_vote_account(
+                        &vote_account_address,
+                        &msg,
+                        Severity::Warning,
+                        Utc::now(),
+                    );
+                }
+            }
+            (_, Err(err)) => warn!(
+                "{}: invalid vote hash {}: {}",
+                vote_account_address, vote.hash, err
+            ),
+            _ => {}
+        }
+
+        if let Some(vote_slot) = vote.slots.last() {
+            if let Some(mean) = tower.record_vote_latency(
+                *vote_slot,
+                shared.current_slot.load(Ordering::Relaxed),
+                config.max_vote_latency,
+            ) {
+                let msg = format!(
+                    "validator {} vote latency averaging {:.1} slots",
+                    config.validator_name(&vote_account_address),
+                    mean
+                );
+                warn!("{}", msg);
+                notifier.send_for_vote_account(
+                    &vote_account_address,
+                    &msg,
+                    Severity::Warning,
+                    Utc::now(),
+                );
+            }
+        }
+
+        if new_votes.is_empty() {
+            return;
+        }
+        if let Some(vote_gap) = config.max_vote_gap.and_then(|max_vote_gap| {
+            tower
+                .vote_gap(&new_votes, &shared.slot_ancestors.read().unwrap())
+                .filter(|vote_gap| vote_gap.skipped_slots > max_vote_gap)
+        }) {
+            let msg = format!(
+                "validator {} skipped voting on {} slots between slots {} and {} [{}]",
+                config.validator_name(&vote_account_address),
+                vote_gap.skipped_slots,
+                vote_gap.last_voted_slot,
+                vote_gap.vote_slot,
+                signature
+            );
+            warn!("{}", msg);
+            notifier.send_for_vote_account(
+                &vote_account_address,
+                &msg,
+                Severity::Warning,
+                Utc::now(),
+            );
+        }
+        event!(
+            Level::Trace,
+            "new_votes",
+            json!({
+                "vote_account": vote_account_address.to_string(),
+                "slots": new_votes,
+                "signature": signature.to_string(),
+            }),
+            "{:<44}: new votes: {} [{}]",
+            vote_account_address,
+            new_votes.iter().map(ToString::to_string).join(", "),
+            signature
+        );
+
+        tower.record_vote_signature(signature, new_votes.clone());
+        shared
+            .statsd
+            .count("votalizer.votes_processed", new_votes.len() as u64);
+
+        let last_vote_slot = new_votes.last().copied();
+        for slot in new_votes {
+            self.processed_vote_counter += 1;
+            shared
+                .metrics
+                .votes_processed
+                .fetch_add(1, Ordering::Relaxed);
+
+            let explain = config.explain.contains(&vote_account_address);
+            let current_slot = shared.current_slot.load(Ordering::Relaxed);
+            let incidents = tower.process_vote_slot(
+                &vote_account_address,
+                slot,
+                &signature,
+                &shared.slot_ancestors.read().unwrap(),
+                current_slot,
+                config.tower_reset_depth,
+                explain,
+            );
+            // The published root is the validator's once the whole vote is applied
+            let rollback = vote
+                .root
+                .filter(|_| Some(slot) == last_vote_slot)
+                .and_then(|root| {
+                    tower.reconcile_root(
+                        &vote_account_address,
+                        slot,
+                        &signature,
+                        root,
+                        current_slot,
+                        explain,
+                    )
+                });
+            for mut incident in incidents.into_iter().chain(rollback) {
+                incident.label = config.labels.get(&vote_account_address).cloned();
+                incident.instance_name = config.instance_name.clone();
+                if !self.reported_incidents.insert(
+                    vote_account_address,
+                    signature,
+                    incident.kind.name(),
+                ) {
+                    info!(
+                        "{} (duplicate incident suppressed)",
+                        incident_summary(&incident)
+                    );
+                    continue;
+                }
+                self.incident_counter += 1;
+                shared.metrics.incidents.fetch_add(1, Ordering::Relaxed);
+                shared.statsd.count("votalizer.incidents", 1);
+                let detected_at = Utc::now();
+                tower.record_incident(
+                    &incident,
+                    detected_at.timestamp(),
+                    config.max_incident_history,
+                );
+                let Some(suppressed_incidents) =
+                    tower.check_incident_cooldown(Instant::now(), config.incident_cooldown)
+                else {
+                    info!(
+                        "{} (suppressed by the incident cooldown)",
+                        incident_summary(&incident)
+                    );
+                    continue;
+                };
+                incident.suppressed_incidents = suppressed_incidents;
+                incident.epoch = config
+                    .epoch_schedule
+                    .as_ref()
+                    .map(|epoch_schedule| epoch_schedule.get_epoch(incident.vote_slot));
+                incident.validator_info = self
+                    .validator_directory
+                    .as_ref()
+                    .and_then(|validator_directory| validator_directory.get(&vote_account_address));
+
+                with_incident_scope(&incident, || {
+                    event!(
+                        Level::Error,
+                        "incident",
+                        incident.to_json(),
+                        "{}\n{}",
+                        incident_summary(&incident),
+                        incident
+                    );
+                    config.write_incident(&incident);
+                });
+                shared
+                    .metrics
+                    .record_incident(incident.summary_json(), detected_at);
+                // Late backfills are likely not genuine violations
+                let severity = if incident.severity >= config.critical_severity
+                    && incident.late_backfill().is_none()
+                {
+                    Severity::Critical
+                } else {
+                    Severity::Warning
+                };
+                notifier.send_incident(incident, severity, detected_at);
+            }
+        }
+        tower.record_root_time(Instant::now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unordered_vote_slots() {
+        assert_eq!(unordered_vote_slots(&[]), None);
+        assert_eq!(unordered_vote_slots(&[1]), None);
+        assert_eq!(unordered_vote_slots(&[1, 2, 5]), None);
+        assert_eq!(unordered_vote_slots(&[1, 2, 2, 5]), Some([2, 2]));
+        // Out of order slots beyond the first pair, which a pairwise check would miss
+        assert_eq!(unordered_vote_slots(&[1, 3, 2]), Some([3, 2]));
+    }
+
+    #[test]
+    fn test_reported_incidents() {
+        let mut reported_incidents = ReportedIncidents::new(3);
+        let vote_account_address = Pubkey::new_unique();
+        let signatures = [Signature::new_unique(), Signature::new_unique()];
+        let kind = "lockout_violation";
+        assert!(reported_incidents.insert(vote_account_address, signatures[0], kind));
+        assert!(!reported_incidents.insert(vote_account_address, signatures[0], kind));
+        assert!(reported_incidents.insert(Pubkey::new_unique(), signatures[0], kind));
+        // Another kind of incident caused by the same vote
+        assert!(reported_incidents.insert(vote_account_address, signatures[0], "tower_rollback"));
+
+        // The oldest incident is forgotten once the capacity is exceeded
+        assert!(reported_incidents.insert(vote_account_address, signatures[1], kind));
+        assert!(!reported_incidents.insert(vote_account_address, signatures[1], kind));
+        assert!(reported_incidents.insert(vote_account_address, signatures[0], kind));
+        assert_eq!(reported_incidents.reported.len(), 3);
+    }
+}
@@ -0,0 +1,293 @@
+use {
+    solana_sdk::clock::Slot,
+    std::{cmp::Ordering, collections::BTreeMap, time::Instant},
+};
+
+#[derive(Clone, Copy)]
+struct SlotNode {
+    // `None` for a slot only known as the parent of another
+    parent: Option<Slot>,
+    // Ancestors reachable through `parent`, at most `max_tracked_ancestors`
+    ancestor_count: usize,
+    // When the slot was first observed, as a slot or as a parent
+    observed_at: Instant,
+}
+
+/// The ancestry of recently observed slots, kept as parent pointers so that tracking a new slot
+/// takes constant time and memory however many ancestors it has. A slot's ancestors are the
+/// nearest `max_tracked_ancestors` slots reached through its parents, including a parent that is
+/// no longer tracked itself
+#[derive(Clone)]
+pub struct SlotTree {
+    slots: BTreeMap<Slot, SlotNode>,
+    max_tracked_ancestors: usize,
+}
+
+/// The ancestry of a tracked slot. Answers questions about the slot's fork by walking its parents,
+/// without collecting the ancestors
+#[derive(Clone, Copy)]
+pub struct Ancestry<'a> {
+    slots: &'a BTreeMap<Slot, SlotNode>,
+    slot: Slot,
+    node: SlotNode,
+}
+
+impl<'a> Ancestry<'a> {
+    pub fn slot(&self) -> Slot {
+        self.slot
+    }
+
+    /// When the slot was first observed
+    pub fn observed_at(&self) -> Instant {
+        self.node.observed_at
+    }
+
+    /// The ancestors, nearest first
+    pub fn ancestors(&self) -> Ancestors<'a> {
+        Ancestors {
+            slots: self.slots,
+            next: self.node.parent,
+            remaining: self.node.ancestor_count,
+        }
+    }
+
+    /// Whether `candidate` is an ancestor. Only walks back as far as `candidate`
+    pub fn contains_ancestor(&self, candidate: Slot) -> bool {
+        self.ancestors()
+            .find(|ancestor| *ancestor <= candidate)
+            .is_some_and(|ancestor| ancestor == candidate)
+    }
+
+    /// Whether the ancestors reach back to `slot` or beyond, so that the fork is known from `slot`
+    /// on
+    pub fn reaches(&self, slot: Slot) -> bool {
+        self.ancestors().any(|ancestor| ancestor <= slot)
+    }
+
+    /// The newest slot that is an ancestor of both this slot and `other`, if the ancestors reach
+    /// back to one
+    pub fn common_ancestor(&self, other: &Ancestry) -> Option<Slot> {
+        let mut ancestors = self.ancestors();
+        let mut other_ancestors = other.ancestors();
+        let mut ancestor = ancestors.next()?;
+        let mut other_ancestor = other_ancestors.next()?;
+        // Both walks are newest first, so advance whichever is ahead until they meet
+        loop {
+            match ancestor.cmp(&other_ancestor) {
+                Ordering::Equal => return Some(ancestor),
+                Ordering::Greater => ancestor = ancestors.next()?,
+                Ordering::Less => other_ancestor = other_ancestors.next()?,
+            }
+        }
+    }
+}
+
+/// The ancestors of a slot, nearest first
+pub struct Ancestors<'a> {
+    slots: &'a BTreeMap<Slot, SlotNode>,
+    next: Option<Slot>,
+    remaining: usize,
+}
+
+impl Iterator for Ancestors<'_> {
+    type Item = Slot;
+
+    fn next(&mut self) -> Option<Slot> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let slot = self.next?;
+        self.remaining -= 1;
+        self.next = self.slots.get(&slot).and_then(|node| node.parent);
+        Some(slot)
+    }
+}
+
+impl SlotTree {
+    pub fn new(max_tracked_ancestors: usize) -> Self {
+        Self {
+            slots: BTreeMap::new(),
+            max_tracked_ancestors,
+        }
+    }
+
+    /// The number of tracked slots
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn contains(&self, slot: Slot) -> bool {
+        self.slots.contains_key(&slot)
+    }
+
+    /// Tracks `slot` as a child of `parent`, returning its number of ancestors, or `None` if it
+    /// is already tracked. An untracked parent is tracked too, without ancestors of its own
+    pub fn insert(&mut self, slot: Slot, parent: Slot) -> Option<usize> {
+        if self.slots.contains_key(&slot) {
+            return None;
+        }
+        let observed_at = Instant::now();
+        let parent_node = self.slots.entry(parent).or_insert(SlotNode {
+            parent: None,
+            ancestor_count: 0,
+            observed_at,
+        });
+        let ancestor_count = (parent_node.ancestor_count + 1).min(self.max_tracked_ancestors);
+        self.slots.insert(
+            slot,
+            SlotNode {
+                parent: Some(parent),
+                ancestor_count,
+                observed_at,
+            },
+        );
+        Some(ancestor_count)
+    }
+
+    /// Forgets the oldest slots until no more than `max_tracked_slots` are tracked
+    pub fn prune(&mut self, max_tracked_slots: usize) {
+        while self.slots.len() > max_tracked_slots {
+            self.slots.pop_first();
+        }
+    }
+
+    /// The ancestry of `slot`, or `None` if it is not tracked
+    pub fn ancestry(&self, slot: Slot) -> Option<Ancestry<'_>> {
+        Some(Ancestry {
+            slots: &self.slots,
+            slot,
+            node: *self.slots.get(&slot)?,
+        })
+    }
+
+    /// When the first tracked slot newer than `slot` was observed, on any fork: once the cluster
+    /// had moved past `slot` in real time. `None` if no newer slot is tracked
+    pub fn first_observed_after(&self, slot: Slot) -> Option<Instant> {
+        self.slots
+            .range(slot.saturating_add(1)..)
+            .map(|(_, node)| node.observed_at)
+            .min()
+    }
+
+    /// The ancestors of `slot`, nearest first, or `None` if it is not tracked
+    pub fn ancestors(&self, slot: Slot) -> Option<Ancestors<'_>> {
+        self.ancestry(slot).map(|ancestry| ancestry.ancestors())
+    }
+
+    /// Limits the ancestors of `slot` to the nearest `ancestor_count`, as if the rest had been
+    /// trimmed by the tracking limits
+    #[cfg(test)]
+    pub(crate) fn truncate_ancestors(&mut self, slot: Slot, ancestor_count: usize) {
+        let node = self.slots.get_mut(&slot).unwrap();
+        node.ancestor_count = node.ancestor_count.min(ancestor_count);
+    }
+
+    /// Overrides when `slot` was first observed
+    #[cfg(test)]
+    pub(crate) fn set_observed_at(&mut self, slot: Slot, observed_at: Instant) {
+        self.slots.get_mut(&slot).unwrap().observed_at = observed_at;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::time::Duration};
+
+    fn ancestors(slot_tree: &SlotTree, slot: Slot) -> Vec<Slot> {
+        slot_tree.ancestors(slot).unwrap().collect()
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut slot_tree = SlotTree::new(10);
+        assert_eq!(slot_tree.insert(1, 0), Some(1));
+        assert_eq!(slot_tree.insert(2, 1), Some(2));
+        assert_eq!(slot_tree.insert(4, 1), Some(2));
+        assert_eq!(slot_tree.insert(2, 1), None);
+        assert_eq!(slot_tree.len(), 4);
+
+        assert_eq!(ancestors(&slot_tree, 0), Vec::<Slot>::new());
+        assert_eq!(ancestors(&slot_tree, 2), vec![1, 0]);
+        assert_eq!(ancestors(&slot_tree, 4), vec![1, 0]);
+        assert!(slot_tree.ancestors(3).is_none());
+
+        let ancestry = slot_tree.ancestry(4).unwrap();
+        assert!(ancestry.contains_ancestor(1));
+        assert!(ancestry.contains_ancestor(0));
+        assert!(!ancestry.contains_ancestor(2));
+        assert!(!ancestry.contains_ancestor(4));
+        assert!(slot_tree.ancestry(3).is_none());
+    }
+
+    #[test]
+    fn test_common_ancestor() {
+        let mut slot_tree = SlotTree::new(10);
+        for slot in 1..=4 {
+            slot_tree.insert(slot, slot - 1);
+        }
+        slot_tree.insert(6, 2);
+        slot_tree.insert(7, 6);
+        let ancestry = |slot| slot_tree.ancestry(slot).unwrap();
+
+        assert_eq!(ancestry(4).common_ancestor(&ancestry(7)), Some(2));
+        assert_eq!(ancestry(7).common_ancestor(&ancestry(4)), Some(2));
+        assert_eq!(ancestry(4).common_ancestor(&ancestry(3)), Some(2));
+        assert_eq!(ancestry(4).common_ancestor(&ancestry(0)), None);
+
+        assert!(ancestry(7).reaches(2));
+        assert!(ancestry(7).reaches(1));
+        assert!(!ancestry(0).reaches(0));
+    }
+
+    #[test]
+    fn test_first_observed_after() {
+        let mut slot_tree = SlotTree::new(10);
+        for slot in 1..=4 {
+            slot_tree.insert(slot, slot - 1);
+        }
+        let now = Instant::now();
+        slot_tree.set_observed_at(3, now + Duration::from_secs(2));
+        slot_tree.set_observed_at(4, now + Duration::from_secs(1));
+
+        assert_eq!(
+            slot_tree.ancestry(3).unwrap().observed_at(),
+            now + Duration::from_secs(2)
+        );
+        assert_eq!(
+            slot_tree.first_observed_after(2),
+            Some(now + Duration::from_secs(1))
+        );
+        assert_eq!(
+            slot_tree.first_observed_after(3),
+            Some(now + Duration::from_secs(1))
+        );
+        assert_eq!(slot_tree.first_observed_after(4), None);
+    }
+
+    #[test]
+    fn test_tracking_limits() {
+        let mut slot_tree = SlotTree::new(3);
+        for slot in 1..=6 {
+            slot_tree.insert(slot, slot - 1);
+        }
+        assert_eq!(ancestors(&slot_tree, 6), vec![5, 4, 3]);
+        assert!(!slot_tree.ancestry(6).unwrap().contains_ancestor(2));
+        assert!(slot_tree.ancestry(6).unwrap().reaches(3));
+        assert!(!slot_tree.ancestry(6).unwrap().reaches(2));
+        assert!(slot_tree
+            .ancestry(6)
+            .unwrap()
+            .common_ancestor(&slot_tree.ancestry(2).unwrap())
+            .is_none());
+
+        // A parent that is no longer tracked is still an ancestor
+        slot_tree.prune(3);
+        assert!(!slot_tree.contains(3));
+        assert_eq!(ancestors(&slot_tree, 6), vec![5, 4, 3]);
+        assert_eq!(ancestors(&slot_tree, 4), vec![3]);
+    }
+}
@@ -0,0 +1,60 @@
+use {
+    log::*,
+    std::{
+        io,
+        net::{SocketAddr, UdpSocket},
+    },
+};
+
+/// Pushes metrics to a StatsD aggregator over UDP. Every method is a no-op when no aggregator is
+/// configured
+pub struct Statsd {
+    socket: Option<UdpSocket>,
+    // Prepended to every metric name
+    prefix: String,
+}
+
+impl Statsd {
+    /// Metric names are prefixed with `instance_name`, when set, to tell votalizers on the same
+    /// host apart
+    pub fn new(addr: Option<SocketAddr>, instance_name: Option<&str>) -> io::Result<Self> {
+        let socket = addr
+            .map(|addr| {
+                let bind_addr: SocketAddr = if addr.is_ipv4() {
+                    "0.0.0.0:0".parse().unwrap()
+                } else {
+                    "[::]:0".parse().unwrap()
+                };
+                let socket = UdpSocket::bind(bind_addr)?;
+                socket.connect(addr)?;
+                // Never hold up vote processing waiting on the aggregator
+                socket.set_nonblocking(true)?;
+                info!("Sending StatsD metrics to {}", addr);
+                Ok::<_, io::Error>(socket)
+            })
+            .transpose()?;
+        Ok(Self {
+            socket,
+            prefix: instance_name
+                .map(|instance_name| format!("{}.", instance_name))
+                .unwrap_or_default(),
+        })
+    }
+
+    fn send(&self, name: &str, value: u64, kind: &str) {
+        if let Some(socket) = &self.socket {
+            let metric = format!("{}{}:{}|{}", self.prefix, name, value, kind);
+            if let Err(err) = socket.send(metric.as_bytes()) {
+                debug!("Unable to send StatsD metric {}: {}", name, err);
+            }
+        }
+    }
+
+    pub fn count(&self, name: &str, value: u64) {
+        self.send(name, value, "c");
+    }
+
+    pub fn gauge(&self, name: &str, value: u64) {
+        self.send(name, value, "g");
+    }
+}
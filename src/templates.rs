@@ -0,0 +1,226 @@
+//! Operator-supplied wording for notifications, for teams that want their own phrasing or emoji
+use {
+    crate::{notifier::incident_summary, tower::Incident},
+    serde::Deserialize,
+    solana_sdk::native_token::lamports_to_sol,
+    std::{fs, path::Path},
+};
+
+/// Placeholders of the incident template
+pub const INCIDENT_FIELDS: &[&str] = &[
+    "summary",
+    "validator",
+    "vote_account",
+    "identity",
+    "stake",
+    "kind",
+    "signature",
+    "vote_slot",
+    "root_slot",
+    "current_slot",
+    "epoch",
+    "severity",
+    "suppressed_incidents",
+];
+
+/// Placeholders of the status template
+pub const STATUS_FIELDS: &[&str] = &[
+    "status",
+    "validators",
+    "votes_processed",
+    "incidents",
+    "epoch",
+];
+
+/// Message templates, loaded from a TOML file of the form:
+///
+/// ```toml
+/// incident = ":rotating_light: {validator}: {kind} at slot {vote_slot} [{signature}]"
+/// status = ":bar_chart: {status}"
+/// ```
+///
+/// Each `{name}` placeholder is replaced with the value of that field. A message without a
+/// template keeps the default wording, which is also available as `{summary}` and `{status}`
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MessageTemplates {
+    incident: Option<String>,
+    status: Option<String>,
+}
+
+impl MessageTemplates {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("Unable to read {}: {}", path.display(), err))?;
+        Self::parse(&contents).map_err(|err| format!("Invalid {}: {}", path.display(), err))
+    }
+
+    fn parse(contents: &str) -> Result<Self, String> {
+        let templates = toml::from_str::<Self>(contents).map_err(|err| err.to_string())?;
+        for (name, template, fields) in [
+            ("incident", &templates.incident, INCIDENT_FIELDS),
+            ("status", &templates.status, STATUS_FIELDS),
+        ] {
+            if let Some(placeholder) = template
+                .iter()
+                .flat_map(|template| placeholders(template))
+                .find(|placeholder| !fields.contains(placeholder))
+            {
+                return Err(format!(
+                    "unknown placeholder {{{}}} in the {} template, expected one of {}",
+                    placeholder,
+                    name,
+                    fields.join(", ")
+                ));
+            }
+        }
+        Ok(templates)
+    }
+
+    /// The notification message for `incident`
+    pub fn incident(&self, incident: &Incident) -> String {
+        let summary = incident_summary(incident);
+        let Some(template) = &self.incident else {
+            return summary;
+        };
+        render(
+            template,
+            &[
+                ("summary", summary),
+                ("validator", incident.validator_name()),
+                ("vote_account", incident.vote_account.to_string()),
+                (
+                    "identity",
+                    incident
+                        .validator_info
+                        .as_ref()
+                        .map(|validator_info| validator_info.identity.to_string())
+                        .unwrap_or_default(),
+                ),
+                (
+                    "stake",
+                    incident
+                        .validator_info
+                        .as_ref()
+                        .map(|validator_info| {
+                            format!("{:.0} SOL", lamports_to_sol(validator_info.activated_stake))
+                        })
+                        .unwrap_or_default(),
+                ),
+                ("kind", incident.kind.title().to_string()),
+                ("signature", incident.signature.to_string()),
+                ("vote_slot", incident.vote_slot.to_string()),
+                ("root_slot", incident.root_slot.to_string()),
+                ("current_slot", incident.current_slot.to_string()),
+                (
+                    "epoch",
+                    incident
+                        .epoch
+                        .map(|epoch| epoch.to_string())
+                        .unwrap_or_default(),
+                ),
+                ("severity", incident.severity.to_string()),
+                (
+                    "suppressed_incidents",
+                    incident.suppressed_incidents.to_string(),
+                ),
+            ],
+        )
+    }
+
+    /// The notification message for a status report, `fields` holding the values of
+    /// `STATUS_FIELDS` other than `status` itself
+    pub fn status(&self, status_report: &str, fields: &[(&str, String)]) -> String {
+        match &self.status {
+            None => status_report.to_string(),
+            Some(template) => render(
+                template,
+                &[("status", status_report.to_string())]
+                    .into_iter()
+                    .chain(fields.iter().cloned())
+                    .collect::<Vec<_>>(),
+            ),
+        }
+    }
+}
+
+// The names of the `{name}` placeholders in `template`
+fn placeholders(template: &str) -> impl Iterator<Item = &str> {
+    template.split('{').skip(1).filter_map(|rest| {
+        let (name, _) = rest.split_once('}')?;
+        (!name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+            .then_some(name)
+    })
+}
+
+// Substitutes the value of each `{name}` placeholder in `template`, leaving unknown ones as is
+fn render(template: &str, fields: &[(&str, String)]) -> String {
+    let mut message = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        message.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let field = rest.find('}').and_then(|end| {
+            fields
+                .iter()
+                .find(|(name, _)| *name == &rest[1..end])
+                .map(|(_, value)| (end, value))
+        });
+        match field {
+            Some((end, value)) => {
+                message.push_str(value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                message.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    message.push_str(rest);
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let fields = [("validator", "v1".to_string()), ("vote_slot", "42".into())];
+        assert_eq!(
+            render(":warning: {validator} voted on {vote_slot}", &fields),
+            ":warning: v1 voted on 42"
+        );
+        assert_eq!(
+            render("{validator}{validator} {unknown} {vote_slot", &fields),
+            "v1v1 {unknown} {vote_slot"
+        );
+        assert_eq!(render("{{validator}}", &fields), "{v1}");
+        assert_eq!(
+            placeholders("{a} {b_1} {} {not valid} {c").collect::<Vec<_>>(),
+            vec!["a", "b_1"]
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let templates =
+            MessageTemplates::parse(r#"status = "{validators} validators: {status}""#).unwrap();
+        assert!(templates.incident.is_none());
+        assert_eq!(
+            templates.status("all good", &[("validators", "3".into())]),
+            "3 validators: all good"
+        );
+        assert_eq!(
+            MessageTemplates::default().status("all good", &[]),
+            "all good"
+        );
+
+        let err = MessageTemplates::parse(r#"incident = "{validator} at {slot}""#)
+            .err()
+            .unwrap();
+        assert!(err.starts_with("unknown placeholder {slot} in the incident template"));
+        assert!(MessageTemplates::parse(r#"incidents = "{validator}""#).is_err());
+    }
+}
@@ -1,56 +1,998 @@
 use {
+    crate::{
+        slot_tree::{Ancestry, SlotTree},
+        validator_info::ValidatorInfo,
+    },
     itertools::Itertools,
     log::*,
-    solana_sdk::{clock::Slot, pubkey::Pubkey, signature::Signature},
+    serde::{Deserialize, Serialize},
+    serde_json::{json, Value},
+    solana_sdk::{
+        clock::{Epoch, Slot, UnixTimestamp},
+        hash::Hash,
+        pubkey::Pubkey,
+        signature::Signature,
+    },
     solana_vote_program::vote_state::{Lockout, MAX_LOCKOUT_HISTORY},
     std::{
-        collections::{BTreeMap, HashSet, VecDeque},
-        fmt::Write,
+        collections::{HashMap, VecDeque},
+        fmt,
+        fs::{self, File},
+        io::{BufReader, BufWriter},
+        mem,
+        path::Path,
+        time::{Duration, Instant},
     },
 };
 
-pub type Incident = String;
+pub struct TowerVote {
+    pub slot: Slot,
+    pub confirmation_count: u32,
+    pub last_locked_out_slot: Slot,
+    pub signature: Signature,
+    /// Whether the vote was still locked out at the vote slot of a lockout violation
+    pub locked_out: Option<bool>,
+}
+
+impl TowerVote {
+    fn new(lockout: &Lockout, signature: Signature) -> Self {
+        Self {
+            slot: lockout.slot,
+            confirmation_count: lockout.confirmation_count,
+            last_locked_out_slot: lockout.last_locked_out_slot(),
+            signature,
+            locked_out: None,
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        let mut vote = json!({
+            "slot": self.slot,
+            "confirmation_count": self.confirmation_count,
+            "last_locked_out_slot": self.last_locked_out_slot,
+            "signature": self.signature.to_string(),
+        });
+        if let Some(locked_out) = self.locked_out {
+            vote["locked_out"] = locked_out.into();
+        }
+        vote
+    }
+}
+
+fn vote_history_to_json<'a>(
+    vote_history: impl IntoIterator<Item = &'a (Signature, Vec<Slot>)>,
+) -> Vec<Value> {
+    vote_history
+        .into_iter()
+        .map(|(signature, slots)| {
+            json!({
+                "signature": signature.to_string(),
+                "slots": slots,
+            })
+        })
+        .collect()
+}
+
+/// A vote for a slot that is not a descendant of a slot the validator is still locked out on
+pub struct LockoutViolation {
+    pub last_lockout_slot: Slot,
+    /// Last slot that `last_lockout_slot` was locked out through
+    pub last_locked_out_slot: Slot,
+    /// Slots on the vote slot's fork, newest first, back to the common ancestor
+    pub vote_fork: Vec<Slot>,
+    /// Slots on the last lockout slot's fork, newest first, back to the common ancestor
+    pub lockout_fork: Vec<Slot>,
+    /// Ancestors common to both forks, newest first, back to the root slot
+    pub common_ancestors: Vec<Slot>,
+    /// First slot on the vote slot's fork after the common ancestor, where it diverged
+    pub vote_fork_point: Slot,
+    /// First slot on the last lockout slot's fork after the common ancestor
+    pub lockout_fork_point: Slot,
+    /// How long after the cluster moved past `last_locked_out_slot` the vote slot was first
+    /// observed, if it was observed late. The vote may have been cast for a slot backfilled once
+    /// the lockout had already expired in real time
+    pub late_backfill: Option<Duration>,
+}
+
+pub enum IncidentKind {
+    LockoutViolation(LockoutViolation),
+    /// A vote for a slot at or below the validator's root slot
+    VoteBelowRoot,
+    /// A vote that moved the validator's root slot backwards, or not at all. The previous root
+    /// slot is retained
+    NonMonotonicRoot {
+        previous_root_slot: Slot,
+    },
+    /// A vote that expired every vote of a deep tower at once, as if the tower was reset, or a
+    /// published root behind the previous root slot
+    TowerRollback {
+        previous_root_slot: Option<Slot>,
+        previous_depth: usize,
+        depth: usize,
+    },
+}
+
+impl IncidentKind {
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::LockoutViolation(_) => "Lockout violation",
+            Self::VoteBelowRoot => "Vote below root",
+            Self::NonMonotonicRoot { .. } => "Non-monotonic root",
+            Self::TowerRollback { .. } => "Tower reset/rollback",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::LockoutViolation(_) => "lockout_violation",
+            Self::VoteBelowRoot => "vote_below_root",
+            Self::NonMonotonicRoot { .. } => "non_monotonic_root",
+            Self::TowerRollback { .. } => "tower_rollback",
+        }
+    }
+}
+
+/// Severity of incidents that undo the validator's root, which is deeper than any lockout, in a
+/// tower of the default depth
+pub const ROOT_SEVERITY: u64 = MAX_LOCKOUT_HISTORY as u64 + 1;
+
+/// An entry in a tower's incident history
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IncidentRecord {
+    pub detected_at: UnixTimestamp,
+    pub kind: &'static str,
+    pub vote_slot: Slot,
+    pub severity: u64,
+}
+
+pub struct Incident {
+    pub kind: IncidentKind,
+    pub vote_account: Pubkey,
+    pub signature: Signature,
+    pub vote_slot: Slot,
+    pub root_slot: Slot,
+    /// The most recent slot observed when the incident was detected
+    pub current_slot: Slot,
+    /// Incidents for the validator suppressed by the incident cooldown since the last one was
+    /// reported
+    pub suppressed_incidents: u64,
+    /// The validator's identity and stake, when looked up
+    pub validator_info: Option<ValidatorInfo>,
+    /// The validator's friendly name, when labeled
+    pub label: Option<String>,
+    /// The name of the votalizer instance that detected the incident, when named
+    pub instance_name: Option<String>,
+    /// Epoch of the vote slot, when the epoch schedule is known
+    pub epoch: Option<Epoch>,
+    /// How alarming the incident is, higher being worse. For a lockout violation this is the
+    /// confirmation count of the deepest vote still locked out at the vote slot, plus the number
+    /// of slots on the abandoned fork since it diverged from the vote's fork. Other incidents undo
+    /// the validator's root, and score one more than the tower depth, `ROOT_SEVERITY` by default
+    pub severity: u64,
+    pub tower: Vec<TowerVote>,
+    pub vote_history: Vec<(Signature, Vec<Slot>)>,
+}
+
+/// The start of the name of every incident file written by the votalizer instance named
+/// `instance_name`, followed by `<VOTE_ACCOUNT_ADDRESS>-<SIGNATURE>`
+pub fn incident_file_prefix(instance_name: Option<&str>) -> String {
+    match instance_name {
+        Some(instance_name) => format!("{}-incident-", instance_name),
+        None => "incident-".into(),
+    }
+}
+
+impl Incident {
+    pub fn filename(&self) -> String {
+        format!(
+            "{}{}-{}.log",
+            incident_file_prefix(self.instance_name.as_deref()),
+            self.vote_account,
+            self.signature
+        )
+    }
+
+    pub fn json_filename(&self) -> String {
+        format!(
+            "{}{}-{}.json",
+            incident_file_prefix(self.instance_name.as_deref()),
+            self.vote_account,
+            self.signature
+        )
+    }
+
+    /// The validator's label followed by its vote account address, or the bare address if it is
+    /// not labeled
+    pub fn validator_name(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{} ({})", label, self.vote_account),
+            None => self.vote_account.to_string(),
+        }
+    }
+
+    /// How long the lockout had expired in real time when a lockout violation's vote slot was
+    /// first observed. Such incidents are likely late backfills rather than genuine violations
+    pub fn late_backfill(&self) -> Option<Duration> {
+        match &self.kind {
+            IncidentKind::LockoutViolation(violation) => violation.late_backfill,
+            _ => None,
+        }
+    }
+
+    /// How many slots the vote slot trails the current slot by. Large for votes on slots that
+    /// were backfilled rather than observed as they were produced
+    pub fn slot_age(&self) -> Slot {
+        self.current_slot.saturating_sub(self.vote_slot)
+    }
+
+    /// The gist of the incident, without its tower or vote history
+    pub fn summary_json(&self) -> Value {
+        let mut summary = json!({
+            "kind": self.kind.name(),
+            "vote_account": self.vote_account.to_string(),
+            "signature": self.signature.to_string(),
+            "vote_slot": self.vote_slot,
+            "root_slot": self.root_slot,
+            "current_slot": self.current_slot,
+            "severity": self.severity,
+        });
+        if let Some(label) = &self.label {
+            summary["label"] = label.as_str().into();
+        }
+        summary
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut incident = json!({
+            "kind": self.kind.name(),
+            "vote_account": self.vote_account.to_string(),
+            "signature": self.signature.to_string(),
+            "vote_slot": self.vote_slot,
+            "root_slot": self.root_slot,
+            "current_slot": self.current_slot,
+            "slot_age": self.slot_age(),
+            "suppressed_incidents": self.suppressed_incidents,
+            "severity": self.severity,
+            "tower": self.tower.iter().map(TowerVote::to_json).collect::<Vec<_>>(),
+            "vote_history": vote_history_to_json(&self.vote_history),
+        });
+        if let Some(label) = &self.label {
+            incident["label"] = label.as_str().into();
+        }
+        if let Some(instance_name) = &self.instance_name {
+            incident["instance"] = instance_name.as_str().into();
+        }
+        if let Some(validator_info) = &self.validator_info {
+            incident["identity"] = validator_info.identity.to_string().into();
+            incident["activated_stake"] = validator_info.activated_stake.into();
+        }
+        if let Some(epoch) = self.epoch {
+            incident["epoch"] = epoch.into();
+        }
+        if let IncidentKind::LockoutViolation(violation) = &self.kind {
+            incident["last_lockout_slot"] = violation.last_lockout_slot.into();
+            incident["last_locked_out_slot"] = violation.last_locked_out_slot.into();
+            incident["vote_fork"] = json!(violation.vote_fork);
+            incident["lockout_fork"] = json!(violation.lockout_fork);
+            incident["common_ancestors"] = json!(violation.common_ancestors);
+            incident["vote_fork_point"] = violation.vote_fork_point.into();
+            incident["lockout_fork_point"] = violation.lockout_fork_point.into();
+            if let Some(late_backfill) = violation.late_backfill {
+                incident["late_backfill_secs"] = late_backfill.as_secs_f64().into();
+            }
+        }
+        if let IncidentKind::NonMonotonicRoot { previous_root_slot } = &self.kind {
+            incident["previous_root_slot"] = (*previous_root_slot).into();
+        }
+        if let IncidentKind::TowerRollback {
+            previous_root_slot,
+            previous_depth,
+            depth,
+        } = &self.kind
+        {
+            incident["previous_root_slot"] = json!(previous_root_slot);
+            incident["previous_tower_depth"] = (*previous_depth).into();
+            incident["tower_depth"] = (*depth).into();
+        }
+        incident
+    }
+}
+
+impl fmt::Display for Incident {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {}",
+            self.kind.title().to_lowercase(),
+            self.validator_name()
+        )?;
+        if self.suppressed_incidents > 0 {
+            writeln!(
+                f,
+                "({} additional incidents suppressed)",
+                self.suppressed_incidents
+            )?;
+        }
+        if let Some(validator_info) = &self.validator_info {
+            writeln!(f, "validator: {}", validator_info)?;
+        }
+        if let Some(instance_name) = &self.instance_name {
+            writeln!(f, "instance: {}", instance_name)?;
+        }
+        writeln!(f, "severity: {}", self.severity)?;
+        writeln!(f, "signature: {}", self.signature)?;
+        writeln!(f, "vote slot: {}", self.vote_slot)?;
+        if let Some(epoch) = self.epoch {
+            writeln!(f, "epoch: {}", epoch)?;
+        }
+        writeln!(f, "root slot: {}", self.root_slot)?;
+        writeln!(
+            f,
+            "current slot: {} (vote slot age: {})",
+            self.current_slot,
+            self.slot_age()
+        )?;
+        match &self.kind {
+            IncidentKind::LockoutViolation(violation) => {
+                writeln!(f, "last lockout slot: {}", violation.last_locked_out_slot)?;
+                if let Some(late_backfill) = violation.late_backfill {
+                    writeln!(
+                        f,
+                        "late backfill: vote slot observed {:.1}s after the lockout expired",
+                        late_backfill.as_secs_f64()
+                    )?;
+                }
+                let locked_out = self
+                    .tower
+                    .iter()
+                    .filter(|vote| vote.locked_out == Some(true))
+                    .count();
+                let confirmation_counts = self.tower.iter().map(|vote| vote.confirmation_count);
+                writeln!(
+                    f,
+                    "lockouts at vote slot: {} locked out, {} expired, confirmation counts {}-{}",
+                    locked_out,
+                    self.tower.len() - locked_out,
+                    confirmation_counts.clone().min().unwrap_or_default(),
+                    confirmation_counts.max().unwrap_or_default(),
+                )?;
+            }
+            IncidentKind::NonMonotonicRoot { previous_root_slot } => {
+                writeln!(f, "previous root slot: {}", previous_root_slot)?
+            }
+            IncidentKind::TowerRollback {
+                previous_root_slot,
+                previous_depth,
+                depth,
+            } => {
+                writeln!(
+                    f,
+                    "previous root slot: {}",
+                    previous_root_slot
+                        .map(|previous_root_slot| previous_root_slot.to_string())
+                        .unwrap_or_else(|| "none".into())
+                )?;
+                writeln!(f, "tower depth: {} -> {}", previous_depth, depth)?;
+            }
+            IncidentKind::VoteBelowRoot => {}
+        }
+        writeln!(f, "tower:")?;
+        for vote in &self.tower {
+            writeln!(
+                f,
+                "  - {} (conf: {}), last lockout slot: {}{} [{}]",
+                vote.slot,
+                vote.confirmation_count,
+                vote.last_locked_out_slot,
+                match vote.locked_out {
+                    Some(true) => ", locked out",
+                    Some(false) => ", expired",
+                    None => "",
+                },
+                vote.signature
+            )?;
+        }
+
+        if let IncidentKind::LockoutViolation(violation) = &self.kind {
+            writeln!(
+                f,
+                "vote slot {} fork point: {}",
+                self.vote_slot, violation.vote_fork_point
+            )?;
+            writeln!(
+                f,
+                "lockout slot {} fork point: {}",
+                violation.last_lockout_slot, violation.lockout_fork_point
+            )?;
+            writeln!(
+                f,
+                "fork at vote slot {} to common ancestor:",
+                self.vote_slot
+            )?;
+            writeln!(
+                f,
+                "  - {}",
+                violation
+                    .vote_fork
+                    .iter()
+                    .map(ToString::to_string)
+                    .join(", ")
+            )?;
+            writeln!(
+                f,
+                "fork at lockout slot {} to common ancestor:",
+                violation.last_lockout_slot
+            )?;
+            writeln!(
+                f,
+                "  - {}",
+                violation
+                    .lockout_fork
+                    .iter()
+                    .map(ToString::to_string)
+                    .join(", ")
+            )?;
+
+            writeln!(f, "common fork ancestors:")?;
+            writeln!(
+                f,
+                "  - {}",
+                violation
+                    .common_ancestors
+                    .iter()
+                    .map(ToString::to_string)
+                    .join(", ")
+            )?;
+        }
+
+        writeln!(f, "vote transaction history:")?;
+        for (signature, slots) in &self.vote_history {
+            writeln!(
+                f,
+                " - {} [{}]",
+                slots.iter().map(ToString::to_string).join(", "),
+                signature
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Slots on a validator's fork that it skipped voting on between consecutive votes
+#[derive(Debug, PartialEq, Eq)]
+pub struct VoteGap {
+    pub last_voted_slot: Slot,
+    pub vote_slot: Slot,
+    /// Ancestors of `vote_slot` after `last_voted_slot` that were not voted on
+    pub skipped_slots: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TimestampAnomaly {
+    /// Seconds ahead of the local clock
+    Future(i64),
+    /// Seconds behind the local clock
+    Past(i64),
+    /// Seconds earlier than the validator's previous vote timestamp
+    NonMonotonic(i64),
+}
+
+impl fmt::Display for TimestampAnomaly {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Future(secs) => write!(f, "{}s in the future", secs),
+            Self::Past(secs) => write!(f, "{}s in the past", secs),
+            Self::NonMonotonic(secs) => write!(f, "{}s earlier than the previous vote", secs),
+        }
+    }
+}
+
+/// Number of recent votes remembered to detect conflicting votes
+const RECENT_VOTE_HASHES: usize = 64;
+
+/// Number of recent votes over which vote latency is averaged
+const VOTE_LATENCY_WINDOW: usize = 100;
+
+/// Number of recent root slot changes over which root advancement is measured
+const ROOT_HISTORY_WINDOW: usize = 32;
+
+/// Rolling record of how many slots behind the cluster a validator's votes are received
+#[derive(Default)]
+pub struct VoteLatency {
+    samples: VecDeque<Slot>,
+    exceeded: bool,
+}
+
+impl VoteLatency {
+    fn record(&mut self, latency: Slot) {
+        if self.samples.len() == VOTE_LATENCY_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(latency);
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        if self.samples.is_empty() {
+            None
+        } else {
+            Some(self.samples.iter().sum::<Slot>() as f64 / self.samples.len() as f64)
+        }
+    }
+
+    pub fn max(&self) -> Option<Slot> {
+        self.samples.iter().max().copied()
+    }
+
+    /// Returns the mean latency if it has just risen above `threshold`. Nothing is
+    /// returned until a full window of votes has been observed
+    fn check(&mut self, threshold: f64) -> Option<f64> {
+        let mean = self
+            .mean()
+            .filter(|_| self.samples.len() == VOTE_LATENCY_WINDOW)?;
+        let exceeded = mean > threshold;
+        let newly_exceeded = exceeded && !self.exceeded;
+        self.exceeded = exceeded;
+        Some(mean).filter(|_| newly_exceeded)
+    }
+}
+
+/// Incremented whenever the format of `SavedTower` changes
+const TOWER_SNAPSHOT_VERSION: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct TowerSnapshotHeader {
+    version: u32,
+    saved_at: UnixTimestamp,
+    // The deepest the towers may grow, a snapshot of other towers being meaningless
+    max_history: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SavedTower {
+    votes: Vec<(Lockout, Signature)>,
+    root_slot: Option<Slot>,
+    vote_history: Vec<(Signature, Vec<Slot>)>,
+}
+
+/// Writes `towers`, of at most `max_history` votes, to `path`, replacing any previous snapshot
+pub fn save_towers(
+    path: &Path,
+    towers: &HashMap<Pubkey, Tower>,
+    max_history: usize,
+    saved_at: UnixTimestamp,
+) -> Result<(), String> {
+    write_saved_towers(
+        path,
+        towers
+            .iter()
+            .map(|(vote_account_address, tower)| (*vote_account_address, tower.save()))
+            .collect(),
+        max_history,
+        saved_at,
+    )
+}
+
+/// Writes towers already saved by `Tower::save` to `path`, replacing any previous snapshot
+pub(crate) fn write_saved_towers(
+    path: &Path,
+    towers: Vec<(Pubkey, SavedTower)>,
+    max_history: usize,
+    saved_at: UnixTimestamp,
+) -> Result<(), String> {
+    let header = TowerSnapshotHeader {
+        version: TOWER_SNAPSHOT_VERSION,
+        saved_at,
+        max_history,
+    };
+
+    // Write to a temporary file first so that a crash never leaves a truncated snapshot behind
+    let tmp_path = path.with_extension("tmp");
+    let mut output = BufWriter::new(File::create(&tmp_path).map_err(|err| err.to_string())?);
+    bincode::serialize_into(&mut output, &header).map_err(|err| err.to_string())?;
+    bincode::serialize_into(&mut output, &towers).map_err(|err| err.to_string())?;
+    output
+        .into_inner()
+        .map_err(|err| err.to_string())?
+        .sync_all()
+        .map_err(|err| err.to_string())?;
+    fs::rename(&tmp_path, path).map_err(|err| err.to_string())
+}
+
+/// Reads the towers saved by `save_towers`, rejecting snapshots saved more than `max_age` seconds
+/// before `now`
+pub fn load_towers(
+    path: &Path,
+    now: UnixTimestamp,
+    max_age: i64,
+    max_history: usize,
+) -> Result<HashMap<Pubkey, Tower>, String> {
+    let mut input = BufReader::new(File::open(path).map_err(|err| err.to_string())?);
+    let header: TowerSnapshotHeader =
+        bincode::deserialize_from(&mut input).map_err(|err| err.to_string())?;
+    if header.version != TOWER_SNAPSHOT_VERSION {
+        return Err(format!(
+            "unsupported snapshot version {} (expected {})",
+            header.version, TOWER_SNAPSHOT_VERSION
+        ));
+    }
+    let age = now - header.saved_at;
+    if age > max_age {
+        return Err(format!("snapshot is {} seconds old", age));
+    }
+    if header.max_history != max_history {
+        return Err(format!(
+            "towers were saved with a maximum of {} votes (expected {})",
+            header.max_history, max_history
+        ));
+    }
+
+    let towers: Vec<(Pubkey, SavedTower)> =
+        bincode::deserialize_from(&mut input).map_err(|err| err.to_string())?;
+    Ok(towers
+        .into_iter()
+        .map(|(vote_account_address, saved_tower)| {
+            (vote_account_address, Tower::load(saved_tower, max_history))
+        })
+        .collect())
+}
 
 pub struct Tower {
     votes: VecDeque<(Lockout, Signature)>,
     root_slot: Option<Slot>,
     vote_history: VecDeque<(Signature, Vec<Slot>)>,
+    last_vote_time: Option<Instant>,
+    stale: bool,
+    // Whether the RPC node last reported the validator as delinquent, if it has yet
+    delinquent: Option<bool>,
+    last_timestamp: Option<UnixTimestamp>,
+    vote_latency: VoteLatency,
+    root_history: VecDeque<(Slot, Instant)>,
+    recent_vote_hashes: VecDeque<(Slot, Hash, Signature)>,
+    last_incident_time: Option<Instant>,
+    suppressed_incidents: u64,
+    incident_count: u64,
+    incident_history: VecDeque<IncidentRecord>,
+    created_at: Instant,
+    max_history: usize,
 }
 
 impl Default for Tower {
     fn default() -> Self {
+        Self::new(MAX_LOCKOUT_HISTORY)
+    }
+}
+
+impl Tower {
+    /// A tower that roots its oldest vote once it holds `max_history` votes, `MAX_LOCKOUT_HISTORY`
+    /// in the vote program
+    pub fn new(max_history: usize) -> Self {
         Self {
             votes: VecDeque::from(vec![
                 (Lockout::default(), Signature::default());
-                MAX_LOCKOUT_HISTORY
+                max_history
             ]),
             root_slot: None,
             vote_history: VecDeque::default(),
+            last_vote_time: None,
+            stale: false,
+            delinquent: None,
+            last_timestamp: None,
+            vote_latency: VoteLatency::default(),
+            root_history: VecDeque::default(),
+            recent_vote_hashes: VecDeque::default(),
+            last_incident_time: None,
+            suppressed_incidents: 0,
+            incident_count: 0,
+            incident_history: VecDeque::default(),
+            created_at: Instant::now(),
+            max_history,
+        }
+    }
+
+    pub(crate) fn save(&self) -> SavedTower {
+        SavedTower {
+            votes: self.votes.iter().cloned().collect(),
+            root_slot: self.root_slot,
+            vote_history: self.vote_history.iter().cloned().collect(),
+        }
+    }
+
+    fn load(saved_tower: SavedTower, max_history: usize) -> Self {
+        Self {
+            votes: saved_tower.votes.into(),
+            root_slot: saved_tower.root_slot,
+            vote_history: saved_tower.vote_history.into(),
+            ..Self::new(max_history)
         }
     }
-}
 
-impl Tower {
     fn last_lockout(&self) -> Option<&Lockout> {
         self.votes.back().map(|(lockout, _)| lockout)
     }
 
+    /// The number of votes in the tower, excluding the placeholders of a new tower
+    pub fn depth(&self) -> usize {
+        self.votes
+            .iter()
+            .filter(|(_, signature)| *signature != Signature::default())
+            .count()
+    }
+
     pub fn last_voted_slot(&self) -> Option<Slot> {
         self.last_lockout().map(|v| v.slot)
     }
 
+    /// Records that a vote was received at `now`. If the validator had been reported as stale,
+    /// returns how long it went without voting
+    pub fn record_vote_time(&mut self, now: Instant) -> Option<Duration> {
+        let silence = self
+            .last_vote_time
+            .filter(|_| self.stale)
+            .map(|last_vote_time| now.duration_since(last_vote_time));
+        self.last_vote_time = Some(now);
+        self.stale = false;
+        silence
+    }
+
+    /// Records an incident detected at `now`. Returns the number of incidents suppressed since the
+    /// last one was reported if this one should be reported, or `None` if it is suppressed because
+    /// the last was reported less than `cooldown` ago
+    pub fn check_incident_cooldown(&mut self, now: Instant, cooldown: Duration) -> Option<u64> {
+        if self
+            .last_incident_time
+            .is_some_and(|last_incident_time| now.duration_since(last_incident_time) < cooldown)
+        {
+            self.suppressed_incidents += 1;
+            return None;
+        }
+        self.last_incident_time = Some(now);
+        Some(std::mem::take(&mut self.suppressed_incidents))
+    }
+
+    /// Records `incident`, detected at `detected_at`, keeping the `max_history` most recent in the
+    /// incident history. Incidents suppressed by the cooldown are recorded too
+    pub fn record_incident(
+        &mut self,
+        incident: &Incident,
+        detected_at: UnixTimestamp,
+        max_history: usize,
+    ) {
+        self.incident_count += 1;
+        while self.incident_history.len() >= max_history.max(1) {
+            self.incident_history.pop_front();
+        }
+        if max_history > 0 {
+            self.incident_history.push_back(IncidentRecord {
+                detected_at,
+                kind: incident.kind.name(),
+                vote_slot: incident.vote_slot,
+                severity: incident.severity,
+            });
+        }
+    }
+
+    /// Number of incidents recorded since the tower was created
+    pub fn incident_count(&self) -> u64 {
+        self.incident_count
+    }
+
+    /// The most recent incidents, oldest first
+    pub fn incident_history(&self) -> &VecDeque<IncidentRecord> {
+        &self.incident_history
+    }
+
+    /// Whether the tower has seen neither a vote nor an incident for longer than `threshold`, so
+    /// that it may be evicted
+    pub fn is_idle(&self, now: Instant, threshold: Duration) -> bool {
+        let last_activity = self
+            .last_vote_time
+            .into_iter()
+            .chain(self.last_incident_time)
+            .fold(self.created_at, Instant::max);
+        now.duration_since(last_activity) > threshold
+    }
+
+    /// Estimated bytes allocated on the heap by the tower, excluding the tower itself
+    pub fn heap_size(&self) -> usize {
+        self.votes.capacity() * mem::size_of::<(Lockout, Signature)>()
+            + self.vote_history.capacity() * mem::size_of::<(Signature, Vec<Slot>)>()
+            + self
+                .vote_history
+                .iter()
+                .map(|(_, slots)| slots.capacity() * mem::size_of::<Slot>())
+                .sum::<usize>()
+            + self.vote_latency.samples.capacity() * mem::size_of::<Slot>()
+            + self.recent_vote_hashes.capacity() * mem::size_of::<(Slot, Hash, Signature)>()
+            + self.incident_history.capacity() * mem::size_of::<IncidentRecord>()
+    }
+
+    /// Returns the time since the last vote if it exceeds `threshold` and the validator has not
+    /// already been reported as stale
+    pub fn check_liveness(&mut self, now: Instant, threshold: Duration) -> Option<Duration> {
+        if self.stale {
+            return None;
+        }
+        let silence = now.duration_since(self.last_vote_time?);
+        if silence > threshold {
+            self.stale = true;
+            Some(silence)
+        } else {
+            None
+        }
+    }
+
+    /// Records whether the RPC node reports the validator as delinquent. Returns the previous
+    /// status, if any, when the validator has just become delinquent or stopped being delinquent
+    pub fn record_delinquency(&mut self, delinquent: bool) -> Option<Option<bool>> {
+        let previous = self.delinquent.replace(delinquent);
+        (previous != Some(delinquent) && (delinquent || previous.is_some())).then_some(previous)
+    }
+
+    /// Compares a vote timestamp against the local clock, `now`, and the validator's previous
+    /// vote timestamp. Deviations from the local clock within `tolerance` seconds are ignored
+    pub fn check_timestamp(
+        &mut self,
+        timestamp: UnixTimestamp,
+        now: UnixTimestamp,
+        tolerance: i64,
+    ) -> Option<TimestampAnomaly> {
+        if timestamp > now + tolerance {
+            // Don't record it, or every subsequent timestamp would appear non-monotonic
+            return Some(TimestampAnomaly::Future(timestamp - now));
+        }
+
+        let last_timestamp = self.last_timestamp.replace(timestamp);
+        if timestamp < now - tolerance {
+            Some(TimestampAnomaly::Past(now - timestamp))
+        } else {
+            last_timestamp
+                .filter(|last_timestamp| timestamp < *last_timestamp)
+                .map(|last_timestamp| TimestampAnomaly::NonMonotonic(last_timestamp - timestamp))
+        }
+    }
+
+    fn tower_votes(&self) -> Vec<TowerVote> {
+        self.votes
+            .iter()
+            .map(|(lockout, signature)| TowerVote::new(lockout, *signature))
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "root_slot": self.root_slot,
+            "votes": self.tower_votes().iter().map(TowerVote::to_json).collect::<Vec<_>>(),
+            "vote_history": vote_history_to_json(&self.vote_history),
+            "incident_count": self.incident_count,
+            "incident_history": self
+                .incident_history
+                .iter()
+                .map(|record| json!({
+                    "detected_at": record.detected_at,
+                    "kind": record.kind,
+                    "vote_slot": record.vote_slot,
+                    "severity": record.severity,
+                }))
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    /// Records that the vote transaction `signature` voted for `slot` with bank hash `hash`. If
+    /// the validator previously voted for `slot` with a different bank hash, returns the signature
+    /// of that conflicting vote.
+    ///
+    /// Another vote for the same slot and bank hash is not a conflict: validators re-sign and
+    /// resend their last vote when it fails to land
+    pub fn record_vote_hash(
+        &mut self,
+        slot: Slot,
+        hash: Hash,
+        signature: Signature,
+    ) -> Option<Signature> {
+        if let Some((_, recorded_hash, recorded_signature)) = self
+            .recent_vote_hashes
+            .iter()
+            .find(|(recorded_slot, _, _)| *recorded_slot == slot)
+        {
+            return (*recorded_hash != hash).then_some(*recorded_signature);
+        }
+
+        if self.recent_vote_hashes.len() == RECENT_VOTE_HASHES {
+            self.recent_vote_hashes.pop_front();
+        }
+        self.recent_vote_hashes.push_back((slot, hash, signature));
+        None
+    }
+
+    /// Measures the gap between the previous vote, from the vote history, and `new_votes`. The
+    /// gap only counts slots the validator could have voted on, ancestors of the newest vote that
+    /// it skipped. After a fork switch the previous vote is not an ancestor, and the slots skipped
+    /// were on a fork the validator was locked out of, so no gap is measured. Nor is one when the
+    /// tracked ancestry does not reach back to the previous vote
+    pub fn vote_gap(&self, new_votes: &[Slot], slot_ancestors: &SlotTree) -> Option<VoteGap> {
+        let last_voted_slot = *self.vote_history.back()?.1.last()?;
+        let vote_slot = *new_votes.last()?;
+        let vote_ancestry = slot_ancestors.ancestry(vote_slot)?;
+        if !vote_ancestry.contains_ancestor(last_voted_slot) {
+            return None;
+        }
+        Some(VoteGap {
+            last_voted_slot,
+            vote_slot,
+            skipped_slots: vote_ancestry
+                .ancestors()
+                .take_while(|slot| *slot > last_voted_slot)
+                .filter(|slot| !new_votes.contains(slot))
+                .count(),
+        })
+    }
+
+    pub fn vote_latency(&self) -> &VoteLatency {
+        &self.vote_latency
+    }
+
+    pub fn root_slot(&self) -> Option<Slot> {
+        self.root_slot
+    }
+
+    /// Records the time the root slot was first seen, if it changed since the last call. The
+    /// placeholder root of a tower yet to root a vote of its own is not recorded
+    pub fn record_root_time(&mut self, now: Instant) {
+        let Some(root_slot) = self.root_slot.filter(|root_slot| *root_slot > 0) else {
+            return;
+        };
+        if self
+            .root_history
+            .back()
+            .is_some_and(|(recorded_slot, _)| *recorded_slot == root_slot)
+        {
+            return;
+        }
+        if self.root_history.len() == ROOT_HISTORY_WINDOW {
+            self.root_history.pop_front();
+        }
+        self.root_history.push_back((root_slot, now));
+    }
+
+    /// Slots per second the root slot advanced over the recent root slot changes, up to `now`
+    /// so that a root that stops advancing slows the rate down. `None` until the root slot has
+    /// been recorded
+    pub fn root_advancement_rate(&self, now: Instant) -> Option<f64> {
+        let (first_slot, first_time) = self.root_history.front()?;
+        let (last_slot, _) = self.root_history.back()?;
+        let elapsed = now.duration_since(*first_time).as_secs_f64();
+        (elapsed > 0.).then(|| last_slot.saturating_sub(*first_slot) as f64 / elapsed)
+    }
+
+    /// Records the latency of a vote received while the cluster was at `current_slot`. Returns
+    /// the mean latency if it has just risen above `threshold`
+    pub fn record_vote_latency(
+        &mut self,
+        vote_slot: Slot,
+        current_slot: Slot,
+        threshold: Option<f64>,
+    ) -> Option<f64> {
+        self.vote_latency
+            .record(current_slot.saturating_sub(vote_slot));
+        threshold.and_then(|threshold| self.vote_latency.check(threshold))
+    }
+
     // Pop all recent votes that are not locked out at the next vote slot.  This
     // allows validators to switch forks once their votes for another fork have
     // expired. This also allows validators continue voting on recent blocks in
     // the same fork without increasing lockouts.
-    fn pop_expired_votes(&mut self, next_vote_slot: Slot) {
+    //
+    // Returns the expired votes, newest first
+    fn pop_expired_votes(&mut self, next_vote_slot: Slot) -> Vec<(Lockout, Signature)> {
+        let mut expired_votes = vec![];
         while let Some(vote) = self.last_lockout() {
             if !vote.is_locked_out_at_slot(next_vote_slot) {
-                self.votes.pop_back();
+                expired_votes.extend(self.votes.pop_back());
             } else {
                 break;
             }
         }
+        expired_votes
     }
 
     fn double_lockouts(&mut self) {
@@ -70,116 +1012,112 @@ impl Tower {
         vote_account_address: &Pubkey,
         vote_slot: Slot,
         signature: &Signature,
-        slot_ancestors: &BTreeMap<Slot, HashSet<Slot>>,
+        vote_ancestry: &Ancestry,
+        lockout_ancestry: &Ancestry,
         root_slot: Slot,
+        current_slot: Slot,
         last_lockout: &Lockout,
-        next_vote_ancestors: &HashSet<Slot>,
+        expired_votes: &[(Lockout, Signature)],
+        late_backfill: Option<Duration>,
     ) -> Incident {
-        let mut incident = String::new();
-        let _ = writeln!(incident, "lockout violation: {}", vote_account_address);
-        let _ = writeln!(incident, "signature: {}", signature);
-        let _ = writeln!(incident, "vote slot: {}", vote_slot);
-        let _ = writeln!(incident, "root slot: {}", root_slot);
-        let _ = writeln!(
-            incident,
-            "last lockout slot: {}",
-            last_lockout.last_locked_out_slot()
-        );
-        let _ = writeln!(incident, "tower:");
-        for lockout in &self.votes {
-            let _ = writeln!(
-                incident,
-                "  - {} (conf: {}), last lockout slot: {} [{}]",
-                lockout.0.slot,
-                lockout.0.confirmation_count,
-                lockout.0.last_locked_out_slot(),
-                lockout.1
-            );
-        }
+        // Taking the ancestries rather than looking the slots up keeps a report from depending on
+        // slots that may have been pruned since the lockout check
+        let common_ancestor = vote_ancestry.common_ancestor(lockout_ancestry);
 
-        let next_vote_ancestors = next_vote_ancestors
-            .iter()
-            .filter(|slot| **slot >= root_slot)
-            .collect::<HashSet<_>>();
-
-        let lockout_slot_ancestors = slot_ancestors
-            .get(&last_lockout.slot)
-            .unwrap_or_else(|| {
-                panic!(
-                    "last lockout slot {} not found in slot ancestors",
-                    last_lockout.slot
-                );
-            })
-            .iter()
-            .filter(|slot| **slot >= root_slot)
-            .collect::<HashSet<_>>();
-
-        let common_ancestors = next_vote_ancestors
-            .intersection(&lockout_slot_ancestors)
-            .collect::<HashSet<_>>();
-
-        let mut next_vote_ancestors = next_vote_ancestors.iter().copied().collect::<Vec<_>>();
-        next_vote_ancestors.sort();
-        next_vote_ancestors.reverse();
-        let _ = writeln!(
-            incident,
-            "fork at vote slot {} to common ancestor:",
-            vote_slot
-        );
-        let _ = writeln!(
-            incident,
-            "  - {}",
-            next_vote_ancestors
-                .iter()
-                .filter(|x| !common_ancestors.contains(x))
-                .map(ToString::to_string)
-                .join(", ")
-        );
-
-        let mut lockout_slot_ancestors = lockout_slot_ancestors.iter().copied().collect::<Vec<_>>();
-        lockout_slot_ancestors.sort();
-        lockout_slot_ancestors.reverse();
-        let _ = writeln!(
-            incident,
-            "fork at lockout slot {} to common ancestor:",
-            last_lockout.slot
-        );
-        let _ = writeln!(
-            incident,
-            "  - {}",
-            lockout_slot_ancestors
-                .iter()
-                .filter(|x| !common_ancestors.contains(x))
-                .map(ToString::to_string)
-                .join(", ")
-        );
+        // Newest first, back to the common ancestor or the root slot
+        let fork_to_common_ancestor = |ancestry: &Ancestry| {
+            ancestry
+                .ancestors()
+                .take_while(|ancestor| Some(*ancestor) != common_ancestor && *ancestor >= root_slot)
+                .collect::<Vec<_>>()
+        };
+        let vote_fork = fork_to_common_ancestor(vote_ancestry);
+        let lockout_fork = fork_to_common_ancestor(lockout_ancestry);
+        let fork_divergence = lockout_fork.len() as u64;
+        // The forks hold ancestors only, so a slot built directly on the common ancestor is its
+        // own fork point
+        let vote_fork_point = vote_fork.last().copied().unwrap_or(vote_slot);
+        let lockout_fork_point = lockout_fork.last().copied().unwrap_or(last_lockout.slot);
 
-        let _ = writeln!(incident, "common fork ancestors:");
-        let mut common_ancestors = common_ancestors.iter().copied().collect::<Vec<_>>();
-        common_ancestors.sort();
-        common_ancestors.reverse();
-        let _ = writeln!(
-            incident,
-            "  - {}",
-            common_ancestors.iter().map(ToString::to_string).join(", ")
-        );
+        // Both forks share the common ancestor and its ancestors, as far back as both reach
+        let since_common_ancestor = |ancestry: &Ancestry| {
+            ancestry
+                .ancestors()
+                .skip_while(|ancestor| Some(*ancestor) != common_ancestor)
+                .take_while(|ancestor| *ancestor >= root_slot)
+                .collect::<Vec<_>>()
+        };
+        let mut common_ancestors = since_common_ancestor(vote_ancestry);
+        common_ancestors.truncate(since_common_ancestor(lockout_ancestry).len());
 
-        let _ = writeln!(incident, "vote transaction history:");
-        for (signature, slots) in &self.vote_history {
-            let _ = writeln!(
-                incident,
-                " - {} [{}]",
-                slots.iter().map(ToString::to_string).join(", "),
-                signature
-            );
+        let mut incident = self.incident(
+            IncidentKind::LockoutViolation(LockoutViolation {
+                last_lockout_slot: last_lockout.slot,
+                last_locked_out_slot: last_lockout.last_locked_out_slot(),
+                vote_fork,
+                lockout_fork,
+                common_ancestors,
+                vote_fork_point,
+                lockout_fork_point,
+                late_backfill,
+            }),
+            vote_account_address,
+            vote_slot,
+            signature,
+            root_slot,
+            current_slot,
+        );
+        // Include the votes that expired at the vote slot, to show why they weren't violated
+        incident.tower.extend(
+            expired_votes
+                .iter()
+                .rev()
+                .map(|(lockout, signature)| TowerVote::new(lockout, *signature)),
+        );
+        for vote in &mut incident.tower {
+            vote.locked_out = Some(vote.last_locked_out_slot >= vote_slot);
         }
-
+        let lockout_depth = incident
+            .tower
+            .iter()
+            .filter(|vote| vote.locked_out == Some(true))
+            .map(|vote| vote.confirmation_count as u64)
+            .max()
+            .unwrap_or_default();
+        incident.severity = lockout_depth + fork_divergence;
         incident
     }
 
+    fn incident(
+        &self,
+        kind: IncidentKind,
+        vote_account_address: &Pubkey,
+        vote_slot: Slot,
+        signature: &Signature,
+        root_slot: Slot,
+        current_slot: Slot,
+    ) -> Incident {
+        Incident {
+            kind,
+            vote_account: *vote_account_address,
+            signature: *signature,
+            vote_slot,
+            root_slot,
+            current_slot,
+            suppressed_incidents: 0,
+            validator_info: None,
+            label: None,
+            instance_name: None,
+            epoch: None,
+            severity: self.max_history as u64 + 1,
+            tower: self.tower_votes(),
+            vote_history: self.vote_history.iter().cloned().collect(),
+        }
+    }
+
     pub fn record_vote_signature(&mut self, signature: Signature, new_votes: Vec<Slot>) {
         self.vote_history.push_back((signature, new_votes));
-        if let Some((_, first_vote_signature)) = self.votes.get(0) {
+        if let Some((_, first_vote_signature)) = self.votes.front() {
             let position = self
                 .vote_history
                 .iter()
@@ -190,62 +1128,1206 @@ impl Tower {
         }
     }
 
+    /// Applies a vote for `vote_slot` to the tower, checking it against the tower's lockouts.
+    /// A vote expiring every vote of a tower at least `tower_reset_depth` votes deep is reported
+    /// as a tower reset, unless `tower_reset_depth` is 0. With `explain`, each step of the check
+    /// is logged at `info` level, so that the decision for a validator can be audited. Returns
+    /// every incident the vote causes
+    #[allow(clippy::too_many_arguments)]
     pub fn process_vote_slot(
         &mut self,
         vote_account_address: &Pubkey,
         vote_slot: Slot,
         signature: &Signature,
-        slot_ancestors: &BTreeMap<Slot, HashSet<Slot>>,
-    ) -> Option<Incident> {
-        let mut maybe_incident = None;
-        self.pop_expired_votes(vote_slot);
+        slot_ancestors: &SlotTree,
+        current_slot: Slot,
+        tower_reset_depth: usize,
+        explain: bool,
+    ) -> Vec<Incident> {
+        // Steps of the check that are logged at debug level anyway are promoted when explaining
+        let level = if explain { Level::Info } else { Level::Debug };
+        if explain {
+            info!(
+                "{}: Explaining vote for {} [{}]: current slot {}, root slot {}, tower {}",
+                vote_account_address,
+                vote_slot,
+                signature,
+                current_slot,
+                self.root_slot
+                    .map(|root_slot| root_slot.to_string())
+                    .unwrap_or_else(|| "none".into()),
+                self.votes
+                    .iter()
+                    .map(|(lockout, _)| format!(
+                        "{} (conf: {}, locked out through {})",
+                        lockout.slot,
+                        lockout.confirmation_count,
+                        lockout.last_locked_out_slot()
+                    ))
+                    .join(", ")
+            );
+        }
+
+        if let Some(root_slot) = self.root_slot.filter(|root_slot| vote_slot <= *root_slot) {
+            if explain {
+                info!(
+                    "{}: Vote for {} is at or below root slot {}: vote below root",
+                    vote_account_address, vote_slot, root_slot
+                );
+            }
+            // The vote can't be applied to the tower, so leave it untouched
+            return vec![self.incident(
+                IncidentKind::VoteBelowRoot,
+                vote_account_address,
+                vote_slot,
+                signature,
+                root_slot,
+                current_slot,
+            )];
+        }
+
+        // Captured before the expired votes are popped, to report the tower as it was
+        let depth = self.depth();
+        let mut incidents = Vec::from_iter(
+            (tower_reset_depth > 0
+                && depth >= tower_reset_depth
+                && self
+                    .votes
+                    .iter()
+                    .filter(|(_, signature)| *signature != Signature::default())
+                    .all(|(lockout, _)| !lockout.is_locked_out_at_slot(vote_slot)))
+            .then(|| {
+                if explain {
+                    info!(
+                        "{}: Vote for {} expires all {} votes of the tower: tower reset",
+                        vote_account_address, vote_slot, depth
+                    );
+                }
+                let mut incident = self.incident(
+                    IncidentKind::TowerRollback {
+                        previous_root_slot: self.root_slot,
+                        previous_depth: depth,
+                        // Only the vote remains
+                        depth: 1,
+                    },
+                    vote_account_address,
+                    vote_slot,
+                    signature,
+                    self.root_slot.unwrap_or_default(),
+                    current_slot,
+                );
+                incident.severity = depth as u64;
+                incident
+            }),
+        );
+        let expired_votes = self.pop_expired_votes(vote_slot);
+        if explain {
+            info!(
+                "{}: Vote for {} popped {} expired votes: [{}]",
+                vote_account_address,
+                vote_slot,
+                expired_votes.len(),
+                expired_votes
+                    .iter()
+                    .map(|(lockout, _)| format!(
+                        "{} (locked out through {})",
+                        lockout.slot,
+                        lockout.last_locked_out_slot()
+                    ))
+                    .join(", ")
+            );
+        }
 
         if let Some(root_slot) = self.root_slot {
-            if !slot_ancestors.contains_key(&root_slot) {
-                debug!(
+            if !slot_ancestors.contains(root_slot) {
+                log!(
+                    level,
                     "{}: Unable to perform lockout check for {} due to unknown root slot {}",
-                    vote_account_address, vote_slot, root_slot
+                    vote_account_address,
+                    vote_slot,
+                    root_slot
                 );
-            } else if let Some(next_vote_ancestors) = slot_ancestors.get(&vote_slot) {
-                if let Some(last_lockout) = self.last_lockout() {
-                    if !slot_ancestors.contains_key(&last_lockout.slot) {
-                        debug!(
+            } else if let Some(vote_ancestry) = slot_ancestors.ancestry(vote_slot) {
+                if explain {
+                    info!(
+                        "{}: Vote slot {} has {} tracked ancestors, {} root slot {}",
+                        vote_account_address,
+                        vote_slot,
+                        vote_ancestry.ancestors().count(),
+                        if vote_ancestry.reaches(root_slot) {
+                            "reaching"
+                        } else {
+                            "not reaching"
+                        },
+                        root_slot
+                    );
+                }
+                match self.last_lockout() {
+                    None => {
+                        if explain {
+                            info!(
+                                "{}: No votes locked out at {}: no lockout to violate",
+                                vote_account_address, vote_slot
+                            );
+                        }
+                    }
+                    Some(last_lockout) => match slot_ancestors.ancestry(last_lockout.slot) {
+                        None => log!(
+                            level,
                             "{}: Unable to perform lockout check for {}: last lockout slot {} unknown",
                             vote_account_address, vote_slot,
                             last_lockout.slot,
-                        );
-                    } else if !next_vote_ancestors.contains(&last_lockout.slot) {
-                        maybe_incident = Some(self.write_incident_report(
-                            vote_account_address,
-                            vote_slot,
-                            signature,
-                            slot_ancestors,
-                            root_slot,
-                            last_lockout,
-                            next_vote_ancestors,
-                        ));
-                    }
+                        ),
+                        Some(_) if vote_ancestry.contains_ancestor(last_lockout.slot) => {
+                            if explain {
+                                info!(
+                                    "{}: Vote slot {} descends from last lockout slot {}: no violation",
+                                    vote_account_address, vote_slot, last_lockout.slot
+                                );
+                            }
+                        }
+                        // Ancestors trimmed by the tracking limits, or not yet observed after a
+                        // restart, can make unrelated slots look like they're on different forks
+                        Some(lockout_ancestry)
+                            if !vote_ancestry.reaches(root_slot)
+                                || !lockout_ancestry.reaches(root_slot) =>
+                        {
+                            log!(
+                                level,
+                                "{}: Unable to perform lockout check for {}: insufficient ancestor data, deferring",
+                                vote_account_address, vote_slot
+                            );
+                        }
+                        Some(lockout_ancestry) => {
+                            // A vote slot first observed after the cluster had moved past the
+                            // lockout, as when the RPC node backfills a fork late, may have been
+                            // voted on once the lockout had expired in real time
+                            let late_backfill = slot_ancestors
+                                .first_observed_after(last_lockout.last_locked_out_slot())
+                                .and_then(|expired_at| {
+                                    vote_ancestry.observed_at().checked_duration_since(expired_at)
+                                })
+                                .filter(|late_backfill| !late_backfill.is_zero());
+                            if explain {
+                                info!(
+                                    "{}: Vote slot {} does not descend from last lockout slot {}, \
+                                    locked out through {}: lockout violation{}",
+                                    vote_account_address,
+                                    vote_slot,
+                                    last_lockout.slot,
+                                    last_lockout.last_locked_out_slot(),
+                                    late_backfill
+                                        .map(|late_backfill| format!(
+                                            ", late backfill by {:.1}s",
+                                            late_backfill.as_secs_f64()
+                                        ))
+                                        .unwrap_or_default()
+                                );
+                            }
+                            incidents.push(self.write_incident_report(
+                                vote_account_address,
+                                vote_slot,
+                                signature,
+                                &vote_ancestry,
+                                &lockout_ancestry,
+                                root_slot,
+                                current_slot,
+                                last_lockout,
+                                &expired_votes,
+                                late_backfill,
+                            ));
+                        }
+                    },
                 }
             } else {
-                debug!(
+                log!(
+                    level,
                     "{}: Unable to perform lockout check for {}: slot unknown",
-                    vote_account_address, vote_slot
+                    vote_account_address,
+                    vote_slot
                 );
             }
         } else {
-            debug!(
+            log!(
+                level,
                 "{}: Unable to perform lockout check for {}: no root slot",
-                vote_account_address, vote_slot
+                vote_account_address,
+                vote_slot
             );
         }
 
-        if self.votes.len() == MAX_LOCKOUT_HISTORY {
-            let vote = self.votes.pop_front().unwrap();
-            self.root_slot = Some(vote.0.slot);
+        if self.votes.len() == self.max_history {
+            let (lockout, root_signature) = self.votes.pop_front().unwrap();
+            match self.root_slot {
+                // The tower's initial placeholder votes all "root" slot 0
+                Some(previous_root_slot)
+                    if lockout.slot <= previous_root_slot
+                        && root_signature != Signature::default() =>
+                {
+                    if explain {
+                        info!(
+                            "{}: Vote for {} roots {}, not above root slot {}: non-monotonic root",
+                            vote_account_address, vote_slot, lockout.slot, previous_root_slot
+                        );
+                    }
+                    incidents.push(self.incident(
+                        IncidentKind::NonMonotonicRoot { previous_root_slot },
+                        vote_account_address,
+                        vote_slot,
+                        signature,
+                        lockout.slot,
+                        current_slot,
+                    ));
+                }
+                _ => {
+                    if explain {
+                        info!(
+                            "{}: Vote for {} roots {}",
+                            vote_account_address, vote_slot, lockout.slot
+                        );
+                    }
+                    self.root_slot = Some(lockout.slot)
+                }
+            }
         }
         self.votes.push_back((Lockout::new(vote_slot), *signature));
         self.double_lockouts();
 
+        incidents
+    }
+
+    /// Reconciles the root slot derived by `process_vote_slot` with `root`, published by newer
+    /// votes once applied. The published root is authoritative, so votes at or below it are rooted
+    /// and a differing derived root is replaced. Until a tower fills after a restart it derives no
+    /// root, or only the placeholder root 0, so this lets lockout checks start from the real root.
+    /// A published root behind the derived root is reported as a rollback
+    pub fn reconcile_root(
+        &mut self,
+        vote_account_address: &Pubkey,
+        vote_slot: Slot,
+        signature: &Signature,
+        root: Slot,
+        current_slot: Slot,
+        explain: bool,
+    ) -> Option<Incident> {
+        let level = if explain { Level::Info } else { Level::Debug };
+        let mut maybe_incident = None;
+        match self.root_slot {
+            Some(root_slot) if root_slot == root => return None,
+            // Not yet derived from the validator's own votes
+            None | Some(0) => log!(
+                level,
+                "{}: Adopting published root slot {}",
+                vote_account_address,
+                root
+            ),
+            // Rooted votes were missed, or the tower has yet to fill
+            Some(root_slot) if root_slot < root => log!(
+                level,
+                "{}: Published root slot {} is ahead of derived root slot {}, adopting it",
+                vote_account_address,
+                root,
+                root_slot
+            ),
+            Some(root_slot) => {
+                warn!(
+                    "{}: Published root slot {} is behind derived root slot {}, adopting it",
+                    vote_account_address, root, root_slot
+                );
+                let depth = self.depth();
+                maybe_incident = Some(self.incident(
+                    IncidentKind::TowerRollback {
+                        previous_root_slot: Some(root_slot),
+                        previous_depth: depth,
+                        depth,
+                    },
+                    vote_account_address,
+                    vote_slot,
+                    signature,
+                    root,
+                    current_slot,
+                ));
+            }
+        }
+        while self
+            .votes
+            .front()
+            .is_some_and(|(lockout, _)| lockout.slot <= root)
+        {
+            self.votes.pop_front();
+        }
+        self.root_slot = Some(root);
         maybe_incident
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn add_slot(slot_ancestors: &mut SlotTree, slot: Slot, parent: Slot) {
+        slot_ancestors.insert(slot, parent);
+    }
+
+    // Slots `0..=last_slot` on a single fork
+    fn linear_ancestors(last_slot: Slot) -> SlotTree {
+        let mut slot_ancestors = SlotTree::new(usize::MAX);
+        for slot in 1..=last_slot {
+            add_slot(&mut slot_ancestors, slot, slot - 1);
+        }
+        slot_ancestors
+    }
+
+    fn vote(
+        tower: &mut Tower,
+        vote_account_address: &Pubkey,
+        slot: Slot,
+        slot_ancestors: &SlotTree,
+    ) -> Option<Incident> {
+        let signature = Signature::new_unique();
+        tower.record_vote_signature(signature, vec![slot]);
+        let mut incidents = tower.process_vote_slot(
+            vote_account_address,
+            slot,
+            &signature,
+            slot_ancestors,
+            slot,
+            0,
+            false,
+        );
+        assert!(incidents.len() <= 1, "{} incidents", incidents.len());
+        incidents.pop()
+    }
+
+    fn reconcile_root(
+        tower: &mut Tower,
+        vote_account_address: &Pubkey,
+        root: Slot,
+    ) -> Option<Incident> {
+        let vote_slot = tower.last_voted_slot().unwrap();
+        tower.reconcile_root(
+            vote_account_address,
+            vote_slot,
+            &Signature::new_unique(),
+            root,
+            vote_slot,
+            false,
+        )
+    }
+
+    // A tower that has rooted slot 0, so that lockout checks are performed
+    fn rooted_tower(vote_account_address: &Pubkey, slot_ancestors: &SlotTree) -> Tower {
+        let mut tower = Tower::default();
+        assert!(vote(&mut tower, vote_account_address, 1, slot_ancestors).is_none());
+        assert_eq!(tower.root_slot, Some(0));
+        tower
+    }
+
+    #[test]
+    fn test_fork_switch_after_lockout_expiry() {
+        let mut slot_ancestors = linear_ancestors(10);
+        add_slot(&mut slot_ancestors, 13, 5);
+
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 10, &slot_ancestors).is_none());
+
+        // Slot 10 is locked out through slot 12, so switching forks at 13 is legal
+        assert!(vote(&mut tower, &vote_account_address, 13, &slot_ancestors).is_none());
+        assert_eq!(tower.last_voted_slot(), Some(13));
+    }
+
+    #[test]
+    fn test_lockout_violation() {
+        let mut slot_ancestors = linear_ancestors(10);
+        add_slot(&mut slot_ancestors, 11, 5);
+        add_slot(&mut slot_ancestors, 12, 11);
+
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 10, &slot_ancestors).is_none());
+
+        let incident = vote(&mut tower, &vote_account_address, 12, &slot_ancestors)
+            .expect("lockout violation");
+        assert_eq!(incident.vote_account, vote_account_address);
+        assert_eq!(incident.vote_slot, 12);
+        assert_eq!(incident.root_slot, 0);
+        let IncidentKind::LockoutViolation(violation) = incident.kind else {
+            panic!("expected a lockout violation");
+        };
+        assert_eq!(violation.last_lockout_slot, 10);
+        assert_eq!(violation.last_locked_out_slot, 12);
+        assert_eq!(violation.vote_fork, vec![11]);
+        assert_eq!(violation.lockout_fork, vec![9, 8, 7, 6]);
+        assert_eq!(violation.common_ancestors, vec![5, 4, 3, 2, 1, 0]);
+        assert_eq!(violation.vote_fork_point, 11);
+        assert_eq!(violation.lockout_fork_point, 6);
+        assert_eq!(violation.late_backfill, None);
+        // The vote for 10 is one deep, on a fork that diverged four slots earlier
+        assert_eq!(incident.severity, 5);
+
+        // Explaining the vote doesn't change the outcome
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 10, &slot_ancestors).is_none());
+        let incident = tower
+            .process_vote_slot(
+                &vote_account_address,
+                12,
+                &Signature::new_unique(),
+                &slot_ancestors,
+                12,
+                0,
+                true,
+            )
+            .pop()
+            .expect("lockout violation");
+        assert_eq!(incident.severity, 5);
+    }
+
+    #[test]
+    fn test_late_backfill() {
+        let mut slot_ancestors = linear_ancestors(10);
+        add_slot(&mut slot_ancestors, 13, 10);
+        add_slot(&mut slot_ancestors, 11, 5);
+        add_slot(&mut slot_ancestors, 12, 11);
+
+        // Slot 13 moved the cluster past the lockout on 10 half a minute before the vote's fork
+        // was observed
+        let now = Instant::now();
+        slot_ancestors.set_observed_at(13, now);
+        slot_ancestors.set_observed_at(12, now + Duration::from_secs(30));
+
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 10, &slot_ancestors).is_none());
+
+        let incident = vote(&mut tower, &vote_account_address, 12, &slot_ancestors)
+            .expect("lockout violation");
+        assert_eq!(incident.late_backfill(), Some(Duration::from_secs(30)));
+        assert!(incident
+            .to_string()
+            .contains("late backfill: vote slot observed 30.0s after the lockout expired\n"));
+        assert_eq!(incident.to_json()["late_backfill_secs"], 30.0);
+
+        // Observed before the cluster moved past the lockout, the vote is a genuine violation
+        slot_ancestors.set_observed_at(12, now);
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 10, &slot_ancestors).is_none());
+        let incident = vote(&mut tower, &vote_account_address, 12, &slot_ancestors)
+            .expect("lockout violation");
+        assert_eq!(incident.late_backfill(), None);
+        assert!(incident.to_json().get("late_backfill_secs").is_none());
+    }
+
+    #[test]
+    fn test_incident_validator_info() {
+        let slot_ancestors = linear_ancestors(10);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        let mut incident =
+            vote(&mut tower, &vote_account_address, 0, &slot_ancestors).expect("vote below root");
+        assert!(!incident.to_string().contains("validator:"));
+        assert!(incident.to_json().get("identity").is_none());
+
+        let identity = Pubkey::new_unique();
+        incident.validator_info = Some(ValidatorInfo {
+            identity,
+            activated_stake: 1_500_000_000_000,
+        });
+        assert!(incident.to_string().contains(&format!(
+            "validator: identity {}, 1500 SOL staked\n",
+            identity
+        )));
+        let json = incident.to_json();
+        assert_eq!(json["identity"], identity.to_string());
+        assert_eq!(json["activated_stake"], 1_500_000_000_000u64);
+    }
+
+    #[test]
+    fn test_incident_label() {
+        let slot_ancestors = linear_ancestors(10);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        let mut incident =
+            vote(&mut tower, &vote_account_address, 0, &slot_ancestors).expect("vote below root");
+        assert_eq!(incident.validator_name(), vote_account_address.to_string());
+        assert!(incident.to_json().get("label").is_none());
+
+        incident.label = Some("validator-01".to_string());
+        let validator_name = format!("validator-01 ({})", vote_account_address);
+        assert_eq!(incident.validator_name(), validator_name);
+        assert!(incident
+            .to_string()
+            .starts_with(&format!("vote below root: {}\n", validator_name)));
+        assert_eq!(incident.to_json()["label"], "validator-01");
+        assert_eq!(incident.summary_json()["label"], "validator-01");
+    }
+
+    #[test]
+    fn test_incident_instance_name() {
+        let slot_ancestors = linear_ancestors(10);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        let mut incident =
+            vote(&mut tower, &vote_account_address, 0, &slot_ancestors).expect("vote below root");
+        let ids = format!("{}-{}", vote_account_address, incident.signature);
+        assert_eq!(incident.filename(), format!("incident-{}.log", ids));
+        assert!(incident.to_json().get("instance").is_none());
+
+        incident.instance_name = Some("mainnet".to_string());
+        assert_eq!(incident.filename(), format!("mainnet-incident-{}.log", ids));
+        assert_eq!(
+            incident.json_filename(),
+            format!("mainnet-incident-{}.json", ids)
+        );
+        assert!(incident.to_string().contains("\ninstance: mainnet\n"));
+        assert_eq!(incident.to_json()["instance"], "mainnet");
+    }
+
+    #[test]
+    fn test_lockout_violation_annotations() {
+        let mut slot_ancestors = linear_ancestors(10);
+        add_slot(&mut slot_ancestors, 11, 5);
+        add_slot(&mut slot_ancestors, 12, 11);
+
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 8, &slot_ancestors).is_none());
+        assert!(vote(&mut tower, &vote_account_address, 9, &slot_ancestors).is_none());
+
+        // The vote for 9 expired at 12, the vote for 8 did not
+        let incident = vote(&mut tower, &vote_account_address, 12, &slot_ancestors)
+            .expect("lockout violation");
+        assert_eq!(
+            incident
+                .tower
+                .iter()
+                .map(|vote| (vote.slot, vote.confirmation_count, vote.locked_out))
+                .collect::<Vec<_>>(),
+            vec![(8, 2, Some(true)), (9, 1, Some(false))]
+        );
+        assert!(incident
+            .to_string()
+            .contains("lockouts at vote slot: 1 locked out, 1 expired"));
+
+        // Towers outside of incidents are not annotated
+        assert!(tower
+            .tower_votes()
+            .iter()
+            .all(|vote| vote.locked_out.is_none()));
+    }
+
+    #[test]
+    fn test_incomplete_ancestors() {
+        let mut slot_ancestors = linear_ancestors(10);
+        add_slot(&mut slot_ancestors, 11, 5);
+        add_slot(&mut slot_ancestors, 12, 11);
+        let vote_account_address = Pubkey::new_unique();
+
+        // The vote slot's ancestors don't reach back to the root
+        let mut partial_ancestors = slot_ancestors.clone();
+        partial_ancestors.truncate_ancestors(12, 2);
+        let mut tower = rooted_tower(&vote_account_address, &partial_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 10, &partial_ancestors).is_none());
+        assert!(vote(&mut tower, &vote_account_address, 12, &partial_ancestors).is_none());
+
+        // The last lockout slot's ancestors don't reach back to the root
+        let mut partial_ancestors = slot_ancestors.clone();
+        partial_ancestors.truncate_ancestors(10, 3);
+        let mut tower = rooted_tower(&vote_account_address, &partial_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 10, &partial_ancestors).is_none());
+        assert!(vote(&mut tower, &vote_account_address, 12, &partial_ancestors).is_none());
+
+        // Once both reach back to the root, the violation is reported
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 10, &slot_ancestors).is_none());
+        assert!(vote(&mut tower, &vote_account_address, 12, &slot_ancestors).is_some());
+    }
+
+    #[test]
+    fn test_vote_below_root() {
+        let slot_ancestors = linear_ancestors(40);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        for slot in 2..=MAX_LOCKOUT_HISTORY as Slot + 5 {
+            assert!(vote(&mut tower, &vote_account_address, slot, &slot_ancestors).is_none());
+        }
+        assert_eq!(tower.root_slot, Some(5));
+
+        let votes = tower.votes.clone();
+        for slot in [3, 5] {
+            let incident = vote(&mut tower, &vote_account_address, slot, &slot_ancestors)
+                .expect("vote below root");
+            assert!(matches!(incident.kind, IncidentKind::VoteBelowRoot));
+            assert_eq!(incident.vote_slot, slot);
+            assert_eq!(incident.root_slot, 5);
+            assert_eq!(incident.severity, ROOT_SEVERITY);
+        }
+        assert_eq!(tower.votes, votes);
+
+        // A vote detected well after its slot was produced
+        let incident = tower
+            .process_vote_slot(
+                &vote_account_address,
+                4,
+                &Signature::new_unique(),
+                &slot_ancestors,
+                40,
+                0,
+                false,
+            )
+            .pop()
+            .expect("vote below root");
+        assert_eq!(incident.current_slot, 40);
+        assert_eq!(incident.slot_age(), 36);
+    }
+
+    #[test]
+    fn test_double_lockouts() {
+        let slot_ancestors = linear_ancestors(10);
+
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = Tower::default();
+        let mut confirmation_counts = vec![];
+        for slot in 1..=3 {
+            assert!(vote(&mut tower, &vote_account_address, slot, &slot_ancestors).is_none());
+            confirmation_counts.push(
+                tower
+                    .votes
+                    .iter()
+                    .rev()
+                    .take(slot as usize)
+                    .map(|(lockout, _)| (lockout.slot, lockout.confirmation_count))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(
+            confirmation_counts,
+            vec![
+                vec![(1, 1)],
+                vec![(2, 1), (1, 2)],
+                vec![(3, 1), (2, 2), (1, 3)],
+            ]
+        );
+
+        // Slot 3 is only locked out through slot 5, and slot 2 through slot 6
+        assert!(vote(&mut tower, &vote_account_address, 7, &slot_ancestors).is_none());
+        assert_eq!(
+            tower
+                .votes
+                .iter()
+                .rev()
+                .take(2)
+                .map(|(lockout, _)| (lockout.slot, lockout.confirmation_count))
+                .collect::<Vec<_>>(),
+            vec![(7, 1), (1, 3)]
+        );
+    }
+
+    #[test]
+    fn test_unknown_root_slot() {
+        let mut slot_ancestors = linear_ancestors(10);
+        add_slot(&mut slot_ancestors, 11, 5);
+        add_slot(&mut slot_ancestors, 12, 11);
+
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 10, &slot_ancestors).is_none());
+        // Forget slot 0, the root
+        slot_ancestors.prune(slot_ancestors.len() - 1);
+        assert!(vote(&mut tower, &vote_account_address, 12, &slot_ancestors).is_none());
+    }
+
+    #[test]
+    fn test_pruned_tower_slots() {
+        let mut slot_ancestors = linear_ancestors(10);
+        add_slot(&mut slot_ancestors, 11, 5);
+        add_slot(&mut slot_ancestors, 12, 11);
+        add_slot(&mut slot_ancestors, 13, 12);
+
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 10, &slot_ancestors).is_none());
+        // Forget every slot the tower references, its root and last lockout included
+        slot_ancestors.prune(3);
+        assert!(!slot_ancestors.contains(0));
+        assert!(!slot_ancestors.contains(10));
+        assert!(vote(&mut tower, &vote_account_address, 12, &slot_ancestors).is_none());
+        assert!(vote(&mut tower, &vote_account_address, 13, &slot_ancestors).is_none());
+        assert_eq!(tower.last_lockout().map(|lockout| lockout.slot), Some(13));
+    }
+
+    #[test]
+    fn test_liveness() {
+        let threshold = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut tower = Tower::default();
+        assert_eq!(tower.check_liveness(start, threshold), None);
+
+        assert_eq!(tower.record_vote_time(start), None);
+        assert_eq!(tower.check_liveness(start + threshold, threshold), None);
+
+        let silence = threshold + Duration::from_secs(1);
+        assert_eq!(
+            tower.check_liveness(start + silence, threshold),
+            Some(silence)
+        );
+        // Only reported once
+        assert_eq!(tower.check_liveness(start + silence * 2, threshold), None);
+
+        let resumed = start + silence * 3;
+        assert_eq!(tower.record_vote_time(resumed), Some(silence * 3));
+        assert_eq!(tower.check_liveness(resumed + threshold, threshold), None);
+    }
+
+    #[test]
+    fn test_record_delinquency() {
+        let mut tower = Tower::default();
+        assert_eq!(tower.record_delinquency(false), None);
+        assert_eq!(tower.record_delinquency(false), None);
+        assert_eq!(tower.record_delinquency(true), Some(Some(false)));
+        assert_eq!(tower.record_delinquency(true), None);
+        assert_eq!(tower.record_delinquency(false), Some(Some(true)));
+
+        // Delinquent when first checked
+        let mut tower = Tower::default();
+        assert_eq!(tower.record_delinquency(true), Some(None));
+    }
+
+    #[test]
+    fn test_is_idle() {
+        let threshold = Duration::from_secs(60);
+        let mut tower = Tower::default();
+        let start = tower.created_at;
+        assert!(!tower.is_idle(start + threshold, threshold));
+        assert!(tower.is_idle(start + threshold * 2, threshold));
+
+        // Incidents keep the tower active, as well as votes
+        tower.check_incident_cooldown(start + threshold * 2, threshold);
+        assert!(!tower.is_idle(start + threshold * 3, threshold));
+        tower.record_vote_time(start + threshold * 4);
+        assert!(!tower.is_idle(start + threshold * 5, threshold));
+        assert!(tower.is_idle(start + threshold * 6, threshold));
+    }
+
+    #[test]
+    fn test_incident_cooldown() {
+        let start = Instant::now();
+        let mut tower = Tower::default();
+        // No cooldown
+        assert_eq!(
+            tower.check_incident_cooldown(start, Duration::ZERO),
+            Some(0)
+        );
+        assert_eq!(
+            tower.check_incident_cooldown(start, Duration::ZERO),
+            Some(0)
+        );
+
+        let cooldown = Duration::from_secs(60);
+        assert_eq!(tower.check_incident_cooldown(start, cooldown), None);
+        assert_eq!(
+            tower.check_incident_cooldown(start + Duration::from_secs(59), cooldown),
+            None
+        );
+        assert_eq!(
+            tower.check_incident_cooldown(start + cooldown, cooldown),
+            Some(2)
+        );
+        assert_eq!(
+            tower.check_incident_cooldown(start + cooldown * 2, cooldown),
+            Some(0)
+        );
+    }
+
+    #[test]
+    fn test_record_incident() {
+        let slot_ancestors = linear_ancestors(10);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        let incident =
+            vote(&mut tower, &vote_account_address, 0, &slot_ancestors).expect("vote below root");
+        for detected_at in 1..=3 {
+            tower.record_incident(&incident, detected_at, 2);
+        }
+        assert_eq!(tower.incident_count(), 3);
+        assert_eq!(
+            tower
+                .incident_history()
+                .iter()
+                .map(|record| record.detected_at)
+                .collect::<Vec<_>>(),
+            vec![2, 3]
+        );
+        assert_eq!(tower.incident_history()[0].kind, "vote_below_root");
+        assert_eq!(tower.incident_history()[0].vote_slot, 0);
+
+        // Lowering the limit trims the history, while the count keeps growing
+        tower.record_incident(&incident, 4, 1);
+        assert_eq!(tower.incident_count(), 4);
+        assert_eq!(tower.incident_history().len(), 1);
+        tower.record_incident(&incident, 5, 0);
+        assert_eq!(tower.incident_count(), 5);
+        assert!(tower.incident_history().is_empty());
+    }
+
+    #[test]
+    fn test_check_timestamp() {
+        let now = 1_000_000;
+        let tolerance = 60;
+        let mut tower = Tower::default();
+        assert_eq!(tower.check_timestamp(now, now, tolerance), None);
+        assert_eq!(tower.check_timestamp(now + 30, now, tolerance), None);
+        assert_eq!(
+            tower.check_timestamp(now + 90, now, tolerance),
+            Some(TimestampAnomaly::Future(90))
+        );
+        assert_eq!(
+            tower.check_timestamp(now + 10, now, tolerance),
+            Some(TimestampAnomaly::NonMonotonic(20))
+        );
+        assert_eq!(
+            tower.check_timestamp(now - 120, now, tolerance),
+            Some(TimestampAnomaly::Past(120))
+        );
+        assert_eq!(tower.check_timestamp(now - 30, now, tolerance), None);
+    }
+
+    #[test]
+    fn test_vote_gap() {
+        let mut slot_ancestors = linear_ancestors(10);
+        // Slot 11 was skipped by its leader
+        add_slot(&mut slot_ancestors, 12, 10);
+        add_slot(&mut slot_ancestors, 13, 5);
+
+        let vote_account_address = Pubkey::new_unique();
+        assert_eq!(Tower::default().vote_gap(&[2], &slot_ancestors), None);
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 2, &slot_ancestors).is_none());
+
+        assert_eq!(
+            tower.vote_gap(&[8], &slot_ancestors),
+            Some(VoteGap {
+                last_voted_slot: 2,
+                vote_slot: 8,
+                skipped_slots: 5,
+            })
+        );
+        // Slots voted on are not skipped
+        assert_eq!(
+            tower
+                .vote_gap(&[5, 8], &slot_ancestors)
+                .map(|vote_gap| vote_gap.skipped_slots),
+            Some(4)
+        );
+        // Nor are slots on other forks, or slots that were never produced
+        assert!(vote(&mut tower, &vote_account_address, 10, &slot_ancestors).is_none());
+        assert_eq!(
+            tower
+                .vote_gap(&[12], &slot_ancestors)
+                .map(|vote_gap| vote_gap.skipped_slots),
+            Some(0)
+        );
+        assert_eq!(tower.vote_gap(&[13], &slot_ancestors), None);
+        // Nor slots of unknown ancestry
+        assert_eq!(tower.vote_gap(&[14], &slot_ancestors), None);
+    }
+
+    #[test]
+    fn test_vote_latency() {
+        let mut tower = Tower::default();
+        assert_eq!(tower.vote_latency().mean(), None);
+
+        for slot in 0..VOTE_LATENCY_WINDOW as Slot - 1 {
+            assert_eq!(tower.record_vote_latency(slot, slot + 4, Some(3.)), None);
+        }
+        assert_eq!(tower.vote_latency().mean(), Some(4.));
+        assert_eq!(tower.vote_latency().max(), Some(4));
+
+        // Only reported once the window is full, and then only once
+        assert_eq!(tower.record_vote_latency(100, 104, Some(3.)), Some(4.));
+        assert_eq!(tower.record_vote_latency(101, 105, Some(3.)), None);
+
+        // A vote for a slot not yet observed has no latency
+        assert_eq!(tower.record_vote_latency(200, 150, None), None);
+        assert_eq!(tower.vote_latency().max(), Some(4));
+    }
+
+    #[test]
+    fn test_root_advancement_rate() {
+        let slot_ancestors = linear_ancestors(100);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        let start = Instant::now();
+        tower.record_root_time(start);
+        assert_eq!(tower.root_advancement_rate(start), None);
+
+        for slot in 2..=MAX_LOCKOUT_HISTORY as Slot + 10 {
+            vote(&mut tower, &vote_account_address, slot, &slot_ancestors);
+            tower.record_root_time(start + Duration::from_secs(slot));
+        }
+        // Rooted slots 1 through 10, recorded a second apart
+        assert_eq!(tower.root_slot(), Some(10));
+        let last_rooted = start + Duration::from_secs(MAX_LOCKOUT_HISTORY as u64 + 10);
+        assert_eq!(tower.root_advancement_rate(last_rooted), Some(1.));
+
+        // A root that stops advancing slows the rate down
+        assert_eq!(
+            tower.root_advancement_rate(last_rooted + Duration::from_secs(9)),
+            Some(0.5)
+        );
+    }
+
+    #[test]
+    fn test_save_load_towers() {
+        let slot_ancestors = linear_ancestors(10);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        assert!(vote(&mut tower, &vote_account_address, 2, &slot_ancestors).is_none());
+        let towers = HashMap::from([(vote_account_address, tower)]);
+
+        let path = std::env::temp_dir().join(format!("towers-{}.bin", vote_account_address));
+        save_towers(&path, &towers, MAX_LOCKOUT_HISTORY, 1_000).unwrap();
+        assert!(load_towers(&path, 2_000, 60, MAX_LOCKOUT_HISTORY).is_err());
+        assert!(load_towers(&path, 1_030, 60, MAX_LOCKOUT_HISTORY - 1).is_err());
+        let loaded_towers = load_towers(&path, 1_030, 60, MAX_LOCKOUT_HISTORY).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let tower = &towers[&vote_account_address];
+        let loaded_tower = &loaded_towers[&vote_account_address];
+        assert_eq!(loaded_tower.votes, tower.votes);
+        assert_eq!(loaded_tower.root_slot, Some(0));
+        assert_eq!(loaded_tower.vote_history, tower.vote_history);
+    }
+
+    #[test]
+    fn test_save_load_switched_tower() {
+        let slot_ancestors = linear_ancestors(100);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = Tower::default();
+        for slot in [1, 2, 3, 100] {
+            assert!(vote(&mut tower, &vote_account_address, slot, &slot_ancestors).is_none());
+        }
+        // Slot 100 expired the older votes, leaving a shallower tower
+        assert!(tower.votes.len() < MAX_LOCKOUT_HISTORY);
+        let towers = HashMap::from([(vote_account_address, tower)]);
+
+        let path = std::env::temp_dir().join(format!("towers-{}.bin", vote_account_address));
+        save_towers(&path, &towers, MAX_LOCKOUT_HISTORY, 1_000).unwrap();
+        let loaded_towers = load_towers(&path, 1_030, 60, MAX_LOCKOUT_HISTORY).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let tower = &towers[&vote_account_address];
+        let loaded_tower = &loaded_towers[&vote_account_address];
+        assert_eq!(loaded_tower.votes, tower.votes);
+        assert_eq!(loaded_tower.root_slot, tower.root_slot);
+        assert_eq!(loaded_tower.vote_history, tower.vote_history);
+    }
+
+    #[test]
+    fn test_non_monotonic_root() {
+        let slot_ancestors = linear_ancestors(40);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        for slot in 2..=MAX_LOCKOUT_HISTORY as Slot + 2 {
+            assert!(vote(&mut tower, &vote_account_address, slot, &slot_ancestors).is_none());
+        }
+        assert_eq!(tower.root_slot, Some(2));
+        assert_eq!(tower.votes.front().unwrap().0.slot, 3);
+
+        // Simulate a root that has advanced past the oldest vote in the tower
+        tower.root_slot = Some(4);
+        let incident = vote(&mut tower, &vote_account_address, 34, &slot_ancestors)
+            .expect("non-monotonic root");
+        assert!(matches!(
+            incident.kind,
+            IncidentKind::NonMonotonicRoot {
+                previous_root_slot: 4
+            }
+        ));
+        assert_eq!(incident.root_slot, 3);
+        assert_eq!(tower.root_slot, Some(4));
+    }
+
+    #[test]
+    fn test_lockout_violation_with_non_monotonic_root() {
+        let mut slot_ancestors = linear_ancestors(MAX_LOCKOUT_HISTORY as Slot + 2);
+        // Slot 34 forks off before the last vote, at 33, which is locked out through 35
+        add_slot(&mut slot_ancestors, 34, 20);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        for slot in 2..=MAX_LOCKOUT_HISTORY as Slot + 2 {
+            assert!(vote(&mut tower, &vote_account_address, slot, &slot_ancestors).is_none());
+        }
+        assert_eq!(tower.votes.front().unwrap().0.slot, 3);
+
+        // A root that has advanced past the oldest vote, and a vote on a fork locked out of
+        let signature = Signature::new_unique();
+        tower.root_slot = Some(4);
+        let incidents = tower.process_vote_slot(
+            &vote_account_address,
+            34,
+            &signature,
+            &slot_ancestors,
+            34,
+            0,
+            false,
+        );
+        assert_eq!(incidents.len(), 2);
+        assert!(matches!(
+            incidents[0].kind,
+            IncidentKind::LockoutViolation(_)
+        ));
+        assert!(matches!(
+            incidents[1].kind,
+            IncidentKind::NonMonotonicRoot {
+                previous_root_slot: 4
+            }
+        ));
+        assert_eq!(incidents[1].root_slot, 3);
+    }
+
+    #[test]
+    fn test_reconcile_root() {
+        // Slots observed since a restart, without the placeholder root slot 0
+        let mut slot_ancestors = SlotTree::new(usize::MAX);
+        for slot in 6..=9 {
+            add_slot(&mut slot_ancestors, slot, slot - 1);
+        }
+        add_slot(&mut slot_ancestors, 12, 7);
+
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = Tower::default();
+        assert!(vote(&mut tower, &vote_account_address, 8, &slot_ancestors).is_none());
+        assert_eq!(tower.root_slot, None);
+        assert!(reconcile_root(&mut tower, &vote_account_address, 6).is_none());
+        assert_eq!(tower.root_slot, Some(6));
+        assert_eq!(tower.votes.len(), 1);
+        assert!(vote(&mut tower, &vote_account_address, 9, &slot_ancestors).is_none());
+
+        // Slot 8 is locked out through slot 12, which could not be checked without a root
+        let incident = vote(&mut tower, &vote_account_address, 12, &slot_ancestors)
+            .expect("lockout violation");
+        assert!(matches!(incident.kind, IncidentKind::LockoutViolation(_)));
+        assert_eq!(incident.root_slot, 6);
+
+        // A published root ahead of the derived root roots the votes it covers
+        assert!(reconcile_root(&mut tower, &vote_account_address, 8).is_none());
+        assert_eq!(tower.root_slot, Some(8));
+        assert_eq!(tower.votes.front().unwrap().0.slot, 12);
+
+        // One behind is adopted too, leaving the votes above it, but is a rollback
+        let incident =
+            reconcile_root(&mut tower, &vote_account_address, 7).expect("tower rollback");
+        assert!(matches!(
+            incident.kind,
+            IncidentKind::TowerRollback {
+                previous_root_slot: Some(8),
+                previous_depth: 1,
+                depth: 1,
+            }
+        ));
+        assert_eq!(incident.root_slot, 7);
+        assert_eq!(incident.severity, ROOT_SEVERITY);
+        assert_eq!(tower.root_slot, Some(7));
+        assert_eq!(tower.votes.len(), 1);
+    }
+
+    #[test]
+    fn test_tower_reset() {
+        let slot_ancestors = linear_ancestors(100);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        for slot in 2..=4 {
+            assert!(vote(&mut tower, &vote_account_address, slot, &slot_ancestors).is_none());
+        }
+        assert_eq!(tower.depth(), 4);
+        let vote_with_reset_depth = |tower: &mut Tower, slot, tower_reset_depth| {
+            tower
+                .process_vote_slot(
+                    &vote_account_address,
+                    slot,
+                    &Signature::new_unique(),
+                    &slot_ancestors,
+                    slot,
+                    tower_reset_depth,
+                    false,
+                )
+                .pop()
+        };
+
+        // Slot 1 is locked out through slot 17, so slot 17 leaves it in the tower
+        assert!(vote_with_reset_depth(&mut tower, 17, 4).is_none());
+        assert_eq!(tower.depth(), 2);
+
+        // Too shallow to report
+        assert!(vote_with_reset_depth(&mut tower, 100, 4).is_none());
+        assert_eq!(tower.depth(), 1);
+
+        let mut tower = rooted_tower(&vote_account_address, &slot_ancestors);
+        for slot in 2..=4 {
+            assert!(vote(&mut tower, &vote_account_address, slot, &slot_ancestors).is_none());
+        }
+        let incident = vote_with_reset_depth(&mut tower, 100, 4).expect("tower reset");
+        assert!(matches!(
+            incident.kind,
+            IncidentKind::TowerRollback {
+                previous_root_slot: Some(0),
+                previous_depth: 4,
+                depth: 1,
+            }
+        ));
+        assert_eq!(incident.severity, 4);
+        // The incident shows the tower that was reset
+        assert_eq!(incident.tower.len(), MAX_LOCKOUT_HISTORY);
+        assert_eq!(tower.depth(), 1);
+        assert_eq!(tower.last_voted_slot(), Some(100));
+    }
+
+    #[test]
+    fn test_max_history() {
+        let slot_ancestors = linear_ancestors(10);
+        let vote_account_address = Pubkey::new_unique();
+        let mut tower = Tower::new(4);
+        for slot in 1..=5 {
+            assert!(vote(&mut tower, &vote_account_address, slot, &slot_ancestors).is_none());
+        }
+        assert_eq!(tower.root_slot, Some(1));
+        assert_eq!(tower.votes.len(), 4);
+
+        let incident =
+            vote(&mut tower, &vote_account_address, 1, &slot_ancestors).expect("vote below root");
+        assert!(matches!(incident.kind, IncidentKind::VoteBelowRoot));
+        assert_eq!(incident.severity, 5);
+    }
+
+    #[test]
+    fn test_record_vote_hash() {
+        let mut tower = Tower::default();
+        let hash = Hash::new_unique();
+        let signature = Signature::new_unique();
+        assert_eq!(tower.record_vote_hash(1, hash, signature), None);
+        assert_eq!(tower.record_vote_hash(1, hash, signature), None);
+        // A refreshed vote
+        assert_eq!(
+            tower.record_vote_hash(1, hash, Signature::new_unique()),
+            None
+        );
+        assert_eq!(
+            tower.record_vote_hash(1, Hash::new_unique(), Signature::new_unique()),
+            Some(signature)
+        );
+
+        // Only recent votes are remembered
+        for slot in 2..=RECENT_VOTE_HASHES as Slot + 1 {
+            assert_eq!(
+                tower.record_vote_hash(slot, Hash::new_unique(), Signature::new_unique()),
+                None
+            );
+        }
+        assert_eq!(
+            tower.record_vote_hash(1, Hash::new_unique(), Signature::new_unique()),
+            None
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use {
+    crate::{delinquency::VoteAccountsSink, notifier::redact_client_error},
+    log::*,
+    solana_client::nonblocking::rpc_client::RpcClient,
+    solana_sdk::{clock::Slot, native_token::lamports_to_sol, pubkey::Pubkey},
+    std::{
+        collections::{HashMap, HashSet},
+        fmt,
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, RwLock,
+        },
+        time::Duration,
+    },
+    tokio::{sync::mpsc, time::Instant},
+};
+
+/// Minimum time between vote account queries, however many new validators are seen
+const MIN_QUERY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The node behind a vote account
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidatorInfo {
+    pub identity: Pubkey,
+    /// Lamports of stake delegated to the vote account
+    pub activated_stake: u64,
+}
+
+impl fmt::Display for ValidatorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "identity {}, {:.0} SOL staked",
+            self.identity,
+            lamports_to_sol(self.activated_stake)
+        )
+    }
+}
+
+enum Request {
+    Lookup(Pubkey),
+    /// Refresh the stake of every vote account, at an epoch boundary
+    Refresh,
+}
+
+/// Caches the `ValidatorInfo` of vote accounts. Vote accounts are looked up with the RPC node's
+/// `getVoteAccounts` in the background, so that a slow or failing RPC node never holds up vote
+/// processing. Clones share the cache, but each requests its own lookups. The responses may also
+/// be published for the delinquency check, sparing it queries of its own
+#[derive(Clone)]
+pub struct ValidatorDirectory {
+    validators: Arc<RwLock<HashMap<Pubkey, ValidatorInfo>>>,
+    // Vote accounts that have been looked up, successfully or not
+    requested: HashSet<Pubkey>,
+    // First slot of the next epoch, when stake changes, or `u64::MAX` until known
+    next_epoch_slot: Arc<AtomicU64>,
+    requests: mpsc::UnboundedSender<Request>,
+}
+
+impl ValidatorDirectory {
+    /// Starts looking up vote accounts with the RPC node at `json_rpc_url`. If `publish` is given,
+    /// the vote accounts are also queried at least every interval, whatever is looked up, and
+    /// each response is published to the sink. Must be called from within a tokio runtime
+    pub fn start(json_rpc_url: String, publish: Option<(Duration, VoteAccountsSink)>) -> Self {
+        let validators = Arc::<RwLock<HashMap<_, _>>>::default();
+        let next_epoch_slot = Arc::new(AtomicU64::new(u64::MAX));
+        let (requests, pending_requests) = mpsc::unbounded_channel();
+        tokio::spawn(Self::run(
+            RpcClient::new(json_rpc_url),
+            validators.clone(),
+            next_epoch_slot.clone(),
+            pending_requests,
+            publish,
+        ));
+        Self {
+            validators,
+            requested: HashSet::new(),
+            next_epoch_slot,
+            requests,
+        }
+    }
+
+    async fn run(
+        rpc_client: RpcClient,
+        validators: Arc<RwLock<HashMap<Pubkey, ValidatorInfo>>>,
+        next_epoch_slot: Arc<AtomicU64>,
+        mut pending_requests: mpsc::UnboundedReceiver<Request>,
+        publish: Option<(Duration, VoteAccountsSink)>,
+    ) {
+        let min_query_interval = publish
+            .as_ref()
+            .map_or(MIN_QUERY_INTERVAL, |(interval, _)| {
+                MIN_QUERY_INTERVAL.min(*interval)
+            });
+        let mut unknown = HashSet::new();
+        let mut refresh = false;
+        let mut next_query = Instant::now();
+        // When the vote accounts are due to be published again
+        let mut next_publish = Instant::now();
+        loop {
+            if unknown.is_empty() && !refresh {
+                let request = match &publish {
+                    // Queried again once due to be published, even with nothing to look up
+                    Some(_) => tokio::time::timeout_at(next_publish, pending_requests.recv())
+                        .await
+                        .unwrap_or(Some(Request::Refresh)),
+                    None => pending_requests.recv().await,
+                };
+                match request {
+                    Some(Request::Lookup(vote_account)) => {
+                        unknown.insert(vote_account);
+                    }
+                    Some(Request::Refresh) => refresh = true,
+                    None => return,
+                }
+            }
+            tokio::time::sleep_until(next_query).await;
+            while let Ok(request) = pending_requests.try_recv() {
+                match request {
+                    Request::Lookup(vote_account) => {
+                        unknown.insert(vote_account);
+                    }
+                    Request::Refresh => refresh = true,
+                }
+            }
+
+            next_query = Instant::now() + min_query_interval;
+            if let Some((interval, _)) = &publish {
+                next_publish = Instant::now() + *interval;
+            }
+            // Every vote account is returned, so this also refreshes the stake of those known
+            let vote_accounts = match rpc_client.get_vote_accounts().await {
+                Ok(vote_accounts) => vote_accounts,
+                Err(err) => {
+                    // Retried after the query interval
+                    warn!(
+                        "Unable to look up vote accounts: {}",
+                        redact_client_error(err)
+                    );
+                    continue;
+                }
+            };
+            refresh = false;
+            match rpc_client.get_epoch_info().await {
+                Ok(epoch_info) => next_epoch_slot.store(
+                    epoch_info.absolute_slot - epoch_info.slot_index + epoch_info.slots_in_epoch,
+                    Ordering::Relaxed,
+                ),
+                Err(err) => warn!("Unable to get the epoch info: {}", redact_client_error(err)),
+            }
+
+            let mut known = validators.write().unwrap();
+            for vote_account_info in vote_accounts
+                .current
+                .iter()
+                .chain(vote_accounts.delinquent.iter())
+            {
+                if let (Ok(vote_account), Ok(identity)) = (
+                    vote_account_info.vote_pubkey.parse(),
+                    vote_account_info.node_pubkey.parse(),
+                ) {
+                    known.insert(
+                        vote_account,
+                        ValidatorInfo {
+                            identity,
+                            activated_stake: vote_account_info.activated_stake,
+                        },
+                    );
+                }
+            }
+            for vote_account in unknown.drain() {
+                if !known.contains_key(&vote_account) {
+                    debug!("{} not found in the vote accounts", vote_account);
+                }
+            }
+            drop(known);
+            if let Some((_, vote_accounts_sink)) = &publish {
+                vote_accounts_sink.publish(vote_accounts);
+            }
+        }
+    }
+
+    /// Looks up `vote_account` in the background, unless it already has been
+    pub fn lookup(&mut self, vote_account: &Pubkey) {
+        if self.requested.insert(*vote_account) {
+            let _ = self.requests.send(Request::Lookup(*vote_account));
+        }
+    }
+
+    /// Refreshes the stake of every vote account in the background once `slot` reaches the next
+    /// epoch
+    pub fn observe_slot(&self, slot: Slot) {
+        let next_epoch_slot = self.next_epoch_slot.load(Ordering::Relaxed);
+        if slot >= next_epoch_slot
+            && self
+                .next_epoch_slot
+                .compare_exchange(
+                    next_epoch_slot,
+                    u64::MAX,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            debug!("Refreshing stake at epoch boundary slot {}", slot);
+            let _ = self.requests.send(Request::Refresh);
+        }
+    }
+
+    /// The cached info for `vote_account`, if its lookup has completed
+    pub fn get(&self, vote_account: &Pubkey) -> Option<ValidatorInfo> {
+        self.validators.read().unwrap().get(vote_account).cloned()
+    }
+}
@@ -0,0 +1,167 @@
+//! Votes as published by an RPC node's `voteSubscribe`, in whichever shape its vote program
+//! version produces
+use {
+    serde::{Deserialize, Serialize},
+    solana_sdk::clock::{Slot, UnixTimestamp},
+};
+
+/// A vote observed on the pubsub feed. Legacy `Vote` instructions publish only the slots voted
+/// on, while `VoteStateUpdate` and `TowerSync` instructions publish the validator's whole tower,
+/// possibly with its root and the confirmation count of each slot
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", from = "VoteNotification")]
+pub struct Vote {
+    /// Vote account address, as base-58 encoded string
+    pub vote_pubkey: String,
+    /// The slots voted on, in increasing order if the vote is valid
+    pub slots: Vec<Slot>,
+    pub hash: String,
+    pub timestamp: Option<UnixTimestamp>,
+    pub signature: String,
+    /// The validator's root slot, if published with the vote
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<Slot>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Lockout {
+    slot: Slot,
+    // Implied by the slot's position in the tower, so not needed once decoded
+    #[allow(dead_code)]
+    confirmation_count: u32,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VoteSlots {
+    Slots { slots: Vec<Slot> },
+    Lockouts { lockouts: Vec<Lockout> },
+}
+
+/// The shapes of vote notification `Vote` is decoded from
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VoteNotification {
+    vote_pubkey: String,
+    #[serde(flatten)]
+    slots: VoteSlots,
+    hash: String,
+    timestamp: Option<UnixTimestamp>,
+    signature: String,
+    #[serde(default)]
+    root: Option<Slot>,
+}
+
+impl From<VoteNotification> for Vote {
+    fn from(notification: VoteNotification) -> Self {
+        let VoteNotification {
+            vote_pubkey,
+            slots,
+            hash,
+            timestamp,
+            signature,
+            root,
+        } = notification;
+        Self {
+            vote_pubkey,
+            slots: match slots {
+                VoteSlots::Slots { slots } => slots,
+                VoteSlots::Lockouts { lockouts } => {
+                    lockouts.into_iter().map(|lockout| lockout.slot).collect()
+                }
+            },
+            hash,
+            timestamp,
+            signature,
+            root,
+        }
+    }
+}
+
+impl Vote {
+    /// The root, if published and not below the first slot voted on. The vote program rejects
+    /// such votes, so they never land
+    pub fn misplaced_root(&self) -> Option<Slot> {
+        self.root
+            .filter(|root| self.slots.first().is_some_and(|slot| root >= slot))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, serde_json::json};
+
+    #[test]
+    fn test_decode_vote() {
+        let legacy = json!({
+            "votePubkey": "Vote111111111111111111111111111111111111111",
+            "slots": [10, 11],
+            "hash": "11111111111111111111111111111111",
+            "timestamp": 1_700_000_000,
+            "signature": "1111111111111111111111111111111111111111111111111111111111111111",
+        });
+        let vote = serde_json::from_value::<Vote>(legacy.clone()).unwrap();
+        assert_eq!(vote.slots, vec![10, 11]);
+        assert_eq!(vote.timestamp, Some(1_700_000_000));
+        assert_eq!(vote.root, None);
+        // Recorded votes replay unchanged
+        assert_eq!(serde_json::to_value(&vote).unwrap(), legacy);
+
+        let vote_state_update = json!({
+            "votePubkey": "Vote111111111111111111111111111111111111111",
+            "slots": [8, 10, 11],
+            "root": 5,
+            "hash": "11111111111111111111111111111111",
+            "timestamp": null,
+            "signature": "1111111111111111111111111111111111111111111111111111111111111111",
+        });
+        let vote = serde_json::from_value::<Vote>(vote_state_update.clone()).unwrap();
+        assert_eq!(vote.slots, vec![8, 10, 11]);
+        assert_eq!(vote.root, Some(5));
+        assert_eq!(serde_json::to_value(&vote).unwrap(), vote_state_update);
+
+        let tower_sync = json!({
+            "votePubkey": "Vote111111111111111111111111111111111111111",
+            "lockouts": [
+                { "slot": 8, "confirmationCount": 3 },
+                { "slot": 10, "confirmationCount": 2 },
+                { "slot": 11, "confirmationCount": 1 },
+            ],
+            "root": 5,
+            "hash": "11111111111111111111111111111111",
+            "timestamp": 1_700_000_000,
+            "signature": "1111111111111111111111111111111111111111111111111111111111111111",
+        });
+        let vote = serde_json::from_value::<Vote>(tower_sync).unwrap();
+        assert_eq!(vote.slots, vec![8, 10, 11]);
+        assert_eq!(vote.root, Some(5));
+        assert_eq!(vote.timestamp, Some(1_700_000_000));
+        assert_eq!(vote.misplaced_root(), None);
+
+        // Neither shape
+        assert!(serde_json::from_value::<Vote>(json!({
+            "votePubkey": "Vote111111111111111111111111111111111111111",
+            "hash": "11111111111111111111111111111111",
+            "signature": "1111111111111111111111111111111111111111111111111111111111111111",
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn test_misplaced_root() {
+        let mut vote = Vote {
+            vote_pubkey: String::new(),
+            slots: vec![8, 10],
+            hash: String::new(),
+            timestamp: None,
+            signature: String::new(),
+            root: Some(7),
+        };
+        assert_eq!(vote.misplaced_root(), None);
+        vote.root = Some(8);
+        assert_eq!(vote.misplaced_root(), Some(8));
+        vote.root = None;
+        assert_eq!(vote.misplaced_root(), None);
+    }
+}